@@ -0,0 +1,397 @@
+use egui::os::OperatingSystem;
+use egui::{
+    Context, Event, Grid, Id, InputState, Key, ModifierNames, Modifiers, PointerButton, Response,
+    Ui,
+};
+use enum_iterator::{all, Sequence};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Sequence)]
+pub enum KeyAction {
+    ZoomToFit,
+    ResetZoom,
+    ToggleInspection,
+    NextGeneration,
+    PreviousGeneration,
+    NextBag,
+    ToggleOrigin,
+    FocusFilter,
+    Pan,
+    CopySelection,
+    Undo,
+    Redo,
+}
+
+impl Display for KeyAction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyAction::ZoomToFit => write!(f, "Zoom to Fit"),
+            KeyAction::ResetZoom => write!(f, "Reset Zoom"),
+            KeyAction::ToggleInspection => write!(f, "Toggle Inspection"),
+            KeyAction::NextGeneration => write!(f, "Next Generation"),
+            KeyAction::PreviousGeneration => write!(f, "Previous Generation"),
+            KeyAction::NextBag => write!(f, "Next Bag"),
+            KeyAction::ToggleOrigin => write!(f, "Toggle Origin"),
+            KeyAction::FocusFilter => write!(f, "Focus Filter"),
+            KeyAction::Pan => write!(f, "Pan"),
+            KeyAction::CopySelection => write!(f, "Copy Selection"),
+            KeyAction::Undo => write!(f, "Undo"),
+            KeyAction::Redo => write!(f, "Redo"),
+        }
+    }
+}
+
+impl KeyAction {
+    pub fn default_binding(&self) -> KeyBinding {
+        match self {
+            KeyAction::ZoomToFit => KeyBinding::key(Modifiers::NONE, Key::F),
+            KeyAction::ResetZoom => KeyBinding::key(Modifiers::NONE, Key::Num0),
+            KeyAction::ToggleInspection => KeyBinding::hold(Modifiers::COMMAND),
+            KeyAction::NextGeneration => KeyBinding::key(Modifiers::ALT, Key::ArrowRight),
+            KeyAction::PreviousGeneration => KeyBinding::key(Modifiers::ALT, Key::ArrowLeft),
+            KeyAction::NextBag => KeyBinding::key(Modifiers::ALT, Key::ArrowDown),
+            KeyAction::ToggleOrigin => KeyBinding::key(Modifiers::NONE, Key::O),
+            KeyAction::FocusFilter => KeyBinding::key(Modifiers::COMMAND, Key::F),
+            KeyAction::Pan => KeyBinding::drag(Modifiers::NONE, PointerButton::Middle),
+            KeyAction::CopySelection => KeyBinding::key(Modifiers::SHIFT, Key::C),
+            KeyAction::Undo => KeyBinding::key(Modifiers::COMMAND, Key::Z),
+            KeyAction::Redo => KeyBinding::key(Modifiers::COMMAND.plus(Modifiers::SHIFT), Key::Z),
+        }
+    }
+
+    /// 割り当てられる入力の種類
+    /// インスペクションは押している間だけ有効にするか、キーで切り替えるかを選べる
+    pub fn accepts(&self, trigger: &KeyTrigger) -> bool {
+        match (self, trigger) {
+            (KeyAction::Pan, KeyTrigger::Drag(_)) => true,
+            (KeyAction::Pan, _) => false,
+            (KeyAction::ToggleInspection, KeyTrigger::Hold) => true,
+            (_, KeyTrigger::Key(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyTrigger {
+    /// キーを押した時
+    Key(Key),
+    /// ボタンでドラッグしている間
+    Drag(PointerButton),
+    /// 修飾キーを押している間
+    Hold,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyBinding {
+    pub modifiers: Modifiers,
+    pub trigger: KeyTrigger,
+}
+
+impl KeyBinding {
+    pub const fn key(modifiers: Modifiers, key: Key) -> Self {
+        Self {
+            modifiers,
+            trigger: KeyTrigger::Key(key),
+        }
+    }
+
+    pub const fn drag(modifiers: Modifiers, button: PointerButton) -> Self {
+        Self {
+            modifiers,
+            trigger: KeyTrigger::Drag(button),
+        }
+    }
+
+    pub const fn hold(modifiers: Modifiers) -> Self {
+        Self {
+            modifiers,
+            trigger: KeyTrigger::Hold,
+        }
+    }
+
+    pub fn format(&self, is_mac: bool) -> String {
+        let modifiers = ModifierNames::NAMES.format(&self.modifiers, is_mac);
+        let trigger = match self.trigger {
+            KeyTrigger::Key(key) => key.name().to_string(),
+            KeyTrigger::Drag(button) => format!("{:?} Drag", button),
+            KeyTrigger::Hold => return format!("Hold {}", modifiers),
+        };
+        if modifiers.is_empty() {
+            trigger
+        } else {
+            format!("{}+{}", modifiers, trigger)
+        }
+    }
+}
+
+/// 押された修飾キーを OS によらない形にする
+/// Mac の Cmd と他の OS の Ctrl はどちらも `command` として扱う
+fn normalize_modifiers(modifiers: Modifiers) -> Modifiers {
+    if modifiers.command {
+        Modifiers {
+            ctrl: false,
+            mac_cmd: false,
+            ..modifiers
+        }
+    } else {
+        modifiers
+    }
+}
+
+/// 既定の割り当てに対するユーザーの上書き
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Keymap {
+    overrides: HashMap<KeyAction, KeyBinding>,
+}
+
+impl Keymap {
+    pub fn binding(&self, action: KeyAction) -> KeyBinding {
+        self.overrides
+            .get(&action)
+            .copied()
+            .unwrap_or_else(|| action.default_binding())
+    }
+
+    pub fn set(&mut self, action: KeyAction, binding: KeyBinding) {
+        if binding == action.default_binding() {
+            self.overrides.remove(&action);
+        } else {
+            self.overrides.insert(action, binding);
+        }
+    }
+
+    pub fn reset(&mut self, action: KeyAction) {
+        self.overrides.remove(&action);
+    }
+
+    pub fn is_overridden(&self, action: KeyAction) -> bool {
+        self.overrides.contains_key(&action)
+    }
+
+    /// キーに割り当てた操作がこのフレームで押されたか
+    pub fn pressed(&self, input: &InputState, action: KeyAction) -> bool {
+        let binding = self.binding(action);
+        match binding.trigger {
+            KeyTrigger::Key(key) => {
+                input.key_pressed(key) && input.modifiers.matches_exact(binding.modifiers)
+            }
+            _ => false,
+        }
+    }
+
+    /// 修飾キーに割り当てた操作が押されている間か
+    pub fn held(&self, input: &InputState, action: KeyAction) -> bool {
+        let binding = self.binding(action);
+        match binding.trigger {
+            KeyTrigger::Hold => {
+                binding.modifiers.any() && input.modifiers.matches_exact(binding.modifiers)
+            }
+            _ => false,
+        }
+    }
+
+    /// ドラッグに割り当てた操作で `response` がドラッグされているか
+    pub fn dragged(&self, response: &Response, action: KeyAction) -> bool {
+        let binding = self.binding(action);
+        match binding.trigger {
+            KeyTrigger::Drag(button) => {
+                response.dragged_by(button)
+                    && response
+                        .ctx
+                        .input(|input| input.modifiers.matches_exact(binding.modifiers))
+            }
+            _ => false,
+        }
+    }
+
+    /// 同じ入力が割り当てられている操作の組
+    pub fn conflicts(&self) -> Vec<(KeyAction, KeyAction)> {
+        let actions = all::<KeyAction>().collect::<Vec<_>>();
+        let mut conflicts = vec![];
+        for (i, a) in actions.iter().enumerate() {
+            for b in &actions[i + 1..] {
+                if self.binding(*a) == self.binding(*b) {
+                    conflicts.push((*a, *b));
+                }
+            }
+        }
+        conflicts
+    }
+}
+
+/// 割り当てを変更中の操作と、それまでに押された修飾キー
+#[derive(Debug, Clone, Copy)]
+struct Capture {
+    action: KeyAction,
+    modifiers: Modifiers,
+}
+
+impl Capture {
+    /// このフレームの入力から新しい割り当てを決める
+    /// Escape で取り消した場合は `Some(None)` を返す
+    /// 割り当てに使った入力は他のショートカットで処理されないように取り除く
+    fn poll(&mut self, input: &mut InputState) -> Option<Option<KeyBinding>> {
+        let captured = input
+            .events
+            .iter()
+            .enumerate()
+            .find_map(|(i, event)| Some((i, self.binding_for(event)?)));
+        if let Some((i, binding)) = captured {
+            input.events.remove(i);
+            return Some(binding);
+        }
+        // 修飾キーだけを押して離したら押している間の割り当てにする
+        if input.modifiers.any() {
+            self.modifiers = self.modifiers.plus(normalize_modifiers(input.modifiers));
+        } else if self.modifiers.any() {
+            let binding = KeyBinding::hold(self.modifiers);
+            self.modifiers = Modifiers::NONE;
+            if self.action.accepts(&binding.trigger) {
+                return Some(Some(binding));
+            }
+        }
+        None
+    }
+
+    fn binding_for(&self, event: &Event) -> Option<Option<KeyBinding>> {
+        let binding = match event {
+            Event::Key {
+                key: Key::Escape,
+                pressed: true,
+                modifiers,
+                ..
+            } if modifiers.is_none() => return Some(None),
+            Event::Key {
+                key,
+                pressed: true,
+                modifiers,
+                ..
+            } => KeyBinding::key(normalize_modifiers(*modifiers), *key),
+            // 左ボタンだけのドラッグは選択や編集に使うので修飾キーとの組み合わせに限る
+            Event::PointerButton {
+                button,
+                pressed: true,
+                modifiers,
+                ..
+            } if *button != PointerButton::Primary || modifiers.any() => {
+                KeyBinding::drag(normalize_modifiers(*modifiers), *button)
+            }
+            _ => return None,
+        };
+        self.action
+            .accepts(&binding.trigger)
+            .then_some(Some(binding))
+    }
+}
+
+const CAPTURE_ID: &str = "keymap capture";
+
+/// 割り当ての変更中か
+/// 変更中に押したキーでショートカットが動かないよう、入力を処理する側で確認する
+pub fn is_capturing(ctx: &Context) -> bool {
+    ctx.data(|data| data.get_temp::<Option<Capture>>(Id::new(CAPTURE_ID)))
+        .flatten()
+        .is_some()
+}
+
+pub fn keymap_editor(ui: &mut Ui, keymap: &mut Keymap) {
+    let id = Id::new(CAPTURE_ID);
+    let mut capture = ui
+        .data(|data| data.get_temp::<Option<Capture>>(id))
+        .flatten();
+    let is_mac = ui.ctx().os() == OperatingSystem::Mac;
+
+    if let Some(c) = capture.as_mut() {
+        if let Some(binding) = ui.input_mut(|input| c.poll(input)) {
+            if let Some(binding) = binding {
+                keymap.set(c.action, binding);
+            }
+            capture = None;
+        }
+    }
+
+    Grid::new("keymap")
+        .num_columns(3)
+        .striped(true)
+        .show(ui, |ui| {
+            for action in all::<KeyAction>() {
+                ui.label(action.to_string());
+                let capturing = capture.is_some_and(|c| c.action == action);
+                let text = if capturing {
+                    "Press keys...".to_string()
+                } else {
+                    keymap.binding(action).format(is_mac)
+                };
+                if ui.selectable_label(capturing, text).clicked() {
+                    capture = (!capturing).then_some(Capture {
+                        action,
+                        modifiers: Modifiers::NONE,
+                    });
+                }
+                if ui
+                    .add_enabled(keymap.is_overridden(action), egui::Button::new("Reset"))
+                    .clicked()
+                {
+                    keymap.reset(action);
+                }
+                ui.end_row();
+            }
+        });
+
+    for (a, b) in keymap.conflicts() {
+        ui.colored_label(
+            ui.visuals().error_fg_color,
+            format!("{} and {} share {}", a, b, keymap.binding(a).format(is_mac)),
+        );
+    }
+
+    ui.data_mut(|data| data.insert_temp(id, capture));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn override_and_reset_binding() {
+        let mut keymap = Keymap::default();
+        assert!(keymap.conflicts().is_empty());
+
+        let binding = KeyBinding::key(Modifiers::NONE, Key::F);
+        keymap.set(KeyAction::ResetZoom, binding);
+        assert_eq!(keymap.binding(KeyAction::ResetZoom), binding);
+        assert_eq!(
+            keymap.conflicts(),
+            vec![(KeyAction::ZoomToFit, KeyAction::ResetZoom)]
+        );
+
+        keymap.set(KeyAction::ResetZoom, KeyAction::ResetZoom.default_binding());
+        assert!(!keymap.is_overridden(KeyAction::ResetZoom));
+        assert!(keymap.conflicts().is_empty());
+    }
+
+    #[test]
+    fn capture_consumes_key_event() {
+        let mut capture = Capture {
+            action: KeyAction::ZoomToFit,
+            modifiers: Modifiers::NONE,
+        };
+        let mut input = InputState::default();
+        input.events.push(Event::Key {
+            key: Key::G,
+            physical_key: None,
+            pressed: true,
+            repeat: false,
+            modifiers: Modifiers::NONE,
+        });
+        assert_eq!(
+            capture.poll(&mut input),
+            Some(Some(KeyBinding::key(Modifiers::NONE, Key::G)))
+        );
+        assert!(input.events.is_empty());
+        assert!(!input.key_pressed(Key::G));
+    }
+}
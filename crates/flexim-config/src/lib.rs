@@ -3,7 +3,10 @@
 // const ZOOM_SPEED: f32 = 1.0;
 // const SCROLL_SPEED: f32 = 1.0;
 
-use egui::{Context, Id, Ui, Window};
+pub mod keymap;
+
+use crate::keymap::{keymap_editor, Keymap};
+use egui::{CollapsingHeader, Context, Id, Ui, Window};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -14,15 +17,32 @@ pub struct Config {
     pub scroll_speed: f32,
     pub grid_snap_distance: f32,
     pub measure_grid_width: f32,
+    /// ピクセルグリッドの間隔 (絶対座標)
+    #[serde(default = "default_pixel_grid_spacing")]
+    pub pixel_grid_spacing: f32,
+    /// ピクセルグリッドを表示し始める拡大率
+    #[serde(default = "default_pixel_grid_min_scale")]
+    pub pixel_grid_min_scale: f32,
+    #[serde(default)]
+    pub keymap: Keymap,
+}
+
+fn default_pixel_grid_spacing() -> f32 {
+    1.0
+}
+
+fn default_pixel_grid_min_scale() -> f32 {
+    8.0
 }
 
 impl Config {
     pub fn get_global(ui: &mut Ui) -> Self {
-        let config = ui
-            .ctx()
-            .data_mut(|writer| writer.get_persisted::<Config>(Id::new(CONFIG_WINDOW_ID)))
-            .unwrap_or_default();
-        config
+        Self::get_global_ctx(ui.ctx())
+    }
+
+    pub fn get_global_ctx(ctx: &Context) -> Self {
+        ctx.data_mut(|writer| writer.get_persisted::<Config>(Id::new(CONFIG_WINDOW_ID)))
+            .unwrap_or_default()
     }
 }
 
@@ -35,6 +55,9 @@ impl Default for Config {
             scroll_speed: 1.0,
             grid_snap_distance: 5.0,
             measure_grid_width: 3.0,
+            pixel_grid_spacing: default_pixel_grid_spacing(),
+            pixel_grid_min_scale: default_pixel_grid_min_scale(),
+            keymap: Keymap::default(),
         }
     }
 }
@@ -104,6 +127,28 @@ impl ConfigWindow {
                             .speed(0.01),
                     );
                 });
+
+                ui.horizontal(|ui| {
+                    ui.label("Pixel Grid Spacing");
+                    ui.add(
+                        egui::DragValue::new(&mut config.pixel_grid_spacing)
+                            .range(0.1..=1000.0)
+                            .speed(0.1),
+                    );
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Pixel Grid Min Scale");
+                    ui.add(
+                        egui::DragValue::new(&mut config.pixel_grid_min_scale)
+                            .range(config.zoom_lower_limit..=config.zoom_upper_limit)
+                            .speed(0.1),
+                    );
+                });
+
+                CollapsingHeader::new("Keymap").show(ui, |ui| {
+                    keymap_editor(ui, &mut config.keymap);
+                });
             });
 
         ctx.data_mut(|writer| {
@@ -31,6 +31,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             name: "test_data".to_string(),
             data_type: DataType::Image.into(),
             special_columns: Default::default(),
+            transform: None,
         })),
     }];
     let image_bytes = include_bytes!("../../../assets/flexim-logo-1.png");
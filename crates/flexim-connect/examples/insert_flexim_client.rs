@@ -29,6 +29,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             name: "test_data".to_string(),
             data_type: DataType::Image.into(),
             special_columns: HashMap::new(),
+            transform: None,
         })),
     }];
     let image_bytes = include_bytes!("../../../assets/flexim-logo-1.png");
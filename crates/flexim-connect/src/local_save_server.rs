@@ -1,11 +1,10 @@
 use crate::grpc::flexim_connect_server::FleximConnect;
 use crate::grpc::list_bags_response::BagMeta;
 use crate::grpc::*;
-use crate::utility::protobuf_data_type_to_fl_data;
+use crate::utility::{protobuf_data_type_to_fl_data, protobuf_transform_to_fl_transform};
 use flexim_storage::{BagId, Storage, StorageQuery};
 use std::fs::File;
 use std::io::BufWriter;
-use std::ops::Deref;
 use std::path::PathBuf;
 
 use std::sync::Arc;
@@ -67,10 +66,11 @@ impl FleximConnect for LocalSaveServerImpl {
             let data = protobuf_data_type_to_fl_data(meta.clone(), buffer)
                 .map_err(|e| Status::internal(e.to_string()))?;
             let data_id = data.id();
+            let transform = protobuf_transform_to_fl_transform(&meta);
 
             let bag_id = BagId::new(meta.bag_id);
             self.storage
-                .insert_data(bag_id, meta.name, data)
+                .insert_data_with_transform(bag_id, meta.name, data, transform)
                 .map_err(|e| Status::internal(e.to_string()))?;
 
             let bag = self.storage.get_bag(bag_id).unwrap();
@@ -83,7 +83,7 @@ impl FleximConnect for LocalSaveServerImpl {
                 )
                 .unwrap(),
             );
-            bincode::serialize_into(&mut writer, bag.deref()).unwrap();
+            bag.write_to(&mut writer).unwrap();
 
             Ok(Response::new(AppendDataResponse {
                 bag_id: meta.bag_id,
@@ -1,7 +1,7 @@
 use crate::grpc::flexim_connect_server::FleximConnect;
 use crate::grpc::list_bags_response::BagMeta;
 use crate::grpc::*;
-use crate::utility::protobuf_data_type_to_fl_data;
+use crate::utility::{protobuf_data_type_to_fl_data, protobuf_transform_to_fl_transform};
 use flexim_storage::{Storage, StorageQuery};
 
 use std::sync::Arc;
@@ -59,9 +59,15 @@ impl FleximConnect for FleximConnectServerImpl {
             let data = protobuf_data_type_to_fl_data(meta.clone(), buffer)
                 .map_err(|e| Status::internal(e.to_string()))?;
             let data_id = data.id();
+            let transform = protobuf_transform_to_fl_transform(&meta);
 
             self.storage
-                .insert_data(flexim_storage::BagId::new(meta.bag_id), meta.name, data)
+                .insert_data_with_transform(
+                    flexim_storage::BagId::new(meta.bag_id),
+                    meta.name,
+                    data,
+                    transform,
+                )
                 .map_err(|e| Status::internal(e.to_string()))?;
 
             Ok(Response::new(AppendDataResponse {
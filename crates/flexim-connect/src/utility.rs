@@ -3,7 +3,7 @@ use crate::grpc::append_data_request::DataMeta;
 use crate::grpc::DataType;
 use anyhow::Context;
 use flexim_data_type::{
-    FlData, FlDataFrame, FlDataFrameSpecialColumn, FlImage, FlObject, FlTensor2D,
+    FlAffineTransform, FlData, FlDataFrame, FlDataFrameSpecialColumn, FlImage, FlObject, FlTensor2D,
 };
 use polars::prelude::{IpcReader, SerReader};
 use serde_json::Value;
@@ -26,6 +26,19 @@ pub(crate) fn protobuf_data_type_to_fl_data(
     })
 }
 
+/// 未指定の拡大率 (0) は 1 として扱う
+pub(crate) fn protobuf_transform_to_fl_transform(meta: &DataMeta) -> Option<FlAffineTransform> {
+    let transform = meta.transform.as_ref()?;
+    let scale = |s: f64| if s == 0.0 { 1.0 } else { s };
+    Some(FlAffineTransform {
+        offset_x: transform.offset_x,
+        offset_y: transform.offset_y,
+        scale_x: scale(transform.scale_x),
+        scale_y: scale(transform.scale_y),
+        rotation: transform.rotation,
+    })
+}
+
 fn dataframe_from_bytes(
     special_columns: HashMap<String, i32>,
     buffer: Vec<u8>,
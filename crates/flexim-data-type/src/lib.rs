@@ -1,4 +1,6 @@
-use anyhow::{anyhow, Context};
+pub mod npy;
+
+use anyhow::{anyhow, bail, ensure, Context};
 
 use ndarray::{Array2, Array3};
 
@@ -129,6 +131,21 @@ impl FlImage {
             height: height as usize,
         })
     }
+
+    /// PNG 以外 (JPEG など) も含めて形式を推定して読み込む
+    pub fn try_from_encoded(value: Vec<u8>) -> anyhow::Result<Self> {
+        let (width, height) = image::io::Reader::new(std::io::Cursor::new(value.as_bytes()))
+            .with_guessed_format()
+            .context("unknown image format")?
+            .into_dimensions()
+            .context("image decoder error")?;
+        Ok(Self {
+            id: gen_id(),
+            value,
+            width: width as usize,
+            height: height as usize,
+        })
+    }
 }
 
 impl FlDataTrait for FlImage {
@@ -155,6 +172,12 @@ impl<A> FlTensor2D<A> {
     }
 }
 
+impl FlTensor2D<f64> {
+    pub fn try_from_npy(bytes: &[u8]) -> anyhow::Result<Self> {
+        Ok(Self::new(npy::read_npy(bytes)?, (0, 0)))
+    }
+}
+
 impl FlDataTrait for FlTensor2D<f64> {
     fn id(&self) -> Id {
         self.id
@@ -194,6 +217,23 @@ impl FlDataFrame {
             special_columns,
         }
     }
+
+    /// 特殊カラムに指定されたカラムを struct に変換してから作成する
+    pub fn try_with_special_columns(
+        mut value: DataFrame,
+        special_columns: HashMap<String, FlDataFrameSpecialColumn>,
+    ) -> anyhow::Result<Self> {
+        for (name, special_column) in &special_columns {
+            let column = value
+                .column(name)
+                .with_context(|| format!("column not found: {}", name))?;
+            let converted = special_column
+                .convert_series(column.as_materialized_series())
+                .with_context(|| format!("failed to convert column {}", name))?;
+            value.with_column(converted)?;
+        }
+        Ok(Self::new(value, special_columns))
+    }
 }
 
 impl FlDataTrait for FlDataFrame {
@@ -202,7 +242,7 @@ impl FlDataTrait for FlDataFrame {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum FlDataFrameSpecialColumn {
     Rectangle,
     Segment,
@@ -217,9 +257,95 @@ impl FlDataFrameSpecialColumn {
             Self::Color => false,
         }
     }
+
+    pub fn field_names(&self) -> &'static [&'static str] {
+        match self {
+            Self::Rectangle | Self::Segment => &["x1", "y1", "x2", "y2"],
+            Self::Color => &["r", "g", "b"],
+        }
+    }
+
+    pub fn validate_fields(&self, fields: &[Field]) -> bool {
+        match self {
+            Self::Rectangle => FlDataFrameRectangle::validate_fields(fields),
+            Self::Segment => FlDataFrameSegment::validate_fields(fields),
+            Self::Color => FlDataFrameColor::validate_fields(fields),
+        }
+    }
+
+    /// 値から特殊カラムの種類を推定する。x1,y1,x2,y2 を持つものは Rectangle とみなす
+    pub fn guess(series: &Series) -> Option<Self> {
+        let keys: Vec<String> = match series.dtype() {
+            DataType::Struct(fields) => fields.iter().map(|f| f.name().to_string()).collect(),
+            DataType::String => {
+                let first = series.str().ok()?.into_iter().flatten().next()?;
+                serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(first)
+                    .ok()?
+                    .keys()
+                    .cloned()
+                    .collect()
+            }
+            _ => return None,
+        };
+        [Self::Rectangle, Self::Color].into_iter().find(|s| {
+            s.field_names().len() == keys.len()
+                && s.field_names().iter().all(|f| keys.iter().any(|k| k == f))
+        })
+    }
+
+    /// JSON 文字列のカラム (`{"x1": 0.0, ...}`) を struct のカラムに変換する
+    /// 既に struct のカラムはフィールドを検証してそのまま返す
+    pub fn convert_series(&self, series: &Series) -> anyhow::Result<Series> {
+        match series.dtype() {
+            DataType::Struct(fields) => {
+                ensure!(
+                    self.validate_fields(fields),
+                    "struct fields do not match {:?}",
+                    self
+                );
+                Ok(series.clone())
+            }
+            DataType::String => self.parse_json_series(series),
+            dtype => bail!("can not convert {:?} into {:?}", dtype, self),
+        }
+    }
+
+    fn parse_json_series(&self, series: &Series) -> anyhow::Result<Series> {
+        let names = self.field_names();
+        let mut values = vec![Vec::with_capacity(series.len()); names.len()];
+        for s in series.str()?.into_iter() {
+            let row = s
+                .map(serde_json::from_str::<HashMap<String, f64>>)
+                .transpose()
+                .with_context(|| format!("invalid value {:?}", s))?;
+            for (name, v) in names.iter().zip(values.iter_mut()) {
+                v.push(match &row {
+                    Some(row) => Some(
+                        *row.get(*name)
+                            .with_context(|| format!("missing {}", name))?,
+                    ),
+                    None => None,
+                });
+            }
+        }
+        let dtype = match self {
+            Self::Color => DataType::Float32,
+            Self::Rectangle | Self::Segment => DataType::Float64,
+        };
+        let fields = names
+            .iter()
+            .zip(values)
+            .map(|(name, v)| Series::new((*name).into(), v).cast(&dtype))
+            .collect::<PolarsResult<Vec<_>>>()?;
+
+        Ok(
+            StructChunked::from_series(series.name().clone(), series.len(), fields.iter())?
+                .into_series(),
+        )
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct FlDataFrameRectangle {
     pub x1: f64,
     pub y1: f64,
@@ -516,6 +642,90 @@ impl FlDataFrameColor {
     }
 }
 
+/// データ座標から表示座標へのアフィン変換
+/// 拡大縮小、回転 (度数法)、平行移動の順に適用する
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FlAffineTransform {
+    pub offset_x: f64,
+    pub offset_y: f64,
+    pub scale_x: f64,
+    pub scale_y: f64,
+    pub rotation: f64,
+}
+
+impl Default for FlAffineTransform {
+    fn default() -> Self {
+        Self {
+            offset_x: 0.0,
+            offset_y: 0.0,
+            scale_x: 1.0,
+            scale_y: 1.0,
+            rotation: 0.0,
+        }
+    }
+}
+
+impl FlAffineTransform {
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// 矩形が矩形のまま変換されるか
+    pub fn is_axis_aligned(&self) -> bool {
+        self.rotation.rem_euclid(90.0) == 0.0
+    }
+
+    pub fn apply(&self, x: f64, y: f64) -> (f64, f64) {
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let (x, y) = (x * self.scale_x, y * self.scale_y);
+        (
+            x * cos - y * sin + self.offset_x,
+            x * sin + y * cos + self.offset_y,
+        )
+    }
+
+    pub fn apply_inverse(&self, x: f64, y: f64) -> (f64, f64) {
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let (x, y) = (x - self.offset_x, y - self.offset_y);
+        (
+            (x * cos + y * sin) / self.scale_x,
+            (-x * sin + y * cos) / self.scale_y,
+        )
+    }
+
+    /// 変換後の矩形を囲む軸平行な矩形
+    pub fn apply_rectangle(&self, rectangle: &FlDataFrameRectangle) -> FlDataFrameRectangle {
+        bounding_rectangle(rectangle, |x, y| self.apply(x, y))
+    }
+
+    pub fn apply_inverse_rectangle(
+        &self,
+        rectangle: &FlDataFrameRectangle,
+    ) -> FlDataFrameRectangle {
+        bounding_rectangle(rectangle, |x, y| self.apply_inverse(x, y))
+    }
+}
+
+fn bounding_rectangle(
+    rectangle: &FlDataFrameRectangle,
+    f: impl Fn(f64, f64) -> (f64, f64),
+) -> FlDataFrameRectangle {
+    let corners = [
+        f(rectangle.x1, rectangle.y1),
+        f(rectangle.x2, rectangle.y1),
+        f(rectangle.x2, rectangle.y2),
+        f(rectangle.x1, rectangle.y2),
+    ];
+    let xs = corners.map(|c| c.0);
+    let ys = corners.map(|c| c.1);
+    FlDataFrameRectangle {
+        x1: xs.into_iter().fold(f64::INFINITY, f64::min),
+        y1: ys.into_iter().fold(f64::INFINITY, f64::min),
+        x2: xs.into_iter().fold(f64::NEG_INFINITY, f64::max),
+        y2: ys.into_iter().fold(f64::NEG_INFINITY, f64::max),
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Hash, PartialEq, Eq)]
 pub enum GenerationSelector {
     Latest,
@@ -571,7 +781,74 @@ fn gen_id() -> Id {
 
 #[cfg(test)]
 mod tests {
+    use super::*;
 
     #[test]
     fn it_works() {}
+
+    #[test]
+    fn special_columns_from_json_strings() {
+        let df = polars::df!(
+            "face" => [Some(r#"{"x1": 0, "y1": 1, "x2": 2, "y2": 3}"#), None],
+            "color" => [r#"{"r": 255, "g": 0, "b": 0}"#, r#"{"r": 0, "g": 0, "b": 255}"#],
+        )
+        .unwrap();
+        assert_eq!(
+            FlDataFrameSpecialColumn::guess(df.column("face").unwrap().as_materialized_series()),
+            Some(FlDataFrameSpecialColumn::Rectangle)
+        );
+        assert_eq!(
+            FlDataFrameSpecialColumn::guess(df.column("color").unwrap().as_materialized_series()),
+            Some(FlDataFrameSpecialColumn::Color)
+        );
+
+        let df = FlDataFrame::try_with_special_columns(
+            df,
+            [
+                ("face".to_string(), FlDataFrameSpecialColumn::Segment),
+                ("color".to_string(), FlDataFrameSpecialColumn::Color),
+            ]
+            .into_iter()
+            .collect(),
+        )
+        .unwrap();
+        let face = df.value.column("face").unwrap();
+        let segment = FlDataFrameSegment::try_from(face.get(0).unwrap()).unwrap();
+        assert_eq!((segment.x2, segment.y2), (2.0, 3.0));
+        assert!(matches!(
+            FlDataFrameSegment::try_from(face.get(1).unwrap()),
+            Err(FlShapeConvertError::NullValue)
+        ));
+        let color = FlDataFrameColor::try_from(df.value.column("color").unwrap().get(1).unwrap());
+        assert_eq!(color.unwrap().b, 255.0);
+    }
+
+    #[test]
+    fn affine_transform_round_trip() {
+        let transform = FlAffineTransform {
+            offset_x: 10.0,
+            offset_y: 20.0,
+            scale_x: 2.0,
+            scale_y: 4.0,
+            rotation: 90.0,
+        };
+        let (x, y) = transform.apply(1.0, 1.0);
+        assert!((x - 6.0).abs() < 1e-9 && (y - 22.0).abs() < 1e-9);
+        let (x, y) = transform.apply_inverse(x, y);
+        assert!((x - 1.0).abs() < 1e-9 && (y - 1.0).abs() < 1e-9);
+        assert!(transform.is_axis_aligned());
+
+        let rectangle = FlAffineTransform {
+            rotation: 45.0,
+            ..Default::default()
+        }
+        .apply_rectangle(&FlDataFrameRectangle {
+            x1: 0.0,
+            y1: 0.0,
+            x2: 1.0,
+            y2: 1.0,
+        });
+        assert!((rectangle.x1 + 0.5f64.sqrt()).abs() < 1e-9);
+        assert!((rectangle.y2 - 2.0f64.sqrt()).abs() < 1e-9);
+    }
 }
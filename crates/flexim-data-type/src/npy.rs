@@ -0,0 +1,193 @@
+//! numpy の `.npy` 形式 (2次元配列のみ) の読み込み
+use anyhow::{bail, ensure, Context};
+use ndarray::{Array2, ShapeBuilder};
+
+const MAGIC: &[u8] = b"\x93NUMPY";
+
+#[derive(Debug, PartialEq)]
+struct Header {
+    descr: String,
+    fortran_order: bool,
+    shape: Vec<usize>,
+}
+
+/// `.npy` のバイト列を f64 の2次元配列として読み込む
+pub fn read_npy(bytes: &[u8]) -> anyhow::Result<Array2<f64>> {
+    ensure!(bytes.starts_with(MAGIC), "not a npy file");
+    let major = *bytes.get(MAGIC.len()).context("truncated npy header")?;
+    let (header_len, offset) = match major {
+        1 => (
+            u16::from_le_bytes(read_array(bytes, 8)?) as usize,
+            MAGIC.len() + 4,
+        ),
+        2 | 3 => (
+            u32::from_le_bytes(read_array(bytes, 8)?) as usize,
+            MAGIC.len() + 6,
+        ),
+        v => bail!("unsupported npy version {}", v),
+    };
+    let header = bytes
+        .get(offset..offset + header_len)
+        .context("truncated npy header")?;
+    let header = parse_header(std::str::from_utf8(header).context("invalid npy header")?)?;
+    let data = &bytes[offset + header_len..];
+
+    let (height, width) = match header.shape.as_slice() {
+        [h, w] => (*h, *w),
+        shape => bail!("expected 2 dimensional array, found shape {:?}", shape),
+    };
+    let len = height
+        .checked_mul(width)
+        .with_context(|| format!("npy shape is too large: {:?}", header.shape))?;
+    let values = decode_values(&header.descr, data, len)?;
+
+    let shape = if header.fortran_order {
+        (height, width).f()
+    } else {
+        (height, width).into_shape()
+    };
+    Ok(Array2::from_shape_vec(shape, values)?)
+}
+
+fn read_array<const N: usize>(bytes: &[u8], start: usize) -> anyhow::Result<[u8; N]> {
+    bytes
+        .get(start..start + N)
+        .context("truncated npy header")?
+        .try_into()
+        .context("truncated npy header")
+}
+
+macro_rules! decode {
+    ($data:expr, $len:expr, $t:ty, $from_bytes:ident) => {{
+        const SIZE: usize = std::mem::size_of::<$t>();
+        let size = $len.checked_mul(SIZE).context("npy shape is too large")?;
+        ensure!($data.len() >= size, "npy data is shorter than its shape");
+        $data
+            .chunks_exact(SIZE)
+            .take($len)
+            .map(|c| <$t>::$from_bytes(c.try_into().unwrap()) as f64)
+            .collect()
+    }};
+}
+
+fn decode_values(descr: &str, data: &[u8], len: usize) -> anyhow::Result<Vec<f64>> {
+    let (Some(endian), Some(kind)) = (descr.get(..1), descr.get(1..)) else {
+        bail!("unsupported npy dtype {}", descr);
+    };
+    let values: Vec<f64> = match (endian, kind) {
+        ("<", "f8") => decode!(data, len, f64, from_le_bytes),
+        (">", "f8") => decode!(data, len, f64, from_be_bytes),
+        ("<", "f4") => decode!(data, len, f32, from_le_bytes),
+        (">", "f4") => decode!(data, len, f32, from_be_bytes),
+        ("<", "i8") => decode!(data, len, i64, from_le_bytes),
+        (">", "i8") => decode!(data, len, i64, from_be_bytes),
+        ("<", "i4") => decode!(data, len, i32, from_le_bytes),
+        (">", "i4") => decode!(data, len, i32, from_be_bytes),
+        ("<", "i2") => decode!(data, len, i16, from_le_bytes),
+        (">", "i2") => decode!(data, len, i16, from_be_bytes),
+        ("<", "u8") => decode!(data, len, u64, from_le_bytes),
+        (">", "u8") => decode!(data, len, u64, from_be_bytes),
+        ("<", "u4") => decode!(data, len, u32, from_le_bytes),
+        (">", "u4") => decode!(data, len, u32, from_be_bytes),
+        ("<", "u2") => decode!(data, len, u16, from_le_bytes),
+        (">", "u2") => decode!(data, len, u16, from_be_bytes),
+        ("|", "u1") => decode!(data, len, u8, from_le_bytes),
+        ("|", "i1") => decode!(data, len, i8, from_le_bytes),
+        ("|", "b1") => decode!(data, len, u8, from_le_bytes),
+        _ => bail!("unsupported npy dtype {}", descr),
+    };
+    Ok(values)
+}
+
+/// `{'descr': '<f8', 'fortran_order': False, 'shape': (3, 4), }` の形式のヘッダを読む
+fn parse_header(header: &str) -> anyhow::Result<Header> {
+    let value_of = |key: &str| -> anyhow::Result<&str> {
+        let key = format!("'{}':", key);
+        let start = header
+            .find(&key)
+            .with_context(|| format!("missing {}", key))?
+            + key.len();
+        Ok(header[start..].trim_start())
+    };
+
+    let descr = value_of("descr")?
+        .strip_prefix('\'')
+        .and_then(|s| s.split('\'').next())
+        .context("invalid descr")?
+        .to_string();
+    let fortran_order = value_of("fortran_order")?.starts_with("True");
+    let shape = value_of("shape")?
+        .strip_prefix('(')
+        .and_then(|s| s.split(')').next())
+        .context("invalid shape")?
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse::<usize>().context("invalid shape"))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    ensure!(descr.len() >= 2, "invalid descr {}", descr);
+
+    Ok(Header {
+        descr,
+        fortran_order,
+        shape,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn npy(descr: &str, fortran_order: bool, shape: &str, data: &[u8]) -> Vec<u8> {
+        let header = format!(
+            "{{'descr': '{}', 'fortran_order': {}, 'shape': {}, }}\n",
+            descr,
+            if fortran_order { "True" } else { "False" },
+            shape
+        );
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend([1, 0]);
+        bytes.extend((header.len() as u16).to_le_bytes());
+        bytes.extend(header.as_bytes());
+        bytes.extend(data);
+        bytes
+    }
+
+    #[test]
+    fn read_c_order_f8() {
+        let data = [1.0_f64, 2.0, 3.0, 4.0, 5.0, 6.0]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect::<Vec<_>>();
+        let array = read_npy(&npy("<f8", false, "(2, 3)", &data)).unwrap();
+        assert_eq!(array.dim(), (2, 3));
+        assert_eq!(array[[1, 0]], 4.0);
+    }
+
+    #[test]
+    fn read_fortran_order_u1() {
+        let array = read_npy(&npy("|u1", true, "(2, 2)", &[1, 2, 3, 4])).unwrap();
+        assert_eq!(array[[0, 1]], 3.0);
+        assert_eq!(array[[1, 0]], 2.0);
+    }
+
+    #[test]
+    fn reject_non_2d() {
+        let data = [0_u8; 3];
+        assert!(read_npy(&npy("|u1", false, "(3,)", &data)).is_err());
+        assert!(read_npy(b"not npy").is_err());
+    }
+
+    #[test]
+    fn reject_oversized_shape() {
+        let shape = format!("({}, {})", usize::MAX, 2);
+        assert!(read_npy(&npy("|u1", false, &shape, &[0; 4])).is_err());
+        let shape = format!("({}, 1)", usize::MAX / 2);
+        assert!(read_npy(&npy("<f8", false, &shape, &[0; 8])).is_err());
+    }
+
+    #[test]
+    fn reject_non_ascii_descr() {
+        assert!(read_npy(&npy("éf8", false, "(1, 1)", &[0; 8])).is_err());
+    }
+}
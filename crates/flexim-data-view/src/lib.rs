@@ -2,6 +2,7 @@ pub mod object;
 
 use egui::ahash::HashMap;
 use flexim_data_type::{FlData, FlDataReference};
+use flexim_table_widget::export::ExportOptions;
 use flexim_table_widget::{FlTable, FlTableDrawContext};
 use itertools::Itertools;
 use std::sync::{Arc, Mutex};
@@ -29,6 +30,11 @@ pub enum ShowColumns {
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct FlDataFrameViewContext {
     pub show_columns: ShowColumns,
+    #[serde(default)]
+    pub export_options: ExportOptions,
+    /// 直前の書き出しに失敗した理由
+    #[serde(skip)]
+    pub export_error: Option<String>,
 }
 
 impl From<FlDataFrameViewContext> for FlTableDrawContext {
@@ -56,6 +62,8 @@ impl FlDataFrameView {
             table: FlTable::new(data_reference),
             view_context: Arc::new(Mutex::new(FlDataFrameViewContext {
                 show_columns: ShowColumns::All,
+                export_options: ExportOptions::default(),
+                export_error: None,
             })),
         }
     }
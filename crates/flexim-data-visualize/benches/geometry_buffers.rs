@@ -0,0 +1,239 @@
+//! 特殊列を毎フレーム `AnyValue` から変換する場合と、世代ごとに作った `GeometryBuffers` を使う場合の比較
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use egui::Context;
+use flexim_data_type::{
+    FlData, FlDataFrame, FlDataFrameRectangle, FlDataFrameSpecialColumn, FlDataReference,
+    FlDataType, GenerationSelector,
+};
+use flexim_data_view::FlDataFrameView;
+use flexim_data_visualize::geometry::{GeometryBuffers, StyleBuffers, StyleColumns};
+use flexim_data_visualize::visualize::FlDataFrameViewRender;
+use flexim_storage::{Bag, Storage, StorageQuery};
+use flexim_table_widget::FlTableDrawContext;
+use itertools::Itertools;
+use polars::prelude::*;
+use std::io::Cursor;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+const SYNTHETIC_ROWS: usize = 100_000;
+
+fn with_special_column(dataframe: DataFrame, special_column: &str) -> FlDataFrame {
+    FlDataFrame::try_with_special_columns(
+        dataframe,
+        [(
+            special_column.to_string(),
+            FlDataFrameSpecialColumn::Rectangle,
+        )]
+        .into_iter()
+        .collect(),
+    )
+    .unwrap()
+}
+
+fn with_row_index(dataframe: &FlDataFrame) -> DataFrame {
+    dataframe
+        .value
+        .with_row_index("__FleximRowId".into(), None)
+        .unwrap()
+        .as_single_chunk()
+        .clone()
+}
+
+fn long_sample() -> FlDataFrame {
+    let data = Cursor::new(include_bytes!("../../../assets/long_sample.csv").to_vec());
+    let dataframe = CsvReadOptions::default()
+        .with_has_header(true)
+        .into_reader_with_file_handle(data)
+        .finish()
+        .unwrap();
+    with_special_column(dataframe, "Face")
+}
+
+fn synthetic(rows: usize) -> FlDataFrame {
+    let faces = (0..rows)
+        .map(|i| {
+            let (x, y) = ((i % 1000) as f64 * 20.0, (i / 1000) as f64 * 20.0);
+            format!(
+                r#"{{"x1": {}, "y1": {}, "x2": {}, "y2": {}}}"#,
+                x,
+                y,
+                x + 10.0,
+                y + 10.0
+            )
+        })
+        .collect_vec();
+    let dataframe = DataFrame::new(vec![
+        Column::new("Face".into(), faces),
+        Column::new(
+            "Name".into(),
+            (0..rows).map(|i| format!("#{}", i)).collect_vec(),
+        ),
+        Column::new(
+            "Age".into(),
+            (0..rows).map(|i| (i % 80) as i64).collect_vec(),
+        ),
+    ])
+    .unwrap();
+    with_special_column(dataframe, "Face")
+}
+
+fn style_columns() -> StyleColumns {
+    StyleColumns {
+        stroke_color: Some("Age".to_string()),
+        fill_color: None,
+        label: Some("Name".to_string()),
+    }
+}
+
+/// 以前の描画処理と同じく、全行を `AnyValue` から変換する
+fn extract_rows(dataframe: &DataFrame) -> usize {
+    let shapes = dataframe
+        .column("Face")
+        .unwrap()
+        .as_materialized_series()
+        .iter()
+        .map(|value| FlDataFrameRectangle::try_from(value).ok())
+        .collect_vec();
+    let row_ids = dataframe
+        .column("__FleximRowId")
+        .unwrap()
+        .as_materialized_series()
+        .iter()
+        .map(|v| v.extract::<u32>().unwrap() as u64)
+        .collect_vec();
+    let style = StyleBuffers::from_dataframe(dataframe, &style_columns()).unwrap();
+    shapes.len() + row_ids.len() + style.labels.map_or(0, |labels| labels.len())
+}
+
+/// 作っておいた配列から全行の図形・行番号・色・ラベルを読む
+fn read_buffers(geometry: &GeometryBuffers, style: &StyleBuffers) -> usize {
+    (0..geometry.len())
+        .filter(|i| {
+            let shape = geometry.shape(*i);
+            let row_id = geometry.row_id(*i);
+            let color = style.stroke_colors.as_ref().map(|colors| colors[*i]);
+            let label = style.labels.as_ref().map(|labels| labels[*i].as_str());
+            black_box((shape.is_some(), row_id, color, label)).0
+        })
+        .count()
+}
+
+/// 実際の描画と同じく、テーブルの描画 (フィルタ結果の世代の確認) とキャッシュの参照を毎フレーム行う
+struct RenderPath {
+    ctx: Context,
+    bag: Arc<RwLock<Bag>>,
+    render: FlDataFrameViewRender,
+}
+
+impl RenderPath {
+    fn new(dataframe: FlDataFrame) -> Self {
+        let storage = Storage::default();
+        let bag_id = storage.create_bag("bench".to_string());
+        storage
+            .insert_data(
+                bag_id,
+                "faces".to_string(),
+                FlData::DataFrame(Arc::new(dataframe)),
+            )
+            .unwrap();
+        let render = FlDataFrameViewRender::new(
+            FlDataFrameView::new(FlDataReference::new(
+                "faces".to_string(),
+                GenerationSelector::Latest,
+                FlDataType::DataFrame,
+            )),
+            "Face".to_string(),
+        )
+        .with_color_scatter_column("Age");
+        let path = Self {
+            ctx: Context::default(),
+            bag: storage.get_bag(bag_id).unwrap(),
+            render,
+        };
+
+        // 別スレッドのフィルタ計算が終わるまで待つ
+        path.frame();
+        let bag = path.bag.read().unwrap();
+        while path
+            .render
+            .dataframe_view
+            .table
+            .computed_dataframe_with_generation(&path.ctx, &bag)
+            .is_none()
+        {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        drop(bag);
+        path
+    }
+
+    fn frame(&self) -> usize {
+        let bag = self.bag.read().unwrap();
+        let mut count = 0;
+        let _ = self.ctx.run(Default::default(), |ctx| {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                self.render
+                    .dataframe_view
+                    .table
+                    .draw(ui, &bag, &FlTableDrawContext::default());
+            });
+            let (geometry, style) = self.render.buffers(ctx, &bag).unwrap();
+            count = read_buffers(&geometry, &style);
+        });
+        count
+    }
+}
+
+fn bench_geometry_buffers(c: &mut Criterion) {
+    let mut group = c.benchmark_group("dataframe rows per frame");
+    for (name, fl_dataframe) in [
+        ("long_sample.csv", long_sample()),
+        ("synthetic", synthetic(SYNTHETIC_ROWS)),
+    ] {
+        let dataframe = with_row_index(&fl_dataframe);
+        group.bench_with_input(
+            BenchmarkId::new("extract every frame", name),
+            &dataframe,
+            |b, dataframe| b.iter(|| extract_rows(black_box(dataframe))),
+        );
+
+        let geometry = GeometryBuffers::from_dataframe(
+            &dataframe,
+            "Face",
+            &FlDataFrameSpecialColumn::Rectangle,
+        )
+        .unwrap();
+        let style = StyleBuffers::from_dataframe(&dataframe, &style_columns()).unwrap();
+        group.bench_with_input(
+            BenchmarkId::new("cached buffers", name),
+            &(geometry, style),
+            |b, (geometry, style)| b.iter(|| read_buffers(black_box(geometry), black_box(style))),
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("build buffers once", name),
+            &dataframe,
+            |b, dataframe| {
+                b.iter(|| {
+                    GeometryBuffers::from_dataframe(
+                        black_box(dataframe),
+                        "Face",
+                        &FlDataFrameSpecialColumn::Rectangle,
+                    )
+                    .unwrap()
+                    .len()
+                })
+            },
+        );
+
+        let render_path = RenderPath::new(fl_dataframe);
+        group.bench_function(BenchmarkId::new("render path across frames", name), |b| {
+            b.iter(|| render_path.frame())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_geometry_buffers);
+criterion_main!(benches);
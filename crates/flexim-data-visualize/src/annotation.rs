@@ -0,0 +1,439 @@
+use crate::transform::{inverse_transform_pos, transform_pos};
+use crate::visualize::VisualizeState;
+use anyhow::Context as _;
+use egui::{Color32, Context, Id, Key, Painter, Pos2, Shape, Stroke, Ui, Vec2};
+use flexim_data_type::{
+    FlAffineTransform, FlDataFrame, FlDataFrameRectangle, FlDataFrameSpecialColumn, FlDataReference,
+};
+use flexim_storage::{Bag, BagId};
+use itertools::Itertools;
+use polars::prelude::*;
+
+const HANDLE_RADIUS: f32 = 6.0;
+/// これより小さい図形はドラッグで作成しない (スクリーン座標)
+const MINIMUM_CREATE_SIZE: f32 = 3.0;
+
+/// Rectangle と Segment はどちらも (x1, y1, x2, y2) で表せる
+pub type Geometry = [f64; 4];
+
+/// 編集後の DataFrame。アプリ側で新しい世代として Bag に追加する
+#[derive(Debug, Clone)]
+pub struct AnnotationEdit {
+    pub bag_id: BagId,
+    pub reference: FlDataReference,
+    pub dataframe: FlDataFrame,
+}
+
+fn annotation_edits_id() -> Id {
+    Id::new("annotation edits")
+}
+
+fn push_annotation_edit(ctx: &Context, edit: AnnotationEdit) {
+    ctx.data_mut(|data| {
+        data.get_temp_mut_or_default::<Vec<AnnotationEdit>>(annotation_edits_id())
+            .push(edit)
+    });
+}
+
+/// 前回の呼び出し以降に確定した編集を取り出す
+pub fn take_annotation_edits(ctx: &Context) -> Vec<AnnotationEdit> {
+    ctx.data_mut(|data| data.remove_temp::<Vec<AnnotationEdit>>(annotation_edits_id()))
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Handle {
+    Move,
+    /// 動かす x, y の Geometry 上の位置
+    Vertex(usize, usize),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct EditDrag {
+    /// 作成中の場合は None
+    row: Option<u64>,
+    handle: Handle,
+    start: Vec2,
+    geometry: Geometry,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AnnotationEditState {
+    pub enabled: bool,
+    selected: Option<u64>,
+    drag: Option<EditDrag>,
+}
+
+impl AnnotationEditState {
+    fn storage_id(id: Id) -> Id {
+        id.with("annotation edit")
+    }
+
+    pub fn load(ctx: &Context, id: Id) -> Self {
+        ctx.data(|data| data.get_temp(Self::storage_id(id)))
+            .unwrap_or_default()
+    }
+
+    pub fn store(self, ctx: &Context, id: Id) {
+        ctx.data_mut(|data| data.insert_temp(Self::storage_id(id), self));
+    }
+}
+
+fn vertices(kind: &FlDataFrameSpecialColumn) -> &'static [(usize, usize)] {
+    match kind {
+        FlDataFrameSpecialColumn::Segment => &[(0, 1), (2, 3)],
+        _ => &[(0, 1), (2, 1), (2, 3), (0, 3)],
+    }
+}
+
+fn dragged(geometry: Geometry, handle: Handle, delta: Vec2) -> Geometry {
+    let (dx, dy) = (delta.x as f64, delta.y as f64);
+    let mut geometry = geometry;
+    match handle {
+        Handle::Move => {
+            geometry[0] += dx;
+            geometry[1] += dy;
+            geometry[2] += dx;
+            geometry[3] += dy;
+        }
+        Handle::Vertex(x, y) => {
+            geometry[x] += dx;
+            geometry[y] += dy;
+        }
+    }
+    geometry
+}
+
+fn distance_to_segment(p: Pos2, a: Pos2, b: Pos2) -> f32 {
+    let ab = b - a;
+    let t = if ab.length_sq() > 0.0 {
+        ((p - a).dot(ab) / ab.length_sq()).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    p.distance(a + ab * t)
+}
+
+/// 表示中の図形のドラッグによる移動・変形、空白部分のドラッグによる作成、Delete キーによる削除を行う
+/// `shapes` は (元の DataFrame の行番号, 図形) で、描画順に並んでいる
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn edit_annotations(
+    ui: &mut Ui,
+    bag: &Bag,
+    painter: &mut Painter,
+    state: &VisualizeState,
+    edit_state: &mut AnnotationEditState,
+    reference: &FlDataReference,
+    dataframe: &FlDataFrame,
+    column: &str,
+    shapes: &[(u64, Geometry)],
+    transform: &FlAffineTransform,
+) -> anyhow::Result<()> {
+    let kind = dataframe
+        .special_columns
+        .get(column)
+        .with_context(|| format!("special column not found: {}", column))?;
+    let origin = painter.clip_rect().min;
+    let to_screen = |x: f64, y: f64| {
+        origin + state.absolute_to_screen(transform_pos(transform, Vec2::new(x as f32, y as f32)))
+    };
+    let to_data =
+        |pos: Pos2| inverse_transform_pos(transform, state.screen_to_absolute(pos - origin));
+
+    let (pointer, pressed, released) = ui.input(|input| {
+        (
+            input.pointer.interact_pos(),
+            input.pointer.primary_pressed(),
+            input.pointer.primary_released(),
+        )
+    });
+    let (delete, escape) = if ui.ctx().wants_keyboard_input() {
+        (false, false)
+    } else {
+        ui.input(|input| {
+            (
+                input.key_pressed(Key::Delete) || input.key_pressed(Key::Backspace),
+                input.key_pressed(Key::Escape),
+            )
+        })
+    };
+    let selected_geometry = edit_state
+        .selected
+        .and_then(|row| shapes.iter().find(|(r, _)| *r == row))
+        .map(|(_, g)| *g);
+
+    let pressed = pressed && !state.inspecting && ui.rect_contains_pointer(painter.clip_rect());
+    if let Some(pointer) = pointer.filter(|_| pressed) {
+        let handle = selected_geometry.and_then(|g| {
+            vertices(kind)
+                .iter()
+                .find(|(x, y)| to_screen(g[*x], g[*y]).distance(pointer) < HANDLE_RADIUS)
+                .map(|(x, y)| (edit_state.selected, Handle::Vertex(*x, *y), g))
+        });
+        let body = || {
+            let p = to_data(pointer);
+            shapes.iter().rev().find_map(|(row, g)| {
+                let hit = match kind {
+                    FlDataFrameSpecialColumn::Segment => {
+                        distance_to_segment(pointer, to_screen(g[0], g[1]), to_screen(g[2], g[3]))
+                            < HANDLE_RADIUS
+                    }
+                    _ => {
+                        let (x, y) = (p.x as f64, p.y as f64);
+                        g[0].min(g[2]) <= x
+                            && x <= g[0].max(g[2])
+                            && g[1].min(g[3]) <= y
+                            && y <= g[1].max(g[3])
+                    }
+                };
+                hit.then_some((Some(*row), Handle::Move, *g))
+            })
+        };
+        let (row, handle, geometry) = handle.or_else(body).unwrap_or_else(|| {
+            let p = to_data(pointer);
+            let (x, y) = (p.x as f64, p.y as f64);
+            (None, Handle::Vertex(2, 3), [x, y, x, y])
+        });
+        edit_state.selected = row;
+        edit_state.drag = Some(EditDrag {
+            row,
+            handle,
+            start: to_data(pointer),
+            geometry,
+        });
+    }
+
+    let mut edited = None;
+    if let Some(drag) = edit_state.drag {
+        let geometry = pointer
+            .map(|p| dragged(drag.geometry, drag.handle, to_data(p) - drag.start))
+            .unwrap_or(drag.geometry);
+        if escape {
+            edit_state.drag = None;
+        } else if released {
+            edit_state.drag = None;
+            match drag.row {
+                Some(row) if geometry != drag.geometry => {
+                    edited = Some(set_geometry(dataframe, column, row as usize, geometry)?);
+                }
+                None => {
+                    let size =
+                        to_screen(geometry[2], geometry[3]) - to_screen(geometry[0], geometry[1]);
+                    if size.length() >= MINIMUM_CREATE_SIZE {
+                        edited = Some(append_geometry(dataframe, column, geometry)?);
+                        edit_state.selected = Some(dataframe.value.height() as u64);
+                    }
+                }
+                _ => {}
+            }
+        }
+        draw_geometry(painter, kind, geometry, &to_screen, Color32::YELLOW);
+    } else if let Some(row) = edit_state.selected {
+        if delete {
+            edited = Some(remove_row(dataframe, row as usize)?);
+            edit_state.selected = None;
+        } else if escape {
+            edit_state.selected = None;
+        }
+    }
+
+    if let Some(geometry) = edit_state
+        .drag
+        .is_none()
+        .then_some(selected_geometry)
+        .flatten()
+    {
+        draw_geometry(painter, kind, geometry, &to_screen, Color32::YELLOW);
+        for (x, y) in vertices(kind) {
+            painter.circle(
+                to_screen(geometry[*x], geometry[*y]),
+                HANDLE_RADIUS / 2.0,
+                Color32::WHITE,
+                Stroke::new(1.0, Color32::BLACK),
+            );
+        }
+    }
+
+    if let Some(dataframe) = edited {
+        push_annotation_edit(
+            ui.ctx(),
+            AnnotationEdit {
+                bag_id: bag.id,
+                reference: reference.clone(),
+                dataframe,
+            },
+        );
+    }
+    Ok(())
+}
+
+fn draw_geometry(
+    painter: &Painter,
+    kind: &FlDataFrameSpecialColumn,
+    geometry: Geometry,
+    to_screen: &impl Fn(f64, f64) -> Pos2,
+    color: Color32,
+) {
+    let points = vertices(kind)
+        .iter()
+        .map(|(x, y)| to_screen(geometry[*x], geometry[*y]))
+        .collect_vec();
+    let stroke = Stroke::new(1.0, color);
+    match kind {
+        FlDataFrameSpecialColumn::Segment => {
+            painter.line_segment([points[0], points[1]], stroke);
+        }
+        _ => {
+            painter.add(Shape::closed_line(points, stroke));
+        }
+    }
+}
+
+/// 表示中の行の図形を (元の DataFrame の行番号, 図形) にする
+pub(crate) fn geometries(series: &Series, indices: &[u64]) -> Vec<(u64, Geometry)> {
+    series
+        .iter()
+        .zip(indices)
+        .filter_map(|(value, row)| {
+            let r = FlDataFrameRectangle::try_from(value).ok()?;
+            Some((*row, [r.x1, r.y1, r.x2, r.y2]))
+        })
+        .collect()
+}
+
+/// 元の型のまま 1 行の図形の列を作る
+fn geometry_column(
+    dataframe: &DataFrame,
+    column: &str,
+    geometry: Geometry,
+) -> anyhow::Result<Column> {
+    let DataType::Struct(fields) = dataframe.column(column)?.dtype() else {
+        anyhow::bail!("{} is not a struct column", column);
+    };
+    let fields = fields
+        .iter()
+        .map(|field| {
+            let value = match field.name().as_str() {
+                "x1" => geometry[0],
+                "y1" => geometry[1],
+                "x2" => geometry[2],
+                "y2" => geometry[3],
+                name => anyhow::bail!("unexpected field: {}", name),
+            };
+            Ok(Series::new(field.name().clone(), [value]).cast(field.dtype())?)
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(StructChunked::from_series(column.into(), 1, fields.iter())?
+        .into_series()
+        .into())
+}
+
+pub fn set_geometry(
+    dataframe: &FlDataFrame,
+    column: &str,
+    row: usize,
+    geometry: Geometry,
+) -> anyhow::Result<FlDataFrame> {
+    let df = &dataframe.value;
+    anyhow::ensure!(row < df.height(), "row {} is out of range", row);
+    let mut new_row = df.slice(row as i64, 1);
+    new_row.with_column(geometry_column(df, column, geometry)?)?;
+    let mut value = df.slice(0, row);
+    value.vstack_mut(&new_row)?;
+    value.vstack_mut(&df.slice(row as i64 + 1, df.height()))?;
+    Ok(FlDataFrame::new(value, dataframe.special_columns.clone()))
+}
+
+/// 図形以外の値が null の行を末尾に追加する
+pub fn append_geometry(
+    dataframe: &FlDataFrame,
+    column: &str,
+    geometry: Geometry,
+) -> anyhow::Result<FlDataFrame> {
+    let df = &dataframe.value;
+    let columns = df
+        .get_columns()
+        .iter()
+        .map(|c| {
+            if c.name().as_str() == column {
+                geometry_column(df, column, geometry)
+            } else {
+                Ok(Series::full_null(c.name().clone(), 1, c.dtype()).into())
+            }
+        })
+        .collect::<anyhow::Result<Vec<Column>>>()?;
+    let value = df.vstack(&DataFrame::new(columns)?)?;
+    Ok(FlDataFrame::new(value, dataframe.special_columns.clone()))
+}
+
+pub fn remove_row(dataframe: &FlDataFrame, row: usize) -> anyhow::Result<FlDataFrame> {
+    let df = &dataframe.value;
+    anyhow::ensure!(row < df.height(), "row {} is out of range", row);
+    let mut value = df.slice(0, row);
+    value.vstack_mut(&df.slice(row as i64 + 1, df.height()))?;
+    Ok(FlDataFrame::new(value, dataframe.special_columns.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn boxes() -> FlDataFrame {
+        let field = |name: &str, values: [f32; 2]| Series::new(name.into(), values);
+        let face = StructChunked::from_series(
+            "face".into(),
+            2,
+            [
+                field("x1", [0.0, 10.0]),
+                field("y1", [0.0, 10.0]),
+                field("x2", [1.0, 20.0]),
+                field("y2", [1.0, 20.0]),
+            ]
+            .iter(),
+        )
+        .unwrap()
+        .into_series();
+        let label = Series::new("label".into(), ["a", "b"]);
+        FlDataFrame::new(
+            DataFrame::new(vec![label.into(), face.into()]).unwrap(),
+            [("face".to_string(), FlDataFrameSpecialColumn::Rectangle)]
+                .into_iter()
+                .collect(),
+        )
+    }
+
+    fn rectangle(dataframe: &FlDataFrame, row: usize) -> Option<FlDataFrameRectangle> {
+        FlDataFrameRectangle::try_from(dataframe.value.column("face").unwrap().get(row).unwrap())
+            .ok()
+    }
+
+    #[test]
+    fn edit_geometries_keeps_schema() {
+        let dataframe = boxes();
+        let moved = dragged([10.0, 10.0, 20.0, 20.0], Handle::Move, Vec2::new(1.0, 2.0));
+        assert_eq!(moved, [11.0, 12.0, 21.0, 22.0]);
+        let resized = dragged(moved, Handle::Vertex(2, 1), Vec2::new(4.0, -2.0));
+        assert_eq!(resized, [11.0, 10.0, 25.0, 22.0]);
+
+        let edited = set_geometry(&dataframe, "face", 1, resized).unwrap();
+        assert_eq!(edited.value.schema(), dataframe.value.schema());
+        assert_eq!(rectangle(&edited, 1).unwrap().x2, 25.0);
+        assert_eq!(rectangle(&edited, 0), rectangle(&dataframe, 0));
+
+        let appended = append_geometry(&edited, "face", [1.0, 2.0, 3.0, 4.0]).unwrap();
+        assert_eq!(appended.value.height(), 3);
+        assert_eq!(rectangle(&appended, 2).unwrap().y2, 4.0);
+        assert!(appended
+            .value
+            .column("label")
+            .unwrap()
+            .get(2)
+            .unwrap()
+            .is_null());
+
+        let removed = remove_row(&appended, 0).unwrap();
+        assert_eq!(removed.value.height(), 2);
+        assert_eq!(rectangle(&removed, 0).unwrap().x2, 25.0);
+        assert_ne!(removed.id, dataframe.id);
+    }
+}
@@ -1,6 +1,8 @@
+use crate::geometry::{GeometryBuffers, StyleBuffers, StyleColumns};
+use crate::tiles::ImagePyramid;
 use egui::ahash::HashMap;
 use egui::util::cache::CacheTrait;
-use egui::Id;
+use egui::{Id, TextureHandle, TextureId};
 use flexim_data_type::FlImage;
 use std::any::Any;
 use std::sync::Arc;
@@ -9,6 +11,8 @@ use std::sync::Arc;
 pub enum Poll<T> {
     Ready(T),
     Pending,
+    /// 作成に失敗した理由
+    Failed(String),
 }
 
 #[derive(Default)]
@@ -25,6 +29,10 @@ impl VisualizedImageCache {
         self.cached_images.insert(id, Poll::Pending);
     }
 
+    pub fn insert_failed(&mut self, id: Id, error: String) {
+        self.cached_images.insert(id, Poll::Failed(error));
+    }
+
     pub fn get(&self, id: Id) -> Option<Poll<Arc<FlImage>>> {
         self.cached_images.get(&id).cloned()
     }
@@ -41,3 +49,154 @@ impl CacheTrait for VisualizedImageCache {
         self
     }
 }
+
+/// この回数のフレームの間描画されなかったタイルは GPU から解放する
+const TILE_RETENTION_PASSES: u64 = 120;
+/// この回数のフレームの間描画されなかった画像のピラミッドはメモリから解放する
+const PYRAMID_RETENTION_PASSES: u64 = 600;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TileKey {
+    pub key: Id,
+    pub level: usize,
+    pub x: u32,
+    pub y: u32,
+}
+
+/// 大きな画像のピラミッドと転送済みのタイル
+#[derive(Default)]
+pub struct TiledImageCache {
+    pyramids: HashMap<Id, (Poll<Arc<ImagePyramid>>, u64)>,
+    tiles: HashMap<TileKey, (TextureHandle, u64)>,
+    pass: u64,
+}
+
+impl TiledImageCache {
+    pub fn insert_pyramid(&mut self, id: Id, pyramid: ImagePyramid) {
+        self.pyramids
+            .insert(id, (Poll::Ready(Arc::new(pyramid)), self.pass));
+    }
+
+    pub fn insert_pending(&mut self, id: Id) {
+        self.pyramids.insert(id, (Poll::Pending, self.pass));
+    }
+
+    pub fn insert_failed(&mut self, id: Id, error: String) {
+        self.pyramids.insert(id, (Poll::Failed(error), self.pass));
+    }
+
+    /// ピラミッドを返し、使用中として記録する
+    pub fn pyramid(&mut self, id: Id) -> Option<Poll<Arc<ImagePyramid>>> {
+        let (pyramid, used) = self.pyramids.get_mut(&id)?;
+        *used = self.pass;
+        Some(pyramid.clone())
+    }
+
+    pub fn insert_tile(&mut self, key: TileKey, texture: TextureHandle) {
+        self.tiles.insert(key, (texture, self.pass));
+    }
+
+    /// 転送済みのタイルを返し、使用中として記録する
+    pub fn tile(&mut self, key: TileKey) -> Option<TextureId> {
+        let (texture, used) = self.tiles.get_mut(&key)?;
+        *used = self.pass;
+        Some(texture.id())
+    }
+}
+
+impl CacheTrait for TiledImageCache {
+    fn update(&mut self) {
+        self.pass += 1;
+        let pass = self.pass;
+        self.tiles
+            .retain(|_, (_, used)| pass - *used <= TILE_RETENTION_PASSES);
+        self.pyramids
+            .retain(|_, (_, used)| pass - *used <= PYRAMID_RETENTION_PASSES);
+    }
+
+    fn len(&self) -> usize {
+        self.pyramids.len() + self.tiles.len()
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// この回数のフレームの間使われなかった座標と色・ラベルは解放する
+const GEOMETRY_RETENTION_PASSES: u64 = 120;
+
+/// 特殊列ごとの座標と色・ラベル。フィルタ結果の世代が変わったら作り直す
+#[derive(Default)]
+pub struct GeometryCache {
+    geometries: HashMap<Id, (Option<u64>, Arc<GeometryBuffers>, u64)>,
+    styles: HashMap<Id, (Option<u64>, StyleColumns, Arc<StyleBuffers>, u64)>,
+    pass: u64,
+}
+
+impl GeometryCache {
+    pub fn insert_geometry(
+        &mut self,
+        id: Id,
+        generation: Option<u64>,
+        geometry: Arc<GeometryBuffers>,
+    ) {
+        self.geometries
+            .insert(id, (generation, geometry, self.pass));
+    }
+
+    /// 同じ世代の座標を返し、使用中として記録する
+    pub fn geometry(&mut self, id: Id, generation: Option<u64>) -> Option<Arc<GeometryBuffers>> {
+        let (cached, geometry, used) = self.geometries.get_mut(&id)?;
+        if *cached != generation {
+            return None;
+        }
+        *used = self.pass;
+        Some(geometry.clone())
+    }
+
+    pub fn insert_style(
+        &mut self,
+        id: Id,
+        generation: Option<u64>,
+        columns: StyleColumns,
+        style: Arc<StyleBuffers>,
+    ) {
+        self.styles
+            .insert(id, (generation, columns, style, self.pass));
+    }
+
+    /// 同じ世代・同じ列の色とラベルを返し、使用中として記録する
+    pub fn style(
+        &mut self,
+        id: Id,
+        generation: Option<u64>,
+        columns: &StyleColumns,
+    ) -> Option<Arc<StyleBuffers>> {
+        let (cached, cached_columns, style, used) = self.styles.get_mut(&id)?;
+        if *cached != generation || cached_columns != columns {
+            return None;
+        }
+        *used = self.pass;
+        Some(style.clone())
+    }
+}
+
+impl CacheTrait for GeometryCache {
+    fn update(&mut self) {
+        self.pass += 1;
+        let pass = self.pass;
+        self.geometries
+            .retain(|_, (_, _, used)| pass - *used <= GEOMETRY_RETENTION_PASSES);
+        self.styles
+            .retain(|_, (_, _, _, used)| pass - *used <= GEOMETRY_RETENTION_PASSES);
+    }
+
+    fn len(&self) -> usize {
+        self.geometries.len() + self.styles.len()
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
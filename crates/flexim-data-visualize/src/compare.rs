@@ -0,0 +1,391 @@
+use crate::cache::{Poll, VisualizedImageCache};
+use crate::layer::{drawn_layers, LayerSettings};
+use crate::visualize::{colormap_image, draw_image, draw_message, DataRender, VisualizeState};
+use egui::{
+    Color32, ComboBox, Context, CursorIcon, DragValue, Id, Image, Painter, Rect, Sense, Slider,
+    Stroke, Ui, Vec2, Widget,
+};
+use enum_iterator::{all, Sequence};
+use flexim_data_type::FlData;
+use flexim_storage::Bag;
+use itertools::Itertools;
+use ndarray::{s, Array2};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+/// スタックタブで画像レイヤーを比較する方法
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Sequence)]
+pub enum StackCompareMode {
+    #[default]
+    Overlay,
+    Swipe,
+    Difference,
+    Flicker,
+}
+
+impl Display for StackCompareMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Overlay => write!(f, "Overlay"),
+            Self::Swipe => write!(f, "Swipe"),
+            Self::Difference => write!(f, "Difference"),
+            Self::Flicker => write!(f, "Flicker"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StackCompareState {
+    pub mode: StackCompareMode,
+    /// 描画領域の幅に対する分割線の位置
+    pub swipe_position: f32,
+    /// 秒
+    pub flicker_interval: f64,
+}
+
+impl Default for StackCompareState {
+    fn default() -> Self {
+        Self {
+            mode: StackCompareMode::default(),
+            swipe_position: 0.5,
+            flicker_interval: 0.5,
+        }
+    }
+}
+
+/// 比較の対象になる画像レイヤー
+pub fn is_image_layer(render: &DataRender) -> bool {
+    matches!(render, DataRender::Image(_) | DataRender::Tensor2D(_))
+}
+
+impl StackCompareState {
+    fn storage_id(id: Id) -> Id {
+        id.with("stack compare")
+    }
+
+    pub fn load(ctx: &Context, id: Id) -> Self {
+        ctx.data_mut(|data| data.get_persisted(Self::storage_id(id)))
+            .unwrap_or_default()
+    }
+
+    pub fn store(&self, ctx: &Context, id: Id) {
+        ctx.data_mut(|data| data.insert_persisted(Self::storage_id(id), self.clone()));
+    }
+
+    pub fn show_header(&mut self, ui: &mut Ui) {
+        ui.horizontal_wrapped(|ui| {
+            ComboBox::from_id_salt("stack compare mode")
+                .selected_text(self.mode.to_string())
+                .show_ui(ui, |ui| {
+                    for mode in all::<StackCompareMode>() {
+                        ui.selectable_value(&mut self.mode, mode, mode.to_string());
+                    }
+                });
+            match self.mode {
+                StackCompareMode::Swipe => {
+                    Slider::new(&mut self.swipe_position, 0.0..=1.0)
+                        .show_value(false)
+                        .ui(ui);
+                }
+                StackCompareMode::Flicker => {
+                    ui.label("Interval");
+                    DragValue::new(&mut self.flicker_interval)
+                        .range(0.05..=5.0)
+                        .speed(0.01)
+                        .suffix("s")
+                        .ui(ui);
+                }
+                StackCompareMode::Overlay | StackCompareMode::Difference => {}
+            }
+        });
+    }
+
+    /// Flicker で表示する画像レイヤーの番号
+    fn flicker_index(&self, time: f64, image_layers: usize) -> usize {
+        (time / self.flicker_interval.max(0.01)) as usize % image_layers.max(1)
+    }
+
+    /// 表示するレイヤーを比較モードに応じて描画する。Difference の場合は最初の2枚の画像レイヤーの代わりに差分を描く
+    /// `layers` は `stack` と同じ順の各レイヤーの設定
+    pub fn render(
+        &mut self,
+        ui: &mut Ui,
+        bag: &Bag,
+        painter: &mut Painter,
+        visualize_state: &VisualizeState,
+        stack: &[Arc<DataRender>],
+        layers: &[LayerSettings],
+    ) -> anyhow::Result<()> {
+        let stack = stack
+            .iter()
+            .zip(layers)
+            .zip(drawn_layers(layers))
+            .filter_map(|(layer, drawn)| drawn.then_some(layer))
+            .collect_vec();
+        let image_layers = stack.iter().filter(|(r, _)| is_image_layer(r)).count();
+        let time = ui.input(|input| input.time);
+        if self.mode == StackCompareMode::Flicker {
+            ui.ctx()
+                .request_repaint_after(std::time::Duration::from_secs_f64(self.flicker_interval));
+        }
+        let difference_layers = stack
+            .iter()
+            .map(|(r, _)| *r)
+            .filter(|r| is_image_layer(r))
+            .take(2)
+            .collect_vec();
+
+        let view_rect = painter.clip_rect();
+        let divider = view_rect.left() + view_rect.width() * self.swipe_position;
+        let mut image_index = 0;
+        for (render, settings) in stack {
+            let opacity = painter.opacity();
+            painter.multiply_opacity(settings.opacity);
+            let start = shape_index(painter);
+
+            ui.add_enabled_ui(!settings.locked, |ui| -> anyhow::Result<()> {
+                if !is_image_layer(render) {
+                    return render.render(ui, bag, painter, visualize_state);
+                }
+                match self.mode {
+                    StackCompareMode::Overlay => {
+                        render.render(ui, bag, painter, visualize_state)?;
+                    }
+                    StackCompareMode::Swipe => {
+                        render.render(ui, bag, painter, visualize_state)?;
+                        // 2枚目以降の画像は分割線の右側だけ見せる
+                        if image_index > 0 {
+                            let clip = Rect::from_x_y_ranges(
+                                divider..=view_rect.right(),
+                                view_rect.y_range(),
+                            );
+                            clip_shapes(painter, start, clip);
+                        }
+                    }
+                    StackCompareMode::Flicker => {
+                        if image_index == self.flicker_index(time, image_layers) {
+                            render.render(ui, bag, painter, visualize_state)?;
+                        }
+                    }
+                    StackCompareMode::Difference => {
+                        if difference_layers.len() < 2 || image_index >= 2 {
+                            render.render(ui, bag, painter, visualize_state)?;
+                        } else if image_index == 0 {
+                            draw_difference(
+                                ui.ctx(),
+                                bag,
+                                painter,
+                                visualize_state,
+                                difference_layers[0],
+                                difference_layers[1],
+                            )?;
+                        }
+                    }
+                }
+                image_index += 1;
+                Ok(())
+            })
+            .inner?;
+            painter.set_opacity(opacity);
+        }
+
+        if self.mode == StackCompareMode::Swipe && image_layers >= 2 {
+            let handle = Rect::from_center_size(
+                egui::pos2(divider, view_rect.center().y),
+                Vec2::new(8.0, view_rect.height()),
+            );
+            let response = ui
+                .interact(
+                    handle,
+                    visualize_state.id.with("swipe divider"),
+                    Sense::drag(),
+                )
+                .on_hover_cursor(CursorIcon::ResizeHorizontal);
+            if response.dragged() && view_rect.width() > 0.0 {
+                self.swipe_position = (self.swipe_position
+                    + response.drag_delta().x / view_rect.width())
+                .clamp(0.0, 1.0);
+            }
+            painter.vline(
+                divider,
+                view_rect.y_range(),
+                Stroke::new(2.0, Color32::WHITE),
+            );
+        }
+        Ok(())
+    }
+}
+
+fn shape_index(painter: &Painter) -> usize {
+    painter.ctx().graphics(|g| {
+        g.get(painter.layer_id())
+            .map(|list| list.next_idx().0)
+            .unwrap_or_default()
+    })
+}
+
+/// `start` 以降に描画された図形を `clip` の範囲に制限する
+fn clip_shapes(painter: &Painter, start: usize, clip: Rect) {
+    let end = shape_index(painter);
+    painter.ctx().graphics_mut(|g| {
+        let list = g.entry(painter.layer_id());
+        for i in start..end {
+            list.mutate_shape(egui::layers::ShapeIdx(i), |shape| {
+                shape.clip_rect = shape.clip_rect.intersect(clip);
+            });
+        }
+    });
+}
+
+/// 画像レイヤーを 0.0 から 1.0 の輝度と左上の位置 (x, y) にする
+fn layer_values(bag: &Bag, render: &DataRender) -> anyhow::Result<(Array2<f64>, (i64, i64))> {
+    match bag.data_by_reference(&render.reference())? {
+        FlData::Image(image) => {
+            let luma = image::load_from_memory(&image.value)?.to_luma8();
+            let values =
+                Array2::from_shape_fn((luma.height() as usize, luma.width() as usize), |(y, x)| {
+                    luma.get_pixel(x as u32, y as u32).0[0] as f64 / 255.0
+                });
+            Ok((values, (0, 0)))
+        }
+        FlData::Tensor(tensor) => {
+            let (min, max) = tensor
+                .value
+                .iter()
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| {
+                    (min.min(*v), max.max(*v))
+                });
+            let range = if max > min { max - min } else { 1.0 };
+            let values = tensor.value.mapv(|v| (v - min) / range);
+            Ok((values, (tensor.offset.1 as i64, tensor.offset.0 as i64)))
+        }
+        _ => anyhow::bail!("not an image layer"),
+    }
+}
+
+/// 2つのレイヤーが重なる範囲の差の絶対値と、その範囲の左上の位置
+pub fn abs_difference(
+    a: &(Array2<f64>, (i64, i64)),
+    b: &(Array2<f64>, (i64, i64)),
+) -> Option<(Array2<f64>, (i64, i64))> {
+    let ((a, (ax, ay)), (b, (bx, by))) = (a, b);
+    let x1 = (*ax).max(*bx);
+    let y1 = (*ay).max(*by);
+    let x2 = (ax + a.ncols() as i64).min(bx + b.ncols() as i64);
+    let y2 = (ay + a.nrows() as i64).min(by + b.nrows() as i64);
+    if x1 >= x2 || y1 >= y2 {
+        return None;
+    }
+    let region = |values: &Array2<f64>, x: i64, y: i64| {
+        values
+            .slice(s![
+                (y1 - y) as usize..(y2 - y) as usize,
+                (x1 - x) as usize..(x2 - x) as usize
+            ])
+            .to_owned()
+    };
+    let difference = (region(a, *ax, *ay) - region(b, *bx, *by)).mapv(f64::abs);
+    Some((difference, (x1, y1)))
+}
+
+fn draw_difference(
+    ctx: &Context,
+    bag: &Bag,
+    painter: &mut Painter,
+    visualize_state: &VisualizeState,
+    a: &DataRender,
+    b: &DataRender,
+) -> anyhow::Result<()> {
+    let data_id = |render: &DataRender| -> anyhow::Result<u64> {
+        Ok(bag.data_by_reference(&render.reference())?.id())
+    };
+    let id = Id::new("stack difference")
+        .with(data_id(a)?)
+        .with(data_id(b)?);
+    let offset_id = id.with("offset");
+
+    let cached = ctx.memory_mut(|mem| mem.caches.cache::<VisualizedImageCache>().get(id));
+    match cached {
+        Some(Poll::Ready(image)) => {
+            let Some(offset) = ctx.memory(|mem| mem.data.get_temp::<(i64, i64)>(offset_id)) else {
+                return Ok(());
+            };
+            let texture = Image::from_bytes(format!("bytes://{:?}.png", id), image.value.clone());
+            let size = Vec2::new(image.width as f32, image.height as f32);
+            let offset = Vec2::new(offset.0 as f32, offset.1 as f32);
+            // 差分は画素単位で求めるため、1枚目のレイヤーの変換で表示する
+            draw_image(
+                painter,
+                &texture,
+                visualize_state,
+                offset,
+                size,
+                &a.transform(bag),
+                Color32::WHITE,
+            )
+        }
+        Some(Poll::Pending) => Ok(()),
+        Some(Poll::Failed(error)) => {
+            draw_message(painter, &error);
+            Ok(())
+        }
+        None => {
+            let layers = (layer_values(bag, a)?, layer_values(bag, b)?);
+            ctx.memory_mut(|mem| {
+                mem.caches
+                    .cache::<VisualizedImageCache>()
+                    .insert_pending(id)
+            });
+            let ctx = ctx.clone();
+            std::thread::spawn(move || {
+                let Some((difference, offset)) = abs_difference(&layers.0, &layers.1) else {
+                    log::warn!("layers do not overlap");
+                    ctx.memory_mut(|mem| {
+                        mem.caches
+                            .cache::<VisualizedImageCache>()
+                            .insert_failed(id, "The layers do not overlap".to_string())
+                    });
+                    ctx.request_repaint();
+                    return;
+                };
+                let image = colormap_image(&difference);
+                ctx.memory_mut(|mem| {
+                    mem.data.insert_temp(offset_id, offset);
+                    mem.caches.cache::<VisualizedImageCache>().insert(id, image);
+                });
+                ctx.request_repaint();
+            });
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn abs_difference_of_overlapping_region() {
+        let a = (array![[0.0, 0.5, 1.0], [0.0, 0.5, 1.0]], (0, 0));
+        let b = (array![[1.0, 1.0], [0.0, 0.0]], (1, 1));
+        let (difference, offset) = abs_difference(&a, &b).unwrap();
+        assert_eq!(offset, (1, 1));
+        assert_eq!(difference, array![[0.5, 0.0]]);
+
+        let far = (array![[0.0]], (10, 10));
+        assert!(abs_difference(&a, &far).is_none());
+    }
+
+    #[test]
+    fn flicker_cycles_image_layers() {
+        let state = StackCompareState {
+            flicker_interval: 0.5,
+            ..Default::default()
+        };
+        assert_eq!(state.flicker_index(0.1, 2), 0);
+        assert_eq!(state.flicker_index(0.6, 2), 1);
+        assert_eq!(state.flicker_index(1.1, 2), 0);
+    }
+}
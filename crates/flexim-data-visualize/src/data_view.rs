@@ -1,17 +1,19 @@
 use crate::visualize::{DataRender, FlDataFrameViewRender};
 use anyhow::Context;
 use egui::ahash::HashMap;
-use egui::{CollapsingHeader, ScrollArea, Style, Ui};
+use egui::{CollapsingHeader, ComboBox, ScrollArea, Style, Ui};
+use enum_iterator::all;
 use flexim_data_type::{FlDataFrame, FlDataReference};
 use flexim_data_view::object::FlObjectView;
-use flexim_data_view::{FlDataFrameView, Id, ShowColumns};
+use flexim_data_view::{FlDataFrameView, FlDataFrameViewContext, Id, ShowColumns};
 use flexim_storage::Bag;
+use flexim_table_widget::export::{ExportFormat, ExportRows};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
 #[derive(Clone, Serialize, Deserialize)]
 pub enum DataView {
-    FlDataFrameView(FlDataFrameView),
+    FlDataFrameView(Box<FlDataFrameView>),
     FlObjectView(FlObjectView),
 }
 
@@ -57,6 +59,19 @@ impl DataView {
             Self::FlObjectView(v) => v.config_panel(ui, bag),
         }
     }
+
+    /// `from` を参照している場合、参照先を `to` に変えたものを返す
+    pub fn retarget(&self, from: &FlDataReference, to: &FlDataReference) -> Option<Self> {
+        if self.reference() != *from {
+            return None;
+        }
+        let mut view = self.clone();
+        match &mut view {
+            Self::FlDataFrameView(v) => v.table.data_reference = to.clone(),
+            Self::FlObjectView(v) => v.content = to.clone(),
+        }
+        Some(view)
+    }
 }
 
 pub trait DataViewable {
@@ -153,6 +168,52 @@ impl DataViewable for FlDataFrameView {
                     }
                 }
             });
+        CollapsingHeader::new("Export")
+            .default_open(false)
+            .show(ui, |ui| {
+                let mut view_context = self.view_context.lock().unwrap();
+                let FlDataFrameViewContext {
+                    export_options: options,
+                    export_error: error,
+                    ..
+                } = &mut *view_context;
+                ComboBox::from_label("Format")
+                    .selected_text(options.format.to_string())
+                    .show_ui(ui, |ui| {
+                        for format in all::<ExportFormat>() {
+                            ui.selectable_value(&mut options.format, format, format.to_string());
+                        }
+                    });
+                for rows in all::<ExportRows>() {
+                    ui.radio_value(&mut options.rows, rows, rows.to_string());
+                }
+                ui.add_enabled_ui(!options.format.requires_flatten(), |ui| {
+                    ui.checkbox(
+                        &mut options.flatten_special_columns,
+                        "Flatten special columns",
+                    )
+                    .on_disabled_hover_text("CSV always flattens struct columns");
+                });
+                if ui.button("Export…").clicked() {
+                    let extension = options.format.extension();
+                    if let Some(path) = rfd::FileDialog::new()
+                        .add_filter(options.format.to_string(), &[extension])
+                        .set_file_name(format!("{}.{}", self.table.data_reference.name, extension))
+                        .save_file()
+                    {
+                        *error = match self.table.export(ui.ctx(), bag, options, &path) {
+                            Ok(()) => None,
+                            Err(e) => {
+                                log::error!("Failed to export dataframe: {:#}", e);
+                                Some(format!("{:#}", e))
+                            }
+                        };
+                    }
+                }
+                if let Some(error) = error {
+                    ui.colored_label(ui.visuals().error_fg_color, error.as_str());
+                }
+            });
     }
 }
 
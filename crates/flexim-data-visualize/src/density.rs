@@ -0,0 +1,130 @@
+use crate::geometry::GeometryBuffers;
+use crate::transform::transform_pos;
+use crate::visualize::VisualizeState;
+use egui::{Color32, Mesh, Painter, Rect, Shape, Vec2};
+use enum_iterator::Sequence;
+use flexim_data_type::FlAffineTransform;
+use scarlet::colormap::{ColorMap, ListedColorMap};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// 密度表示のビンの一辺の大きさ (画面座標)
+const DENSITY_BIN_SIZE: f32 = 8.0;
+/// 密度表示の不透明度
+const DENSITY_ALPHA: u8 = 200;
+
+/// 図形を 1 つずつ描くか、画面上の密度にまとめて描くか
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, Sequence)]
+pub enum LevelOfDetail {
+    /// 表示範囲の図形が閾値より多い時だけ密度にまとめる
+    #[default]
+    Auto,
+    Shapes,
+    Density,
+}
+
+impl Display for LevelOfDetail {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Auto => write!(f, "Auto"),
+            Self::Shapes => write!(f, "Shapes"),
+            Self::Density => write!(f, "Density"),
+        }
+    }
+}
+
+impl LevelOfDetail {
+    /// 表示範囲に `visible` 個の図形がある時に密度にまとめるか
+    pub(crate) fn aggregates(&self, visible: usize, threshold: usize) -> bool {
+        match self {
+            Self::Auto => visible > threshold,
+            Self::Shapes => false,
+            Self::Density => true,
+        }
+    }
+}
+
+/// 大きさ `view_size` の領域を一辺 `bin_size` のビンに分けて点を数える
+/// 横に並ぶビンの数と、行ごとに並べた個数を返す
+fn bin_counts(
+    points: impl IntoIterator<Item = Vec2>,
+    view_size: Vec2,
+    bin_size: f32,
+) -> (usize, Vec<u32>) {
+    let columns = ((view_size.x / bin_size).ceil() as usize).max(1);
+    let rows = ((view_size.y / bin_size).ceil() as usize).max(1);
+    let mut counts = vec![0; columns * rows];
+    for point in points {
+        if !(0.0..view_size.x).contains(&point.x) || !(0.0..view_size.y).contains(&point.y) {
+            continue;
+        }
+        let (x, y) = ((point.x / bin_size) as usize, (point.y / bin_size) as usize);
+        counts[y * columns + x] += 1;
+    }
+    (columns, counts)
+}
+
+/// `rows` の図形の中心を画面上のビンで数えてヒートマップとして描く
+pub(crate) fn draw_density(
+    painter: &Painter,
+    state: &VisualizeState,
+    transform: &FlAffineTransform,
+    geometry: &GeometryBuffers,
+    rows: &[usize],
+) {
+    let view = painter.clip_rect();
+    let points = rows
+        .iter()
+        .filter_map(|i| geometry.center(*i))
+        .map(|center| state.absolute_to_screen(transform_pos(transform, center.to_vec2())));
+    let (columns, counts) = bin_counts(points, view.size(), DENSITY_BIN_SIZE);
+    let max = counts.iter().copied().max().unwrap_or(0);
+    if max == 0 {
+        return;
+    }
+
+    // 少ない所も見えるように対数で正規化する
+    let colormap = ListedColorMap::inferno();
+    let normalize = |count: u32| (count as f64).ln_1p() / (max as f64).ln_1p();
+    let mut mesh = Mesh::default();
+    for (i, count) in counts.iter().enumerate().filter(|(_, count)| **count > 0) {
+        let min =
+            view.min + Vec2::new((i % columns) as f32, (i / columns) as f32) * DENSITY_BIN_SIZE;
+        let color = colormap.transform_single(normalize(*count));
+        mesh.add_colored_rect(
+            Rect::from_min_size(min, Vec2::splat(DENSITY_BIN_SIZE)),
+            Color32::from_rgba_unmultiplied(
+                color.int_r(),
+                color.int_g(),
+                color.int_b(),
+                DENSITY_ALPHA,
+            ),
+        );
+    }
+    painter.add(Shape::mesh(mesh));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bin_counts_in_screen_space() {
+        let points = [
+            Vec2::new(1.0, 1.0),
+            Vec2::new(7.0, 7.0),
+            Vec2::new(9.0, 1.0),
+            Vec2::new(17.0, 9.0),
+            Vec2::new(-1.0, 3.0),
+            Vec2::new(20.0, 3.0),
+        ];
+        let (columns, counts) = bin_counts(points, Vec2::new(20.0, 10.0), 8.0);
+        assert_eq!(columns, 3);
+        assert_eq!(counts, vec![2, 1, 0, 0, 0, 1]);
+
+        assert!(LevelOfDetail::Auto.aggregates(101, 100));
+        assert!(!LevelOfDetail::Auto.aggregates(100, 100));
+        assert!(!LevelOfDetail::Shapes.aggregates(1_000_000, 100));
+        assert!(LevelOfDetail::Density.aggregates(0, 100));
+    }
+}
@@ -0,0 +1,263 @@
+use crate::spatial_index::GridIndex;
+use crate::special_columns_visualize::SpecialColumnShape;
+use crate::visualize::serise_value_to_color;
+use anyhow::{bail, ensure};
+use egui::{Color32, Pos2, Rect};
+use flexim_data_type::{FlDataFrameRectangle, FlDataFrameSegment, FlDataFrameSpecialColumn};
+use geo::Line;
+use itertools::{izip, Itertools};
+use polars::prelude::{DataFrame, DataType, Series};
+
+/// フィルタ結果の特殊列を行ごとの座標の配列にしたもの
+/// 描画のたびに `AnyValue` から変換しないで済むようにフィルタ結果の世代ごとに 1 回だけ作る
+pub struct GeometryBuffers {
+    kind: FlDataFrameSpecialColumn,
+    /// 行ごとの `[x1, y1, x2, y2]`。null の行は `None`
+    coords: Vec<Option<[f64; 4]>>,
+    row_ids: Vec<u64>,
+    index: GridIndex,
+}
+
+/// 座標の配列から取り出した 1 行分の図形
+pub enum BufferedShape {
+    Rectangle(FlDataFrameRectangle),
+    Segment(FlDataFrameSegment),
+}
+
+impl BufferedShape {
+    pub(crate) fn as_shape(&self) -> &dyn SpecialColumnShape {
+        match self {
+            BufferedShape::Rectangle(rectangle) => rectangle,
+            BufferedShape::Segment(segment) => segment,
+        }
+    }
+}
+
+impl GeometryBuffers {
+    /// `dataframe` は元の行番号の列 `__FleximRowId` を含むフィルタ結果
+    pub fn from_dataframe(
+        dataframe: &DataFrame,
+        column: &str,
+        kind: &FlDataFrameSpecialColumn,
+    ) -> anyhow::Result<Self> {
+        let series = dataframe.column(column)?.as_materialized_series();
+        let DataType::Struct(fields) = series.dtype() else {
+            bail!("expected struct column, found {:?}", series.dtype());
+        };
+        let valid = match kind {
+            FlDataFrameSpecialColumn::Rectangle => FlDataFrameRectangle::validate_fields(fields),
+            FlDataFrameSpecialColumn::Segment => FlDataFrameSegment::validate_fields(fields),
+            FlDataFrameSpecialColumn::Color => false,
+        };
+        ensure!(valid, "column {} can not be drawn as {:?}", column, kind);
+
+        let chunked = series.struct_()?;
+        let mut values = vec![];
+        for name in ["x1", "y1", "x2", "y2"] {
+            values.push(chunked.field_by_name(name)?.cast(&DataType::Float64)?);
+        }
+        let nulls = series.is_null();
+        let coords = izip!(
+            nulls.iter(),
+            values[0].f64()?.iter(),
+            values[1].f64()?.iter(),
+            values[2].f64()?.iter(),
+            values[3].f64()?.iter(),
+        )
+        .map(|(null, x1, y1, x2, y2)| {
+            if null == Some(true) {
+                return None;
+            }
+            Some([x1?, y1?, x2?, y2?])
+        })
+        .collect_vec();
+
+        let row_ids = dataframe
+            .column("__FleximRowId")?
+            .idx()?
+            .into_no_null_iter()
+            .map(|id| id as u64)
+            .collect_vec();
+
+        let bounds = coords
+            .iter()
+            .map(|coords| {
+                let [x1, y1, x2, y2] = (*coords)?;
+                Some(Rect::from_two_pos(
+                    Pos2::new(x1 as f32, y1 as f32),
+                    Pos2::new(x2 as f32, y2 as f32),
+                ))
+            })
+            .collect_vec();
+
+        Ok(Self {
+            kind: kind.clone(),
+            coords,
+            row_ids,
+            index: GridIndex::build(bounds),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.coords.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.coords.is_empty()
+    }
+
+    /// `i` 行目の元の行番号
+    pub fn row_id(&self, i: usize) -> u64 {
+        self.row_ids[i]
+    }
+
+    pub fn row_ids(&self) -> &[u64] {
+        &self.row_ids
+    }
+
+    /// `rect` と重なる行を元の順序で返す
+    pub fn query(&self, rect: Rect) -> Vec<usize> {
+        self.index.query(rect)
+    }
+
+    pub fn shape(&self, i: usize) -> Option<BufferedShape> {
+        let [x1, y1, x2, y2] = self.coords[i]?;
+        match self.kind {
+            FlDataFrameSpecialColumn::Rectangle => {
+                Some(BufferedShape::Rectangle(FlDataFrameRectangle {
+                    x1,
+                    y1,
+                    x2,
+                    y2,
+                }))
+            }
+            FlDataFrameSpecialColumn::Segment => Some(BufferedShape::Segment(FlDataFrameSegment {
+                x1,
+                y1,
+                x2,
+                y2,
+            })),
+            FlDataFrameSpecialColumn::Color => None,
+        }
+    }
+
+    /// `i` 行目の図形の中心
+    pub fn center(&self, i: usize) -> Option<Pos2> {
+        let [x1, y1, x2, y2] = self.coords[i]?;
+        Some(Pos2::new(
+            ((x1 + x2) / 2.0) as f32,
+            ((y1 + y2) / 2.0) as f32,
+        ))
+    }
+
+    pub fn measure_segments(&self) -> Vec<Line> {
+        (0..self.len())
+            .filter_map(|i| self.shape(i))
+            .flat_map(|shape| shape.as_shape().measure_segments())
+            .collect_vec()
+    }
+}
+
+/// 色とラベルに使う列
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StyleColumns {
+    pub stroke_color: Option<String>,
+    pub fill_color: Option<String>,
+    pub label: Option<String>,
+}
+
+/// 行ごとの色とラベル。使う列を変えるかフィルタ結果の世代が変わった時に作り直す
+pub struct StyleBuffers {
+    pub stroke_colors: Option<Vec<Color32>>,
+    pub fill_colors: Option<Vec<Color32>>,
+    pub labels: Option<Vec<String>>,
+}
+
+impl StyleBuffers {
+    pub fn from_dataframe(dataframe: &DataFrame, columns: &StyleColumns) -> anyhow::Result<Self> {
+        let series = |column: &Option<String>| -> anyhow::Result<Option<Series>> {
+            column
+                .as_ref()
+                .map(|c| {
+                    Ok(dataframe
+                        .column(c.as_str())?
+                        .as_materialized_series()
+                        .rechunk())
+                })
+                .transpose()
+        };
+        let colors = |series: Option<Series>| {
+            series.map(|series| {
+                series
+                    .iter()
+                    .map(|v| serise_value_to_color(series.field().as_ref(), &v))
+                    .collect_vec()
+            })
+        };
+        Ok(Self {
+            stroke_colors: colors(series(&columns.stroke_color)?),
+            fill_colors: colors(series(&columns.fill_color)?),
+            labels: series(&columns.label)?
+                .map(|series| series.iter().map(|v| v.to_string()).collect_vec()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flexim_data_type::FlDataFrame;
+    use polars::prelude::{Column, NamedFrom};
+
+    #[test]
+    fn buffers_skip_null_rows() {
+        let face = Series::new(
+            "Face".into(),
+            [
+                Some(r#"{"x1": 0.0, "y1": 0.0, "x2": 10.0, "y2": 10.0}"#),
+                None,
+                Some(r#"{"x1": 20.0, "y1": 20.0, "x2": 30.0, "y2": 40.0}"#),
+            ],
+        );
+        let name = Series::new("Name".into(), ["a", "b", "c"]);
+        let dataframe = FlDataFrame::try_with_special_columns(
+            DataFrame::new(vec![Column::from(face), Column::from(name)]).unwrap(),
+            [("Face".to_string(), FlDataFrameSpecialColumn::Rectangle)]
+                .into_iter()
+                .collect(),
+        )
+        .unwrap()
+        .value
+        .with_row_index("__FleximRowId".into(), None)
+        .unwrap();
+
+        let geometry = GeometryBuffers::from_dataframe(
+            &dataframe,
+            "Face",
+            &FlDataFrameSpecialColumn::Rectangle,
+        )
+        .unwrap();
+        assert_eq!(geometry.len(), 3);
+        assert!(geometry.shape(1).is_none());
+        assert_eq!(geometry.row_ids(), &[0, 1, 2]);
+        assert_eq!(
+            geometry.query(Rect::from_min_max(
+                Pos2::new(25.0, 35.0),
+                Pos2::new(50.0, 50.0)
+            )),
+            vec![2]
+        );
+        assert_eq!(geometry.measure_segments().len(), 8);
+
+        let style = StyleBuffers::from_dataframe(
+            &dataframe,
+            &StyleColumns {
+                label: Some("Name".to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(style.labels.map(|labels| labels.len()), Some(3));
+        assert!(style.stroke_colors.is_none());
+    }
+}
@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+
+/// スタックタブの各レイヤーの表示設定
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LayerSettings {
+    pub visible: bool,
+    /// ロック中は操作を受け付けない
+    pub locked: bool,
+    pub opacity: f32,
+    pub solo: bool,
+}
+
+impl Default for LayerSettings {
+    fn default() -> Self {
+        Self {
+            visible: true,
+            locked: false,
+            opacity: 1.0,
+            solo: false,
+        }
+    }
+}
+
+/// 各レイヤーを描画するかどうか。solo のレイヤーがあればそれだけを描画する
+pub fn drawn_layers(layers: &[LayerSettings]) -> Vec<bool> {
+    let any_solo = layers.iter().any(|l| l.solo);
+    layers
+        .iter()
+        .map(|l| l.visible && (!any_solo || l.solo))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn solo_overrides_visibility_of_other_layers() {
+        let hidden = LayerSettings {
+            visible: false,
+            ..Default::default()
+        };
+        let solo = LayerSettings {
+            solo: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            drawn_layers(&[LayerSettings::default(), hidden.clone()]),
+            vec![true, false]
+        );
+        assert_eq!(
+            drawn_layers(&[LayerSettings::default(), solo, hidden]),
+            vec![false, true, false]
+        );
+    }
+}
@@ -1,9 +1,22 @@
+pub mod annotation;
 pub(crate) mod cache;
+pub mod compare;
 pub mod data_view;
 pub mod data_visualizable;
+pub mod density;
+pub mod geometry;
+pub mod layer;
+mod link;
+pub mod measurement;
+mod minimap;
 mod pallet;
+mod rulers;
+mod spatial_index;
 mod special_columns_visualize;
+mod tiles;
+pub mod transform;
 pub mod visualize;
+pub mod zoom;
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,126 @@
+use crate::visualize::{Origin, VisualizeState};
+use egui::{Color32, Context, DragValue, Id, Rect, Stroke, Ui, Vec2, Widget};
+
+/// 同じグループのペインで共有する表示範囲
+#[derive(Debug, Clone, PartialEq)]
+struct LinkedView {
+    current_scale: f32,
+    shift: Vec2,
+    origin: Origin,
+}
+
+/// ポインタがあるペインとその絶対座標
+#[derive(Debug, Clone)]
+struct LinkedPointer {
+    source: Id,
+    pos: Vec2,
+    pass: u64,
+}
+
+fn view_id(group: u8) -> Id {
+    Id::new("visualize link group").with(group)
+}
+
+fn pointer_id(group: u8) -> Id {
+    view_id(group).with("pointer")
+}
+
+impl VisualizeState {
+    fn linked_view(&self) -> LinkedView {
+        LinkedView {
+            current_scale: self.current_scale,
+            shift: self.shift,
+            origin: self.origin,
+        }
+    }
+
+    fn apply_linked_view(&mut self, view: LinkedView) {
+        self.current_scale = view.current_scale;
+        self.shift = view.shift;
+        self.origin = view.origin;
+    }
+
+    /// グループの表示範囲に合わせる
+    pub(crate) fn load_linked_view(&mut self, ctx: &Context) {
+        let Some(group) = self.link_group else {
+            return;
+        };
+        if let Some(view) = ctx.data(|data| data.get_temp::<LinkedView>(view_id(group))) {
+            self.apply_linked_view(view);
+        }
+    }
+
+    /// 表示範囲を変えたらグループの他のペインにも反映する
+    pub(crate) fn store_linked_view(&self, ctx: &Context) {
+        let Some(group) = self.link_group else {
+            return;
+        };
+        let view = self.linked_view();
+        let changed = ctx.data_mut(|data| {
+            let changed = data.get_temp::<LinkedView>(view_id(group)).as_ref() != Some(&view);
+            data.insert_temp(view_id(group), view);
+            changed
+        });
+        if changed {
+            // 先に描画されたペインは次のフレームで追従する
+            ctx.request_repaint();
+        }
+    }
+
+    pub(crate) fn link_header(&mut self, ui: &mut Ui) {
+        let linked = self.link_group.is_some();
+        if ui
+            .selectable_label(linked, "🔗")
+            .on_hover_text("Link zoom and pan with other panes in the same group")
+            .clicked()
+        {
+            self.link_group = if linked { None } else { Some(1) };
+            self.load_linked_view(ui.ctx());
+        }
+        if let Some(group) = &mut self.link_group {
+            let previous = *group;
+            DragValue::new(group).range(1..=9).prefix("#").ui(ui);
+            if *group != previous {
+                self.load_linked_view(ui.ctx());
+            }
+        }
+    }
+
+    /// ポインタの位置をグループの他のペインに伝える
+    pub(crate) fn share_pointer(&self, ctx: &Context, pos: Option<Vec2>) {
+        let (Some(group), Some(pos)) = (self.link_group, pos) else {
+            return;
+        };
+        let pointer = LinkedPointer {
+            source: self.id,
+            pos,
+            pass: ctx.cumulative_pass_nr(),
+        };
+        ctx.data_mut(|data| data.insert_temp(pointer_id(group), pointer));
+    }
+
+    /// グループの他のペインにあるポインタの位置に十字線を描く
+    pub(crate) fn draw_linked_crosshair(&self, ui: &Ui, rect: Rect) {
+        let Some(group) = self.link_group else {
+            return;
+        };
+        let Some(pointer) = ui
+            .ctx()
+            .data(|data| data.get_temp::<LinkedPointer>(pointer_id(group)))
+        else {
+            return;
+        };
+        // ポインタが離れたペインより後に描画されるペインのために 1 フレーム分は残す
+        if pointer.source == self.id || pointer.pass + 1 < ui.ctx().cumulative_pass_nr() {
+            return;
+        }
+        let pos = rect.min + self.absolute_to_screen(pointer.pos);
+        if !rect.contains(pos) {
+            return;
+        }
+        let painter = ui.painter_at(rect);
+        let stroke = Stroke::new(1.0, Color32::from_rgba_unmultiplied(255, 255, 0, 160));
+        painter.hline(rect.x_range(), pos.y, stroke);
+        painter.vline(pos.x, rect.y_range(), stroke);
+    }
+}
@@ -0,0 +1,249 @@
+use crate::visualize::VisualizeState;
+use egui::{Align2, Color32, FontId, Painter, Pos2, Rect, Stroke, Ui, Vec2};
+use geo::{coord, Closest, ClosestPoint, EuclideanDistance, Line, Point};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
+
+const MEASUREMENT_COLOR: Color32 = Color32::from_rgb(0, 200, 255);
+
+/// 線分 `[x1, y1, x2, y2]`
+pub type Segment = [f64; 4];
+
+fn to_line(segment: &Segment) -> Line {
+    Line::new(
+        coord!(x: segment[0], y: segment[1]),
+        coord!(x: segment[2], y: segment[3]),
+    )
+}
+
+pub fn from_line(line: &Line) -> Segment {
+    [line.start.x, line.start.y, line.end.x, line.end.y]
+}
+
+/// 固定した計測結果。座標は絶対座標
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Measurement {
+    /// 2 点間の距離
+    Distance { from: [f64; 2], to: [f64; 2] },
+    /// 2 本の線分のなす角
+    Angle { a: Segment, b: Segment },
+    /// 点と線分の距離
+    PointToSegment { point: [f64; 2], segment: Segment },
+}
+
+impl Measurement {
+    /// 距離または角度 (度)
+    pub fn value(&self) -> f64 {
+        match self {
+            Measurement::Distance { from, to } => (to[0] - from[0]).hypot(to[1] - from[1]),
+            Measurement::Angle { a, b } => {
+                let (a, b) = (to_line(a).delta(), to_line(b).delta());
+                let cos = (a.x * b.x + a.y * b.y) / (a.x.hypot(a.y) * b.x.hypot(b.y));
+                cos.clamp(-1.0, 1.0).acos().to_degrees()
+            }
+            Measurement::PointToSegment { point, segment } => {
+                to_line(segment).euclidean_distance(&Point::new(point[0], point[1]))
+            }
+        }
+    }
+
+    pub fn value_label(&self) -> String {
+        match self {
+            Measurement::Angle { .. } => format!("{:.1}°", self.value()),
+            _ => format!("{:.2}", self.value()),
+        }
+    }
+
+    pub(crate) fn draw(&self, painter: &Painter, state: &VisualizeState, origin: Vec2) {
+        let to_screen = |x: f64, y: f64| {
+            state
+                .absolute_to_screen(Vec2::new(x as f32, y as f32))
+                .to_pos2()
+                + origin
+        };
+        let stroke = Stroke::new(2.0, MEASUREMENT_COLOR);
+        let segment = |s: &Segment| {
+            painter.line_segment([to_screen(s[0], s[1]), to_screen(s[2], s[3])], stroke);
+        };
+        let label_pos = match self {
+            Measurement::Distance { from, to } => {
+                let (from, to) = (to_screen(from[0], from[1]), to_screen(to[0], to[1]));
+                painter.line_segment([from, to], stroke);
+                from.lerp(to, 0.5)
+            }
+            Measurement::Angle { a, b } => {
+                segment(a);
+                segment(b);
+                let (a, b) = (to_screen(a[0], a[1]), to_screen(b[0], b[1]));
+                a.lerp(b, 0.5)
+            }
+            Measurement::PointToSegment { point, segment: s } => {
+                segment(s);
+                let closest = match to_line(s).closest_point(&Point::new(point[0], point[1])) {
+                    Closest::SinglePoint(p) | Closest::Intersection(p) => p,
+                    Closest::Indeterminate => Point::new(s[0], s[1]),
+                };
+                let (from, to) = (
+                    to_screen(point[0], point[1]),
+                    to_screen(closest.x(), closest.y()),
+                );
+                painter.add(egui::Shape::dashed_line(&[from, to], stroke, 4.0, 4.0));
+                from.lerp(to, 0.5)
+            }
+        };
+        let text = painter.text(
+            label_pos,
+            Align2::CENTER_CENTER,
+            self.value_label(),
+            FontId::default(),
+            Color32::BLACK,
+        );
+        painter.rect_filled(text.expand(2.0), 0.0, MEASUREMENT_COLOR);
+        painter.text(
+            label_pos,
+            Align2::CENTER_CENTER,
+            self.value_label(),
+            FontId::default(),
+            Color32::BLACK,
+        );
+    }
+}
+
+impl Display for Measurement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Measurement::Distance { .. } => write!(f, "Distance {}", self.value_label()),
+            Measurement::Angle { .. } => write!(f, "Angle {}", self.value_label()),
+            Measurement::PointToSegment { .. } => {
+                write!(f, "Point to Segment {}", self.value_label())
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ExportedMeasurement<'a> {
+    #[serde(flatten)]
+    measurement: &'a Measurement,
+    value: f64,
+}
+
+/// ペインごとの固定した計測結果。レイアウトと一緒に保存される
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Measurements(Arc<Mutex<Vec<Measurement>>>);
+
+impl Measurements {
+    pub fn pin(&self, measurement: Measurement) {
+        self.0.lock().unwrap().push(measurement);
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        let measurements = self.0.lock().unwrap();
+        let exported = measurements
+            .iter()
+            .map(|measurement| ExportedMeasurement {
+                measurement,
+                value: measurement.value(),
+            })
+            .collect::<Vec<_>>();
+        serde_json::to_string_pretty(&exported)
+    }
+
+    pub(crate) fn draw(&self, ui: &Ui, state: &VisualizeState, rect: Rect) {
+        let painter = ui.painter_at(rect);
+        for measurement in self.0.lock().unwrap().iter() {
+            measurement.draw(&painter, state, rect.min.to_vec2());
+        }
+    }
+
+    /// 右のパネルに表示する一覧
+    pub fn panel(&self, ui: &mut Ui, name: &str) {
+        egui::CollapsingHeader::new("Measurements")
+            .default_open(true)
+            .show(ui, |ui| {
+                let mut measurements = self.0.lock().unwrap();
+                if measurements.is_empty() {
+                    ui.label("Right click while inspecting to pin a measurement");
+                }
+                let mut removed = None;
+                for (i, measurement) in measurements.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.label(measurement.to_string());
+                        if ui.small_button("🗑").clicked() {
+                            removed = Some(i);
+                        }
+                    });
+                }
+                if let Some(i) = removed {
+                    measurements.remove(i);
+                }
+                drop(measurements);
+                ui.horizontal(|ui| {
+                    if ui.button("Export JSON…").clicked() {
+                        if let Some(path) = rfd::FileDialog::new()
+                            .add_filter("JSON", &["json"])
+                            .set_file_name(format!("{}_measurements.json", name))
+                            .save_file()
+                        {
+                            let result = self
+                                .to_json()
+                                .map_err(anyhow::Error::from)
+                                .and_then(|json| Ok(std::fs::write(path, json)?));
+                            if let Err(e) = result {
+                                log::error!("Failed to export measurements: {:#}", e);
+                            }
+                        }
+                    }
+                    if ui.button("Clear").clicked() {
+                        self.0.lock().unwrap().clear();
+                    }
+                });
+            });
+    }
+}
+
+/// 2 点間の距離を測る時の始点
+pub(crate) fn measure_start_id() -> egui::Id {
+    egui::Id::new("measure_start")
+}
+
+pub(crate) fn to_point(pos: Pos2) -> [f64; 2] {
+    [pos.x as f64, pos.y as f64]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measurement_values_and_json() {
+        let angle = Measurement::Angle {
+            a: [0.0, 0.0, 10.0, 0.0],
+            b: [0.0, 0.0, 0.0, 5.0],
+        };
+        assert!((angle.value() - 90.0).abs() < 1e-9);
+        let point_to_segment = Measurement::PointToSegment {
+            point: [5.0, 3.0],
+            segment: [0.0, 0.0, 10.0, 0.0],
+        };
+        assert!((point_to_segment.value() - 3.0).abs() < 1e-9);
+
+        let measurements = Measurements::default();
+        measurements.pin(Measurement::Distance {
+            from: [0.0, 0.0],
+            to: [3.0, 4.0],
+        });
+        measurements.pin(angle);
+        let json: serde_json::Value =
+            serde_json::from_str(&measurements.to_json().unwrap()).unwrap();
+        assert_eq!(json[0]["kind"], "distance");
+        assert_eq!(json[0]["value"], 5.0);
+        assert_eq!(json[1]["kind"], "angle");
+
+        let restored: Measurements =
+            serde_json::from_str(&serde_json::to_string(&measurements).unwrap()).unwrap();
+        assert_eq!(restored.0.lock().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,87 @@
+use crate::layer::{drawn_layers, LayerSettings};
+use crate::visualize::{DataRender, VisualizeState};
+use crate::zoom::segments_bounds;
+use egui::{Color32, Rect, Sense, Stroke, StrokeKind, Ui, Vec2};
+use flexim_storage::Bag;
+use std::sync::Arc;
+
+/// ミニマップの最大の大きさ
+const MINIMAP_SIZE: f32 = 160.0;
+/// 描画領域の端からの距離
+const MINIMAP_MARGIN: f32 = 8.0;
+
+impl VisualizeState {
+    /// 描画領域の右下に全体の縮小図と現在の表示範囲を描く
+    /// ミニマップをクリック・ドラッグするとその位置を中心に表示する
+    pub(crate) fn show_minimap(
+        &mut self,
+        ui: &mut Ui,
+        bag: &Bag,
+        contents: &[Arc<DataRender>],
+        layers: &[LayerSettings],
+        view_rect: Rect,
+    ) {
+        let drawn = contents
+            .iter()
+            .zip(drawn_layers(layers))
+            .filter(|(_, drawn)| *drawn)
+            .map(|(render, _)| render)
+            .collect::<Vec<_>>();
+        let segments = drawn
+            .iter()
+            .filter_map(|render| render.measurable_segments(ui.ctx(), bag).ok())
+            .flatten()
+            .collect::<Vec<_>>();
+        let Some(extent) = segments_bounds(&segments) else {
+            return;
+        };
+        if extent.width() <= 0.0 || extent.height() <= 0.0 {
+            return;
+        }
+
+        let scale = (MINIMAP_SIZE / extent.width()).min(MINIMAP_SIZE / extent.height());
+        let size = extent.size() * scale;
+        let rect = Rect::from_min_size(view_rect.max - size - Vec2::splat(MINIMAP_MARGIN), size);
+        if !view_rect.contains_rect(rect) {
+            return;
+        }
+
+        // ミニマップ上の座標は全体がちょうど収まる表示状態で計算する
+        let mut minimap = self.clone();
+        minimap.current_scale = scale;
+        minimap.center_on(extent.center().to_vec2(), rect.size());
+
+        let response = ui.interact(rect, self.id.with("minimap"), Sense::click_and_drag());
+        let mut painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+        for render in drawn {
+            if matches!(
+                render.as_ref(),
+                DataRender::Image(_) | DataRender::Tensor2D(_)
+            ) {
+                if let Err(e) = render.render(ui, bag, &mut painter, &minimap) {
+                    log::debug!("failed to render minimap: {:#}", e);
+                }
+            }
+        }
+
+        let viewport = Rect::from_two_pos(
+            rect.min + minimap.absolute_to_screen(self.screen_to_absolute(Vec2::ZERO)),
+            rect.min + minimap.absolute_to_screen(self.screen_to_absolute(view_rect.size())),
+        );
+        painter.rect_stroke(
+            viewport,
+            0.0,
+            Stroke::new(1.5, Color32::YELLOW),
+            StrokeKind::Middle,
+        );
+        painter.rect_stroke(rect, 0.0, ui.visuals().window_stroke(), StrokeKind::Inside);
+
+        if response.is_pointer_button_down_on() {
+            if let Some(pos) = response.interact_pointer_pos() {
+                let center = minimap.screen_to_absolute(pos - rect.min);
+                self.center_on(center, view_rect.size());
+            }
+        }
+    }
+}
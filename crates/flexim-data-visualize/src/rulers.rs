@@ -0,0 +1,138 @@
+use crate::visualize::VisualizeState;
+use egui::{Align2, Color32, FontId, Pos2, Rect, Stroke, Ui, Vec2};
+use flexim_config::Config;
+
+/// 定規の幅
+const RULER_SIZE: f32 = 18.0;
+/// 目盛りの数字の間隔の最小値 (画面座標)
+const MIN_LABEL_SPACING: f32 = 60.0;
+/// これより多くの線が必要な場合はグリッドを描かない
+const MAX_GRID_LINES: f32 = 1000.0;
+
+/// `min_step` 以上で 1, 2, 5 × 10^n の間隔
+fn nice_step(min_step: f32) -> f32 {
+    let magnitude = 10f32.powf(min_step.log10().floor());
+    [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|m| m * magnitude)
+        .find(|step| *step >= min_step)
+        .unwrap_or(10.0 * magnitude)
+}
+
+/// `min..=max` に含まれる `step` の倍数 `i * step` の組 `(i, i * step)`
+fn multiples(min: f32, max: f32, step: f32) -> impl Iterator<Item = (i64, f32)> {
+    let start = (min / step).ceil() as i64;
+    let end = (max / step).floor() as i64;
+    (start..=end).map(move |i| (i, i as f32 * step))
+}
+
+fn format_tick(value: f32, step: f32) -> String {
+    if step >= 1.0 {
+        format!("{}", value.round())
+    } else {
+        let digits = (-step.log10().floor()) as usize;
+        format!("{:.*}", digits, value)
+    }
+}
+
+impl VisualizeState {
+    /// 大きさ `view_size` の描画領域に表示されている絶対座標の範囲 (x, y)
+    fn visible_range(&self, view_size: Vec2) -> ((f32, f32), (f32, f32)) {
+        let a = self.screen_to_absolute(Vec2::ZERO);
+        let b = self.screen_to_absolute(view_size);
+        ((a.x.min(b.x), a.x.max(b.x)), (a.y.min(b.y), a.y.max(b.y)))
+    }
+
+    /// 上と左に絶対座標の定規を描く
+    pub(crate) fn draw_rulers(&self, ui: &Ui, rect: Rect) {
+        let painter = ui.painter_at(rect);
+        let visuals = ui.visuals();
+        let background = visuals.extreme_bg_color.gamma_multiply(0.9);
+        let stroke = Stroke::new(1.0, visuals.text_color());
+        let font = FontId::monospace(9.0);
+
+        let top = Rect::from_min_max(rect.min, Pos2::new(rect.max.x, rect.min.y + RULER_SIZE));
+        let left = Rect::from_min_max(rect.min, Pos2::new(rect.min.x + RULER_SIZE, rect.max.y));
+        painter.rect_filled(top, 0.0, background);
+        painter.rect_filled(left, 0.0, background);
+
+        let step = nice_step(MIN_LABEL_SPACING / self.current_scale);
+        let minor = step / 5.0;
+        let ((x_min, x_max), (y_min, y_max)) = self.visible_range(rect.size());
+
+        for (i, x) in multiples(x_min, x_max, minor) {
+            let sx = rect.min.x + self.absolute_to_screen(Vec2::new(x, 0.0)).x;
+            if sx < left.max.x {
+                continue;
+            }
+            let major = i % 5 == 0;
+            let length = if major { RULER_SIZE } else { RULER_SIZE / 3.0 };
+            painter.vline(sx, (top.max.y - length)..=top.max.y, stroke);
+            if major {
+                painter.text(
+                    Pos2::new(sx + 2.0, top.min.y),
+                    Align2::LEFT_TOP,
+                    format_tick(x, step),
+                    font.clone(),
+                    visuals.text_color(),
+                );
+            }
+        }
+        for (i, y) in multiples(y_min, y_max, minor) {
+            let sy = rect.min.y + self.absolute_to_screen(Vec2::new(0.0, y)).y;
+            if sy < top.max.y {
+                continue;
+            }
+            let major = i % 5 == 0;
+            let length = if major { RULER_SIZE } else { RULER_SIZE / 3.0 };
+            painter.hline((left.max.x - length)..=left.max.x, sy, stroke);
+            if major {
+                painter.text(
+                    Pos2::new(left.min.x + 1.0, sy + 2.0),
+                    Align2::LEFT_TOP,
+                    format_tick(y, step),
+                    font.clone(),
+                    visuals.text_color(),
+                );
+            }
+        }
+    }
+
+    /// 拡大率が設定を超えている場合に `Config::pixel_grid_spacing` ごとの格子を描く
+    pub(crate) fn draw_pixel_grid(&self, ui: &Ui, rect: Rect, config: &Config) {
+        let spacing = config.pixel_grid_spacing;
+        if self.current_scale < config.pixel_grid_min_scale || spacing <= 0.0 {
+            return;
+        }
+        let ((x_min, x_max), (y_min, y_max)) = self.visible_range(rect.size());
+        if (x_max - x_min) / spacing + (y_max - y_min) / spacing > MAX_GRID_LINES {
+            return;
+        }
+        let painter = ui.painter_at(rect);
+        let stroke = Stroke::new(1.0, Color32::from_white_alpha(40));
+        for (_, x) in multiples(x_min, x_max, spacing) {
+            let sx = rect.min.x + self.absolute_to_screen(Vec2::new(x, 0.0)).x;
+            painter.vline(sx, rect.y_range(), stroke);
+        }
+        for (_, y) in multiples(y_min, y_max, spacing) {
+            let sy = rect.min.y + self.absolute_to_screen(Vec2::new(0.0, y)).y;
+            painter.hline(rect.x_range(), sy, stroke);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nice_step_rounds_up_to_1_2_5() {
+        for (min_step, expected) in [(0.3, 0.5), (1.0, 1.0), (13.0, 20.0), (60.0, 100.0)] {
+            assert!((nice_step(min_step) - expected).abs() < 1e-4);
+        }
+        assert_eq!(
+            multiples(-2.5, 7.0, 5.0).collect::<Vec<_>>(),
+            vec![(0, 0.0), (1, 5.0)]
+        );
+    }
+}
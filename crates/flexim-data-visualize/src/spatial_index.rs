@@ -0,0 +1,152 @@
+use egui::{Pos2, Rect, Vec2};
+
+/// 一辺あたりの格子の最大数
+const MAX_CELLS_PER_AXIS: usize = 512;
+/// これより多くの格子にまたがる図形は格子に登録せず、毎回の検索で直接調べる
+const MAX_CELLS_PER_ITEM: usize = 64;
+
+/// 図形を囲む矩形を一様な格子に登録した索引
+#[derive(Debug)]
+pub(crate) struct GridIndex {
+    bounds: Rect,
+    cell_size: Vec2,
+    columns: usize,
+    rows: usize,
+    cells: Vec<Vec<u32>>,
+    /// 大きすぎて格子に登録しなかった要素
+    oversized: Vec<u32>,
+    items: Vec<Option<Rect>>,
+}
+
+impl GridIndex {
+    /// `items[i]` が `None` の要素は登録しない
+    pub(crate) fn build(items: Vec<Option<Rect>>) -> Self {
+        let bounds = items
+            .iter()
+            .flatten()
+            .copied()
+            .reduce(|a, b| a.union(b))
+            .unwrap_or(Rect::NOTHING);
+        // 1 つの格子に平均 1 個程度の図形が入るようにする
+        let side = (items.len() as f64).sqrt().ceil() as usize;
+        let side = side.clamp(1, MAX_CELLS_PER_AXIS);
+        let mut index = Self {
+            bounds,
+            cell_size: (bounds.size() / side as f32).max(Vec2::splat(f32::EPSILON)),
+            columns: side,
+            rows: side,
+            cells: vec![vec![]; side * side],
+            oversized: vec![],
+            items: vec![],
+        };
+        for (i, rect) in items.iter().enumerate() {
+            let Some(rect) = rect else {
+                continue;
+            };
+            let (xs, ys) = index.cell_range(*rect);
+            if xs.clone().count() * ys.clone().count() > MAX_CELLS_PER_ITEM {
+                index.oversized.push(i as u32);
+                continue;
+            }
+            for y in ys {
+                for x in xs.clone() {
+                    index.cells[y * index.columns + x].push(i as u32);
+                }
+            }
+        }
+        index.items = items;
+        index
+    }
+
+    fn cell_range(
+        &self,
+        rect: Rect,
+    ) -> (
+        std::ops::RangeInclusive<usize>,
+        std::ops::RangeInclusive<usize>,
+    ) {
+        let cell = |pos: Pos2| {
+            let cell = (pos - self.bounds.min) / self.cell_size;
+            (
+                (cell.x.max(0.0) as usize).min(self.columns - 1),
+                (cell.y.max(0.0) as usize).min(self.rows - 1),
+            )
+        };
+        let (x1, y1) = cell(rect.min);
+        let (x2, y2) = cell(rect.max);
+        (x1..=x2, y1..=y2)
+    }
+
+    /// `rect` と重なる要素の番号を昇順で返す
+    pub(crate) fn query(&self, rect: Rect) -> Vec<usize> {
+        if !self.bounds.intersects(rect) {
+            return vec![];
+        }
+        let (xs, ys) = self.cell_range(rect);
+        let mut found = ys
+            .flat_map(|y| xs.clone().map(move |x| y * self.columns + x))
+            .flat_map(|cell| self.cells[cell].iter())
+            .chain(&self.oversized)
+            .map(|i| *i as usize)
+            .filter(|i| self.items[*i].is_some_and(|item| item.intersects(rect)))
+            .collect::<Vec<_>>();
+        found.sort_unstable();
+        found.dedup();
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_returns_overlapping_items_in_order() {
+        let items = (0..100)
+            .map(|i| {
+                let min = Pos2::new((i % 10) as f32 * 10.0, (i / 10) as f32 * 10.0);
+                (i != 55).then(|| Rect::from_min_size(min, Vec2::splat(5.0)))
+            })
+            .collect::<Vec<_>>();
+        let index = GridIndex::build(items);
+
+        let found = index.query(Rect::from_min_max(
+            Pos2::new(42.0, 42.0),
+            Pos2::new(62.0, 52.0),
+        ));
+        assert_eq!(found, vec![44, 45, 46, 54, 56]);
+        assert!(index
+            .query(Rect::from_min_size(
+                Pos2::new(200.0, 0.0),
+                Vec2::splat(10.0)
+            ))
+            .is_empty());
+        assert_eq!(index.query(Rect::EVERYTHING).len(), 99);
+    }
+
+    #[test]
+    fn large_items_are_not_registered_to_every_cell() {
+        let mut items = (0..10_000)
+            .map(|i| {
+                let min = Pos2::new((i % 100) as f32 * 10.0, (i / 100) as f32 * 10.0);
+                Some(Rect::from_min_size(min, Vec2::splat(5.0)))
+            })
+            .collect::<Vec<_>>();
+        items.push(Some(Rect::from_min_max(
+            Pos2::ZERO,
+            Pos2::new(1000.0, 1000.0),
+        )));
+        let index = GridIndex::build(items);
+
+        assert_eq!(index.oversized, vec![10_000]);
+        assert!(!index.cells.iter().flatten().any(|i| *i == 10_000));
+        assert_eq!(
+            index.query(Rect::from_min_size(
+                Pos2::new(502.0, 502.0),
+                Vec2::splat(1.0)
+            )),
+            vec![5050, 10_000]
+        );
+        assert_eq!(index.query(Rect::EVERYTHING).len(), 10_001);
+    }
+}
@@ -1,11 +1,13 @@
+use crate::transform::transform_pos;
 use crate::visualize::VisualizeState;
 use egui::epaint::{PathShape, StrokeKind};
 use egui::{
-    Align2, Color32, FontId, Painter, Pos2, Rangef, Rect, Response, Sense, Shape, Stroke, Ui, Vec2,
+    Align2, Color32, FontId, Painter, Pos2, Rect, Response, Sense, Shape, Stroke, Ui, Vec2,
 };
 use enum_iterator::Sequence;
-use flexim_data_type::{FlDataFrameRectangle, FlDataFrameSegment};
+use flexim_data_type::{FlAffineTransform, FlDataFrameRectangle, FlDataFrameSegment};
 use geo::Line;
+use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use std::fmt::{Debug, Display, Formatter};
 
@@ -44,6 +46,9 @@ pub struct RenderParameter {
     pub edge_accent_start: EdgeAccent,
     pub edge_accent_end: EdgeAccent,
     pub label: Option<String>,
+    pub transform: FlAffineTransform,
+    /// ポインタの近くにある図形だけ当たり判定の領域を確保する
+    pub interactive: bool,
 }
 
 impl SpecialColumnShape for FlDataFrameRectangle {
@@ -59,74 +64,71 @@ impl SpecialColumnShape for FlDataFrameRectangle {
             stroke_thickness: thickness,
             label,
             fill_color,
+            transform,
+            interactive,
             ..
         } = parameter;
 
-        let rect = Rect::from_two_pos(
-            painter.clip_rect().min
-                + state.absolute_to_screen(Vec2::new(self.x1 as f32, self.y1 as f32)),
+        let corners = [
+            (self.x1, self.y1),
+            (self.x2, self.y1),
+            (self.x2, self.y2),
+            (self.x1, self.y2),
+        ]
+        .map(|(x, y)| {
             painter.clip_rect().min
-                + state.absolute_to_screen(Vec2::new(self.x2 as f32, self.y2 as f32)),
-        );
-        if let Some(fill_color) = fill_color {
-            painter.rect_filled(rect, 0.0, fill_color);
+                + state.absolute_to_screen(transform_pos(&transform, Vec2::new(x as f32, y as f32)))
+        });
+        let rect = Rect::from_points(&corners);
+        if transform.is_axis_aligned() {
+            if let Some(fill_color) = fill_color {
+                painter.rect_filled(rect, 0.0, fill_color);
+            }
+            painter.rect_stroke(
+                rect,
+                0.0,
+                Stroke::new(thickness, color),
+                StrokeKind::Outside,
+            );
+        } else {
+            // 回転している場合は四角形として描く
+            painter.add(Shape::convex_polygon(
+                corners.to_vec(),
+                fill_color.unwrap_or(Color32::TRANSPARENT),
+                Stroke::new(thickness, color),
+            ));
         }
-        painter.rect_stroke(rect, 0.0, Stroke::new(thickness, color), StrokeKind::Outside);
 
-        let is_command = ui.input(|input| input.modifiers.command_only());
-        let sense = if is_command {
+        if !interactive {
+            if let Some(label) = label {
+                draw_label(painter, rect.left_top(), Align2::LEFT_BOTTOM, &label, color);
+            }
+            return None;
+        }
+
+        let sense = if state.inspecting {
             Sense::hover()
         } else {
             Sense::click()
         };
 
-        let mut responses = vec![
-            ui.allocate_rect(
-                Rect::from_x_y_ranges(
-                    rect.x_range().expand(thickness),
-                    Rangef::point(rect.top()).expand(thickness),
-                ),
-                sense,
-            ),
-            ui.allocate_rect(
-                Rect::from_x_y_ranges(
-                    rect.x_range().expand(thickness),
-                    Rangef::point(rect.bottom()).expand(thickness),
-                ),
-                sense,
-            ),
-            ui.allocate_rect(
-                Rect::from_x_y_ranges(
-                    Rangef::point(rect.left()).expand(thickness),
-                    rect.y_range().expand(thickness),
-                ),
-                sense,
-            ),
-            ui.allocate_rect(
-                Rect::from_x_y_ranges(
-                    Rangef::point(rect.right()).expand(thickness),
-                    rect.y_range().expand(thickness),
-                ),
-                sense,
-            ),
-        ];
+        // 各辺を囲む矩形で当たり判定をとる
+        let mut responses = (0..corners.len())
+            .map(|i| {
+                let edge = Rect::from_two_pos(corners[i], corners[(i + 1) % corners.len()]);
+                ui.allocate_rect(
+                    Rect::from_x_y_ranges(
+                        edge.x_range().expand(thickness),
+                        edge.y_range().expand(thickness),
+                    ),
+                    sense,
+                )
+            })
+            .collect_vec();
 
         if let Some(label) = label {
-            let text_rect = painter.text(
-                rect.left_top(),
-                Align2::LEFT_BOTTOM,
-                label.as_str(),
-                FontId::default(),
-                Color32::BLACK,
-            );
-            painter.rect_filled(text_rect, 0.0, color);
-            let text_rect = painter.text(
-                rect.left_top(),
-                Align2::LEFT_BOTTOM,
-                label.as_str(),
-                FontId::default(),
-                Color32::BLACK,
-            );
+            let text_rect =
+                draw_label(painter, rect.left_top(), Align2::LEFT_BOTTOM, &label, color);
             responses.push(ui.allocate_rect(text_rect, Sense::click()));
         }
 
@@ -159,28 +161,34 @@ impl SpecialColumnShape for FlDataFrameSegment {
             label,
             edge_accent_start,
             edge_accent_end,
+            transform,
+            interactive,
             ..
         } = parameter;
 
-        let mut segment_p1 = (Vec2::new(self.x1 as f32, self.y1 as f32) * state.scale()).to_pos2()
-            + state.shift
-            + painter.clip_rect().min.to_vec2();
-        let mut segment_p2 = (Vec2::new(self.x2 as f32, self.y2 as f32) * state.scale()).to_pos2()
-            + state.shift
-            + painter.clip_rect().min.to_vec2();
+        let to_screen = |x: f64, y: f64| {
+            painter.clip_rect().min
+                + state.absolute_to_screen(transform_pos(&transform, Vec2::new(x as f32, y as f32)))
+        };
+        let mut segment_p1 = to_screen(self.x1, self.y1);
+        let mut segment_p2 = to_screen(self.x2, self.y2);
         let center = (segment_p1 + segment_p2.to_vec2()) / 2.0;
 
         let rectangle = Rect::from_min_max(segment_p1, segment_p2);
-        let response = if rectangle.width() < 1.0 || rectangle.height() < 1.0 {
-            ui.allocate_rect(rectangle, Sense::click())
+        let response = if !interactive {
+            None
+        } else if rectangle.width() < 1.0 || rectangle.height() < 1.0 {
+            Some(ui.allocate_rect(rectangle, Sense::click()))
         } else {
             let p1_rectangle = Rect::from_center_size(segment_p1, Vec2::splat(1.0));
             let p2_rectangle = Rect::from_center_size(segment_p2, Vec2::splat(1.0));
             let center_rectangle = Rect::from_center_size(center, Vec2::splat(1.0));
 
-            ui.allocate_rect(p1_rectangle, Sense::click())
-                .union(ui.allocate_rect(p2_rectangle, Sense::click()))
-                .union(ui.allocate_rect(center_rectangle, Sense::click()))
+            Some(
+                ui.allocate_rect(p1_rectangle, Sense::click())
+                    .union(ui.allocate_rect(p2_rectangle, Sense::click()))
+                    .union(ui.allocate_rect(center_rectangle, Sense::click())),
+            )
         };
 
         match edge_accent_start {
@@ -204,28 +212,14 @@ impl SpecialColumnShape for FlDataFrameSegment {
 
         painter.line_segment([segment_p1, segment_p2], Stroke::new(thickness, color));
 
-        let response = if let Some(label) = label {
-            let text_rect = painter.text(
-                center,
-                Align2::CENTER_CENTER,
-                label.as_str(),
-                FontId::default(),
-                Color32::BLACK,
-            );
-            painter.rect_filled(text_rect, 0.0, color);
-            let text_rect = painter.text(
-                center,
-                Align2::CENTER_CENTER,
-                label.as_str(),
-                FontId::default(),
-                Color32::BLACK,
-            );
-            response | (ui.allocate_rect(text_rect, Sense::click()))
-        } else {
-            response
-        };
+        if let Some(label) = label {
+            let text_rect = draw_label(painter, center, Align2::CENTER_CENTER, &label, color);
+            if let Some(response) = response {
+                return Some(response | ui.allocate_rect(text_rect, Sense::click()));
+            }
+        }
 
-        Some(response)
+        response
     }
 
     fn measure_segments(&self) -> Vec<Line> {
@@ -233,6 +227,13 @@ impl SpecialColumnShape for FlDataFrameSegment {
     }
 }
 
+/// 背景を塗ったラベルを描き、その範囲を返す
+fn draw_label(painter: &Painter, pos: Pos2, align: Align2, label: &str, color: Color32) -> Rect {
+    let text_rect = painter.text(pos, align, label, FontId::default(), Color32::BLACK);
+    painter.rect_filled(text_rect, 0.0, color);
+    painter.text(pos, align, label, FontId::default(), Color32::BLACK)
+}
+
 fn arrow_head_shape(
     point: Pos2,
     back_vector: Vec2,
@@ -0,0 +1,233 @@
+use crate::cache::{Poll, TileKey, TiledImageCache};
+use crate::transform::{inverse_transform_pos, transform_pos};
+use crate::visualize::{draw_message, draw_texture, VisualizeState};
+use egui::{Color32, ColorImage, Id, Painter, TextureOptions, Vec2};
+use flexim_data_type::FlAffineTransform;
+use image::imageops::FilterType;
+use image::RgbaImage;
+use std::ops::RangeInclusive;
+
+/// タイルの一辺の大きさ (ピクセル)
+const TILE_SIZE: u32 = 256;
+/// 縦横のどちらかがこれより大きい場合はタイルに分けて描画する
+pub(crate) const TILED_THRESHOLD: usize = 2048;
+/// 1 フレームで GPU に転送するタイルの最大数
+const MAX_UPLOADS_PER_FRAME: usize = 8;
+
+/// 縦横を半分ずつにした画像の列。最後の画像は 1 枚のタイルに収まる
+pub struct ImagePyramid {
+    levels: Vec<RgbaImage>,
+}
+
+impl ImagePyramid {
+    pub fn build(base: RgbaImage) -> Self {
+        let mut levels = vec![base];
+        loop {
+            let last = levels.last().unwrap();
+            if last.width().max(last.height()) <= TILE_SIZE {
+                break;
+            }
+            let next = image::imageops::resize(
+                last,
+                (last.width() / 2).max(1),
+                (last.height() / 2).max(1),
+                FilterType::Triangle,
+            );
+            levels.push(next);
+        }
+        Self { levels }
+    }
+
+    fn tile_image(&self, level: usize, x: u32, y: u32) -> ColorImage {
+        let image = &self.levels[level];
+        let (x0, y0) = (x * TILE_SIZE, y * TILE_SIZE);
+        let width = TILE_SIZE.min(image.width() - x0);
+        let height = TILE_SIZE.min(image.height() - y0);
+        let tile = image::imageops::crop_imm(image, x0, y0, width, height).to_image();
+        ColorImage::from_rgba_unmultiplied([width as usize, height as usize], tile.as_raw())
+    }
+}
+
+/// 画面上の 1 ピクセルに元画像の `pixels_per_point` ピクセルが入る時に使う段
+fn level_for_scale(pixels_per_point: f32, levels: usize) -> usize {
+    if pixels_per_point <= 1.0 || !pixels_per_point.is_finite() {
+        return 0;
+    }
+    (pixels_per_point.log2().floor() as usize).min(levels - 1)
+}
+
+/// `min..max` (その段のピクセル座標) に重なるタイルの番号
+fn tile_range(min: f32, max: f32, length: u32) -> Option<RangeInclusive<u32>> {
+    if max <= 0.0 || min >= length as f32 {
+        return None;
+    }
+    let last = (length - 1) / TILE_SIZE;
+    let start = (min.max(0.0) as u32 / TILE_SIZE).min(last);
+    let end = (max.max(0.0) as u32 / TILE_SIZE).min(last);
+    Some(start..=end)
+}
+
+/// 大きな画像を現在の拡大率に合った解像度のタイルに分けて、表示されている部分だけを描画する
+/// `source` は初回だけ別スレッドで呼ばれ、結果は `TiledImageCache` に保持される
+/// `source` が失敗した場合はその理由を表示する
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn draw_tiled(
+    painter: &mut Painter,
+    key: Id,
+    source: impl FnOnce() -> anyhow::Result<RgbaImage> + Send + 'static,
+    state: &VisualizeState,
+    offset: Vec2,
+    size: Vec2,
+    transform: &FlAffineTransform,
+    tint_color: Color32,
+) {
+    let ctx = painter.ctx().clone();
+    let pyramid = ctx.memory_mut(|mem| {
+        let cache = mem.caches.cache::<TiledImageCache>();
+        match cache.pyramid(key) {
+            Some(Poll::Ready(pyramid)) => Ok(Some(pyramid)),
+            Some(Poll::Pending) => Ok(None),
+            Some(Poll::Failed(error)) => Err(error),
+            None => {
+                cache.insert_pending(key);
+                let ctx = ctx.clone();
+                std::thread::spawn(move || match source() {
+                    Ok(image) => {
+                        let pyramid = ImagePyramid::build(image);
+                        ctx.memory_mut(|mem| {
+                            mem.caches
+                                .cache::<TiledImageCache>()
+                                .insert_pyramid(key, pyramid)
+                        });
+                        ctx.request_repaint();
+                    }
+                    Err(e) => {
+                        log::error!("failed to build image pyramid: {:#}", e);
+                        ctx.memory_mut(|mem| {
+                            mem.caches
+                                .cache::<TiledImageCache>()
+                                .insert_failed(key, format!("{:#}", e))
+                        });
+                        ctx.request_repaint();
+                    }
+                });
+                Ok(None)
+            }
+        }
+    });
+    let pyramid = match pyramid {
+        Ok(Some(pyramid)) => pyramid,
+        Ok(None) => return,
+        Err(error) => {
+            draw_message(painter, &format!("Failed to load image: {}", error));
+            return;
+        }
+    };
+
+    // 画像の 1 ピクセルが画面上で何ピクセルになるか
+    let origin = transform_pos(transform, offset);
+    let point_per_pixel = [Vec2::X, Vec2::Y]
+        .into_iter()
+        .map(|axis| (transform_pos(transform, offset + axis) - origin).length())
+        .fold(0.0f32, f32::max)
+        * state.current_scale;
+    let level = level_for_scale(1.0 / point_per_pixel, pyramid.levels.len());
+
+    // 表示されている範囲を画像のピクセル座標に戻す
+    let view_size = painter.clip_rect().size();
+    let corners = [
+        Vec2::ZERO,
+        Vec2::new(view_size.x, 0.0),
+        view_size,
+        Vec2::new(0.0, view_size.y),
+    ]
+    .map(|corner| inverse_transform_pos(transform, state.screen_to_absolute(corner)) - offset);
+    let min = corners.iter().copied().reduce(Vec2::min).unwrap();
+    let max = corners.iter().copied().reduce(Vec2::max).unwrap();
+
+    let mut uploads = 0;
+    let mut draw_level = |level: usize, uploads: &mut usize| {
+        let image = &pyramid.levels[level];
+        let pixel = size / Vec2::new(image.width() as f32, image.height() as f32);
+        let (Some(xs), Some(ys)) = (
+            tile_range(min.x / pixel.x, max.x / pixel.x, image.width()),
+            tile_range(min.y / pixel.y, max.y / pixel.y, image.height()),
+        ) else {
+            return;
+        };
+        for y in ys {
+            for x in xs.clone() {
+                let key = TileKey { key, level, x, y };
+                let cached = ctx.memory_mut(|mem| mem.caches.cache::<TiledImageCache>().tile(key));
+                let texture = match cached {
+                    Some(texture) => texture,
+                    None if *uploads < MAX_UPLOADS_PER_FRAME => {
+                        *uploads += 1;
+                        let handle = ctx.load_texture(
+                            format!("tile {:?} {} {} {}", key.key, level, x, y),
+                            pyramid.tile_image(level, x, y),
+                            TextureOptions::default(),
+                        );
+                        let id = handle.id();
+                        ctx.memory_mut(|mem| {
+                            mem.caches
+                                .cache::<TiledImageCache>()
+                                .insert_tile(key, handle)
+                        });
+                        id
+                    }
+                    None => {
+                        ctx.request_repaint();
+                        continue;
+                    }
+                };
+                let tile_min = Vec2::new((x * TILE_SIZE) as f32, (y * TILE_SIZE) as f32);
+                let tile_max = Vec2::new(
+                    ((x + 1) * TILE_SIZE).min(image.width()) as f32,
+                    ((y + 1) * TILE_SIZE).min(image.height()) as f32,
+                );
+                draw_texture(
+                    painter,
+                    texture,
+                    state,
+                    offset + tile_min * pixel,
+                    (tile_max - tile_min) * pixel,
+                    transform,
+                    tint_color,
+                );
+            }
+        }
+    };
+
+    // 細かい段のタイルが揃うまでは最も粗い段で埋めておく
+    let coarsest = pyramid.levels.len() - 1;
+    if level != coarsest {
+        draw_level(coarsest, &mut uploads);
+    }
+    draw_level(level, &mut uploads);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pyramid_levels_and_visible_tiles() {
+        let pyramid = ImagePyramid::build(RgbaImage::new(1000, 300));
+        let sizes = pyramid
+            .levels
+            .iter()
+            .map(|level| level.dimensions())
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, vec![(1000, 300), (500, 150), (250, 75)]);
+
+        assert_eq!(level_for_scale(0.5, 3), 0);
+        assert_eq!(level_for_scale(2.5, 3), 1);
+        assert_eq!(level_for_scale(100.0, 3), 2);
+
+        assert_eq!(tile_range(-10.0, 300.0, 1000), Some(0..=1));
+        assert_eq!(tile_range(700.0, 5000.0, 1000), Some(2..=3));
+        assert_eq!(tile_range(1000.0, 1200.0, 1000), None);
+        assert_eq!(pyramid.tile_image(0, 3, 1).size, [1000 - 768, 300 - 256]);
+    }
+}
@@ -0,0 +1,81 @@
+use egui::{CollapsingHeader, DragValue, Grid, Ui, Vec2};
+use flexim_data_type::{FlAffineTransform, FlDataReference};
+use flexim_storage::Bag;
+use geo::{coord, Line};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+
+/// レンダーごとのアフィン変換
+/// 上書きしていない場合はアップロード時にデータに設定された変換を使う
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RenderTransform(Arc<Mutex<Option<FlAffineTransform>>>);
+
+impl RenderTransform {
+    pub fn resolve(&self, bag: &Bag, reference: &FlDataReference) -> FlAffineTransform {
+        if let Some(transform) = *self.0.lock().unwrap() {
+            return transform;
+        }
+        bag.transform_by_reference(reference)
+            .ok()
+            .flatten()
+            .unwrap_or_default()
+    }
+
+    pub fn config_panel(&self, ui: &mut Ui, bag: &Bag, reference: &FlDataReference) {
+        CollapsingHeader::new("Transform").show(ui, |ui| {
+            let data_transform = bag
+                .transform_by_reference(reference)
+                .ok()
+                .flatten()
+                .unwrap_or_default();
+            let mut transform = self.0.lock().unwrap();
+            let mut overridden = transform.is_some();
+            ui.checkbox(&mut overridden, "Override");
+            let mut value = transform.unwrap_or(data_transform);
+            ui.add_enabled_ui(overridden, |ui| {
+                Grid::new("transform").num_columns(2).show(ui, |ui| {
+                    ui.label("Offset");
+                    ui.horizontal(|ui| {
+                        ui.add(DragValue::new(&mut value.offset_x).prefix("x: "));
+                        ui.add(DragValue::new(&mut value.offset_y).prefix("y: "));
+                    });
+                    ui.end_row();
+                    ui.label("Scale");
+                    ui.horizontal(|ui| {
+                        for scale in [&mut value.scale_x, &mut value.scale_y] {
+                            ui.add(DragValue::new(scale).range(0.001..=1000.0).speed(0.01));
+                        }
+                    });
+                    ui.end_row();
+                    ui.label("Rotation");
+                    ui.add(
+                        DragValue::new(&mut value.rotation)
+                            .range(-180.0..=180.0)
+                            .suffix("°"),
+                    );
+                    ui.end_row();
+                });
+                if ui.button("Reset").clicked() {
+                    value = FlAffineTransform::default();
+                }
+            });
+            *transform = overridden.then_some(value);
+        });
+    }
+}
+
+pub fn transform_pos(transform: &FlAffineTransform, pos: Vec2) -> Vec2 {
+    let (x, y) = transform.apply(pos.x as f64, pos.y as f64);
+    Vec2::new(x as f32, y as f32)
+}
+
+pub fn inverse_transform_pos(transform: &FlAffineTransform, pos: Vec2) -> Vec2 {
+    let (x, y) = transform.apply_inverse(pos.x as f64, pos.y as f64);
+    Vec2::new(x as f32, y as f32)
+}
+
+pub fn transform_line(transform: &FlAffineTransform, line: &Line) -> Line {
+    let (x1, y1) = transform.apply(line.start.x, line.start.y);
+    let (x2, y2) = transform.apply(line.end.x, line.end.y);
+    Line::new(coord!(x: x1, y: y1), coord!(x: x2, y: y2))
+}
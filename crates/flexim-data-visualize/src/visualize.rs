@@ -1,36 +1,47 @@
-use crate::cache::{Poll, VisualizedImageCache};
+use crate::annotation::{edit_annotations, geometries, AnnotationEditState};
+use crate::cache::{GeometryCache, Poll, VisualizedImageCache};
+use crate::compare::StackCompareState;
+use crate::density::{draw_density, LevelOfDetail};
+use crate::layer::{drawn_layers, LayerSettings};
+use crate::measurement::{from_line, measure_start_id, to_point, Measurement, Measurements};
+use crate::tiles::{draw_tiled, TILED_THRESHOLD};
+use crate::transform::{inverse_transform_pos, transform_line, transform_pos, RenderTransform};
+use crate::zoom::{request_zoom, segments_bounds, take_zoom_request, ZoomRequest};
 
 use std::io::Cursor;
 use std::ops::Deref;
 
 use egui::{
     Align, Align2, Button, CollapsingHeader, Color32, ComboBox, Context, DragValue, FontId, Id,
-    Image, Layout, Painter, PointerButton, Pos2, Rect, Response, Sense, Shape, Slider, Stroke, Ui,
-    Vec2, Widget,
+    Image, Key, Layout, Mesh, Painter, Pos2, Rect, Response, Sense, Shape, Slider, Stroke,
+    TextureId, Ui, Vec2, Widget,
 };
 
 use flexim_data_type::{
-    FlData, FlDataFrameColor, FlDataFrameRectangle, FlDataFrameSegment, FlDataFrameSpecialColumn,
-    FlDataReference, FlImage, FlShapeConvertError,
+    FlAffineTransform, FlData, FlDataFrameColor, FlDataFrameRectangle, FlDataFrameSegment,
+    FlDataFrameSpecialColumn, FlDataReference, FlImage, FlShapeConvertError,
 };
 use flexim_data_view::FlDataFrameView;
-use image::{DynamicImage, ImageBuffer, Rgb};
+use image::{DynamicImage, ImageBuffer, RgbImage};
 use itertools::Itertools;
+use ndarray::Array2;
 
+use crate::geometry::{GeometryBuffers, StyleBuffers, StyleColumns};
 use crate::pallet::pallet;
 use crate::special_columns_visualize::{EdgeAccent, RenderParameter, SpecialColumnShape};
 use anyhow::Context as _;
 
 use egui::load::TexturePoll;
-use flexim_table_widget::cache::DataFramePoll;
+use flexim_table_widget::FlTableState;
 
 use enum_iterator::all;
+use flexim_config::keymap::{is_capturing, KeyAction};
 use flexim_config::Config;
 use flexim_storage::Bag;
 use flexim_utility::left_and_right_layout;
 use geo::{coord, Closest, ClosestPoint, Coord, EuclideanDistance, Line, Vector2DOps};
 use polars::datatypes::DataType;
-use polars::prelude::{AnyValue, Field};
+use polars::prelude::{AnyValue, DataFrame, Field, Series};
 use scarlet::color::RGBColor;
 use scarlet::colormap::ColorMap;
 use serde::{Deserialize, Serialize};
@@ -38,6 +49,12 @@ use std::sync::{Arc, Mutex};
 use unwrap_ord::UnwrapOrd;
 
 const PSEUDO_INFINITE: f32 = 100000.0;
+/// 表示範囲の外でも線の太さやラベルが見える図形を描くための余白 (画面座標)
+const CULL_MARGIN: f32 = 64.0;
+/// 図形の当たり判定をとるポインタからの距離 (画面座標)
+const HIT_MARGIN: f32 = 4.0;
+/// ラベルを表示している時に当たり判定をとる範囲 (画面座標)
+const LABEL_REACH: Vec2 = Vec2::new(200.0, 24.0);
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VisualizeState {
@@ -45,6 +62,46 @@ pub struct VisualizeState {
     pub current_scale: f32,
     pub shift: Vec2,
     pub origin: Origin,
+    /// 同じグループのペインと拡大率と位置を共有する
+    #[serde(default)]
+    pub link_group: Option<u8>,
+    #[serde(default)]
+    pub show_rulers: bool,
+    #[serde(default)]
+    pub show_pixel_grid: bool,
+    #[serde(default)]
+    pub show_minimap: bool,
+    /// 表示しているペインの固定した計測結果
+    #[serde(skip)]
+    pub measurements: Measurements,
+    /// このフレームでインスペクションを行うか
+    #[serde(skip)]
+    pub inspecting: bool,
+}
+
+/// インスペクションが有効か
+/// 修飾キーに割り当てた場合は押している間、キーに割り当てた場合は押すたびに切り替える
+fn inspection_enabled(ctx: &Context) -> bool {
+    let keymap = Config::get_global_ctx(ctx).keymap;
+    let capturing = is_capturing(ctx);
+    if !capturing && ctx.input(|input| keymap.held(input, KeyAction::ToggleInspection)) {
+        return true;
+    }
+    let id = Id::new("inspection toggle");
+    let pass = ctx.cumulative_pass_nr();
+    let (last_pass, mut enabled) = ctx
+        .data(|data| data.get_temp::<(u64, bool)>(id))
+        .unwrap_or_default();
+    if last_pass != pass {
+        if !ctx.wants_keyboard_input()
+            && !capturing
+            && ctx.input(|input| keymap.pressed(input, KeyAction::ToggleInspection))
+        {
+            enabled = !enabled;
+        }
+        ctx.data_mut(|data| data.insert_temp(id, (pass, enabled)));
+    }
+    enabled
 }
 
 impl VisualizeState {
@@ -68,9 +125,21 @@ impl VisualizeState {
         let pos = pos - self.shift;
         pos / scale
     }
+
+    /// 大きさ `view_size` の描画領域に表示されている範囲を絶対座標で返す
+    pub fn visible_region(&self, view_size: Vec2) -> FlDataFrameRectangle {
+        let a = self.screen_to_absolute(Vec2::ZERO);
+        let b = self.screen_to_absolute(view_size);
+        FlDataFrameRectangle {
+            x1: a.x.min(b.x) as f64,
+            y1: a.y.min(b.y) as f64,
+            x2: a.x.max(b.x) as f64,
+            y2: a.y.max(b.y) as f64,
+        }
+    }
 }
 
-#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
 pub enum Origin {
     #[default]
     TopLeft,
@@ -82,6 +151,14 @@ struct InnerState {
     current_scale: f32,
     shift: Vec2,
     origin: Origin,
+    #[serde(default)]
+    link_group: Option<u8>,
+    #[serde(default)]
+    show_rulers: bool,
+    #[serde(default)]
+    show_pixel_grid: bool,
+    #[serde(default)]
+    show_minimap: bool,
 }
 
 impl Default for InnerState {
@@ -90,20 +167,36 @@ impl Default for InnerState {
             current_scale: 1.0,
             shift: Vec2::ZERO,
             origin: Origin::default(),
+            link_group: None,
+            show_rulers: false,
+            show_pixel_grid: false,
+            show_minimap: false,
         }
     }
 }
 
 impl VisualizeState {
     pub fn load(ctx: &Context, id: Id) -> Self {
-        let inner_state =
-            ctx.data_mut(|data| data.get_persisted::<InnerState>(id).unwrap_or_default());
-        Self {
+        let inner_state = ctx.data_mut(|data| data.get_persisted::<InnerState>(id));
+        // 初めて表示する時は全体が収まるようにする
+        if inner_state.is_none() {
+            request_zoom(ctx, id, ZoomRequest::Fit);
+        }
+        let inner_state = inner_state.unwrap_or_default();
+        let mut state = Self {
             id,
             current_scale: inner_state.current_scale,
             shift: inner_state.shift,
             origin: inner_state.origin,
-        }
+            link_group: inner_state.link_group,
+            show_rulers: inner_state.show_rulers,
+            show_pixel_grid: inner_state.show_pixel_grid,
+            show_minimap: inner_state.show_minimap,
+            measurements: Measurements::default(),
+            inspecting: inspection_enabled(ctx),
+        };
+        state.load_linked_view(ctx);
+        state
     }
 
     fn store(&self, ctx: &Context) {
@@ -114,9 +207,14 @@ impl VisualizeState {
                     current_scale: self.current_scale,
                     shift: self.shift,
                     origin: self.origin,
+                    link_group: self.link_group,
+                    show_rulers: self.show_rulers,
+                    show_pixel_grid: self.show_pixel_grid,
+                    show_minimap: self.show_minimap,
                 },
             )
         });
+        self.store_linked_view(ctx);
     }
 
     pub fn is_valid(&self, ui: &mut Ui) -> bool {
@@ -149,6 +247,19 @@ impl VisualizeState {
                 if b.clicked() {
                     state.current_scale += 0.1;
                 }
+                for (label, hover, request) in [
+                    ("Fit", "Zoom to fit", ZoomRequest::Fit),
+                    ("1:1", "Actual size", ZoomRequest::OneToOne),
+                    (
+                        "Highlighted",
+                        "Zoom to highlighted rows",
+                        ZoomRequest::Highlighted,
+                    ),
+                ] {
+                    if ui.button(label).on_hover_text(hover).clicked() {
+                        request_zoom(ui.ctx(), state.id, request);
+                    }
+                }
                 if ui
                     .button(match state.origin {
                         Origin::TopLeft => "左上",
@@ -161,30 +272,110 @@ impl VisualizeState {
                         Origin::BottomLeft => Origin::TopLeft,
                     };
                 }
+                ui.toggle_value(&mut state.show_rulers, "📏")
+                    .on_hover_text("Rulers");
+                ui.toggle_value(&mut state.show_pixel_grid, "#")
+                    .on_hover_text("Pixel grid");
+                ui.toggle_value(&mut state.show_minimap, "🗺")
+                    .on_hover_text("Minimap");
+                state.link_header(ui);
             },
         );
     }
 
     pub fn show(&mut self, ui: &mut Ui, bag: &Bag, contents: &[Arc<DataRender>]) {
+        self.show_layers(
+            ui,
+            bag,
+            contents,
+            &vec![LayerSettings::default(); contents.len()],
+        );
+    }
+
+    /// `layers` は `contents` と同じ順の各レイヤーの設定
+    pub fn show_layers(
+        &mut self,
+        ui: &mut Ui,
+        bag: &Bag,
+        contents: &[Arc<DataRender>],
+        layers: &[LayerSettings],
+    ) {
         let old_state = self.clone();
         let config = Config::get_global(ui);
 
         self.show_header(ui);
+        let mut compare = StackCompareState::load(ui.ctx(), self.id);
+        if contents.len() > 1 {
+            compare.show_header(ui);
+        }
 
         let _response = ui
             .with_layout(Layout::top_down(Align::Min), |ui| {
                 let response = {
                     if contents.len() > 1 {
-                        stack_visualize(ui, bag, self, contents)
+                        stack_visualize(ui, bag, self, &mut compare, contents, layers)
                     } else {
                         visualize(ui, bag, self, contents[0].as_ref())
                     }
                 };
 
-                if response.dragged_by(PointerButton::Middle) {
+                let capturing = is_capturing(ui.ctx());
+                if !capturing && config.keymap.dragged(&response, KeyAction::Pan) {
                     self.shift += response.drag_delta();
                 }
 
+                if response.contains_pointer() && !ui.ctx().wants_keyboard_input() && !capturing {
+                    let (fit, reset_zoom, toggle_origin) = ui.input(|input| {
+                        (
+                            config.keymap.pressed(input, KeyAction::ZoomToFit),
+                            config.keymap.pressed(input, KeyAction::ResetZoom),
+                            config.keymap.pressed(input, KeyAction::ToggleOrigin),
+                        )
+                    });
+                    if fit {
+                        request_zoom(ui.ctx(), self.id, ZoomRequest::Fit);
+                    }
+                    if reset_zoom {
+                        self.current_scale = 1.0;
+                        self.shift = Vec2::ZERO;
+                    }
+                    if toggle_origin {
+                        self.origin = match self.origin {
+                            Origin::TopLeft => Origin::BottomLeft,
+                            Origin::BottomLeft => Origin::TopLeft,
+                        };
+                    }
+                }
+
+                if let Some(request) = take_zoom_request(ui.ctx(), self.id) {
+                    self.apply_zoom_request(
+                        request,
+                        ui.ctx(),
+                        bag,
+                        contents,
+                        layers,
+                        response.rect.size(),
+                    );
+                }
+
+                self.share_pointer(
+                    ui.ctx(),
+                    response
+                        .hover_pos()
+                        .map(|pos| self.screen_to_absolute(pos - response.rect.min)),
+                );
+                if self.show_pixel_grid {
+                    self.draw_pixel_grid(ui, response.rect, &config);
+                }
+                self.measurements.draw(ui, self, response.rect);
+                if self.show_rulers {
+                    self.draw_rulers(ui, response.rect);
+                }
+                if self.show_minimap {
+                    self.show_minimap(ui, bag, contents, layers, response.rect);
+                }
+                self.draw_linked_crosshair(ui, response.rect);
+
                 if let Some(hover_pos) = response.hover_pos() {
                     let hover_pos = hover_pos - response.rect.min;
                     ui.input(|input| {
@@ -213,6 +404,9 @@ impl VisualizeState {
             *self = old_state;
         }
         self.store(ui.ctx());
+        if contents.len() > 1 {
+            compare.store(ui.ctx(), self.id);
+        }
     }
 }
 
@@ -232,6 +426,32 @@ impl DataRender {
         }
     }
 
+    /// `from` を参照している場合、設定を保ったまま参照先を `to` に変えたものを返す
+    pub fn retarget(&self, from: &FlDataReference, to: &FlDataReference) -> Option<Self> {
+        if self.reference() != *from {
+            return None;
+        }
+        let mut render = self.clone();
+        match &mut render {
+            DataRender::Image(render) => render.content = to.clone(),
+            DataRender::Tensor2D(render) => render.content = to.clone(),
+            DataRender::DataFrameView(render) => {
+                render.dataframe_view.table.data_reference = to.clone()
+            }
+        }
+        Some(render)
+    }
+
+    /// 表示に使うアフィン変換
+    pub fn transform(&self, bag: &Bag) -> FlAffineTransform {
+        let transform = match self {
+            DataRender::Image(render) => &render.transform,
+            DataRender::Tensor2D(render) => &render.transform,
+            DataRender::DataFrameView(render) => &render.transform,
+        };
+        transform.resolve(bag, &self.reference())
+    }
+
     /// 検査可能な線分を返す
     pub fn measurable_segments(&self, ctx: &Context, bag: &Bag) -> anyhow::Result<Vec<Line>> {
         match self {
@@ -240,6 +460,14 @@ impl DataRender {
             DataRender::DataFrameView(render) => render.measurable_segments(ctx, bag),
         }
     }
+
+    /// テーブルで選択されている行の図形の線分を返す
+    pub fn highlighted_segments(&self, ctx: &Context, bag: &Bag) -> anyhow::Result<Vec<Line>> {
+        match self {
+            DataRender::DataFrameView(render) => render.highlighted_segments(ctx, bag),
+            _ => Ok(vec![]),
+        }
+    }
 }
 
 impl From<FlImageRender> for DataRender {
@@ -311,11 +539,16 @@ pub trait DataRenderable {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FlImageRender {
     pub content: FlDataReference,
+    #[serde(default)]
+    pub transform: RenderTransform,
 }
 
 impl FlImageRender {
     pub fn new(content: FlDataReference) -> Self {
-        Self { content }
+        Self {
+            content,
+            transform: RenderTransform::default(),
+        }
     }
 }
 
@@ -335,10 +568,33 @@ impl DataRenderable for FlImageRender {
         let data = bag.data_by_reference(&self.content)?;
 
         if let FlData::Image(data) = data {
-            let image = Image::from_bytes(format!("bytes://{}.png", data.id), data.value.clone());
+            let size = Vec2::new(data.width as f32, data.height as f32);
+            let transform = self.transform.resolve(bag, &self.content);
+            if data.width.max(data.height) > TILED_THRESHOLD {
+                let bytes = data.value.clone();
+                draw_tiled(
+                    painter,
+                    Id::new(data.id),
+                    move || Ok(image::load_from_memory(&bytes)?.to_rgba8()),
+                    state,
+                    Vec2::ZERO,
+                    size,
+                    &transform,
+                    Color32::WHITE,
+                );
+                return Ok(());
+            }
 
-            let size = Vec2::new(data.width as f32, data.height as f32) * state.scale();
-            draw_image(painter, &image, state.shift, size, Color32::WHITE)
+            let image = Image::from_bytes(format!("bytes://{}.png", data.id), data.value.clone());
+            draw_image(
+                painter,
+                &image,
+                state,
+                Vec2::ZERO,
+                size,
+                &transform,
+                Color32::WHITE,
+            )
         } else {
             Err(anyhow::anyhow!(
                 "mismatched data type expected FlData::Image"
@@ -351,12 +607,16 @@ impl DataRenderable for FlImageRender {
 
         if let FlData::Image(data) = data {
             let size = (data.width as f64, data.height as f64);
-            Ok(vec![
+            let transform = self.transform.resolve(bag, &self.content);
+            Ok([
                 Line::new(coord!(x: 0.0, y: 0.0), coord!(x: size.0, y: 0.0)),
                 Line::new(coord!(x: 0.0, y: 0.0), coord!(x: 0.0, y: size.1)),
                 Line::new(coord!(x: size.0, y: 0.0), coord!(x: size.0, y: size.1)),
                 Line::new(coord!(x: 0.0, y: size.1), coord!(x: size.0, y: size.1)),
-            ])
+            ]
+            .iter()
+            .map(|line| transform_line(&transform, line))
+            .collect())
         } else {
             Err(anyhow::anyhow!(
                 "mismatched data type expected FlData::Image"
@@ -364,8 +624,9 @@ impl DataRenderable for FlImageRender {
         }
     }
 
-    fn config_panel(&self, ui: &mut Ui, _bag: &Bag) {
+    fn config_panel(&self, ui: &mut Ui, bag: &Bag) {
         ui.label("FlImage");
+        self.transform.config_panel(ui, bag, &self.content);
     }
 }
 
@@ -384,6 +645,8 @@ impl Default for FlTensor2DRenderContext {
 pub struct FlTensor2DRender {
     content: FlDataReference,
     context: Arc<Mutex<FlTensor2DRenderContext>>,
+    #[serde(default)]
+    transform: RenderTransform,
 }
 
 impl FlTensor2DRender {
@@ -391,6 +654,7 @@ impl FlTensor2DRender {
         Self {
             content,
             context: Arc::new(Mutex::new(FlTensor2DRenderContext::default())),
+            transform: RenderTransform::default(),
         }
     }
 }
@@ -411,6 +675,32 @@ impl DataRenderable for FlTensor2DRender {
         let data = bag.data_by_reference(&self.content)?;
         if let FlData::Tensor(data) = data {
             let id = Id::new(data.id);
+            let transparency = (self.context.lock().unwrap().transparency * 255.0) as u8;
+            let tint_color = Color32::from_rgba_premultiplied(
+                transparency,
+                transparency,
+                transparency,
+                transparency,
+            );
+            let size = Vec2::new(data.value.shape()[1] as f32, data.value.shape()[0] as f32);
+            let offset = Vec2::new(data.offset.1 as f32, data.offset.0 as f32);
+            let transform = self.transform.resolve(bag, &self.content);
+
+            if data.value.shape().iter().any(|len| *len > TILED_THRESHOLD) {
+                let content = data.clone();
+                draw_tiled(
+                    painter,
+                    id,
+                    move || Ok(DynamicImage::ImageRgb8(colormap_pixels(&content.value)).to_rgba8()),
+                    state,
+                    offset,
+                    size,
+                    &transform,
+                    tint_color,
+                );
+                return Ok(());
+            }
+
             let image = painter.ctx().memory_mut(|mem| {
                 let cache = mem.caches.cache::<VisualizedImageCache>();
                 if let Some(image) = cache.get(id) {
@@ -423,49 +713,10 @@ impl DataRenderable for FlTensor2DRender {
                     let ctx = painter.ctx().clone();
                     let content = data.clone();
                     std::thread::spawn(move || {
-                        let cm = scarlet::colormap::ListedColorMap::viridis();
-                        let max = content
-                            .value
-                            .iter()
-                            .copied()
-                            .max_by_key(|t| UnwrapOrd(*t))
-                            .unwrap();
-                        let min = content
-                            .value
-                            .iter()
-                            .copied()
-                            .min_by_key(|t| UnwrapOrd(*t))
-                            .unwrap();
-                        let normalize = move |v| (v - min) / (max - min);
-                        let transformed: Vec<RGBColor> =
-                            cm.transform(content.value.iter().map(|v| normalize(*v)));
-                        let pixels: Vec<u8> = transformed
-                            .into_iter()
-                            .flat_map(|c| [c.int_r(), c.int_g(), c.int_b()])
-                            .collect();
-                        let image_buffer: ImageBuffer<Rgb<u8>, _> = ImageBuffer::from_vec(
-                            content.value.shape()[1] as u32,
-                            content.value.shape()[0] as u32,
-                            pixels,
-                        )
-                        .unwrap();
-                        let image = DynamicImage::ImageRgb8(image_buffer);
-
-                        let mut image_png_bytes = Vec::new();
-                        let mut cursor = Cursor::new(&mut image_png_bytes);
-                        image
-                            .write_to(&mut cursor, image::ImageOutputFormat::Png)
-                            .unwrap();
+                        let image = colormap_image(&content.value);
                         ctx.memory_mut(|mem| {
                             let cache = mem.caches.cache::<VisualizedImageCache>();
-                            cache.insert(
-                                id,
-                                FlImage::new(
-                                    image_png_bytes,
-                                    image.width() as usize,
-                                    image.height() as usize,
-                                ),
-                            )
+                            cache.insert(id, image)
                         })
                     });
                     cache.insert_pending(id);
@@ -477,21 +728,7 @@ impl DataRenderable for FlTensor2DRender {
                 let image =
                     Image::from_bytes(format!("bytes://{}.png", data.id), image.value.clone());
 
-                let transparency = (self.context.lock().unwrap().transparency * 255.0) as u8;
-                let tint_color = Color32::from_rgba_premultiplied(
-                    transparency,
-                    transparency,
-                    transparency,
-                    transparency,
-                );
-
-                let size = Vec2::new(data.value.shape()[1] as f32, data.value.shape()[0] as f32)
-                    * state.scale();
-
-                let offset = data.offset;
-                let offset = Vec2::new(offset.1 as f32, offset.0 as f32) * state.scale();
-
-                draw_image(painter, &image, state.shift + offset, size, tint_color)?;
+                draw_image(painter, &image, state, offset, size, &transform, tint_color)?;
             }
 
             Ok(())
@@ -510,8 +747,9 @@ impl DataRenderable for FlTensor2DRender {
 
         let offset = (data.offset.1 as f64, data.offset.0 as f64);
         let size = (data.value.shape()[1] as f64, data.value.shape()[0] as f64);
+        let transform = self.transform.resolve(bag, &self.content);
 
-        Ok(vec![
+        Ok([
             Line::new(
                 coord!(x: offset.0, y: offset.1),
                 coord!(x: size.0 + offset.0, y: offset.1),
@@ -528,10 +766,13 @@ impl DataRenderable for FlTensor2DRender {
                 coord!(x: offset.0, y: size.1 + offset.1),
                 coord!(x: size.0 + offset.0, y: size.1 + offset.1),
             ),
-        ])
+        ]
+        .iter()
+        .map(|line| transform_line(&transform, line))
+        .collect())
     }
 
-    fn config_panel(&self, ui: &mut Ui, _bag: &Bag) {
+    fn config_panel(&self, ui: &mut Ui, bag: &Bag) {
         ui.label("FlTensor2D");
         CollapsingHeader::new("Config").show(ui, |ui| {
             let mut render_context = self.context.lock().unwrap();
@@ -540,6 +781,7 @@ impl DataRenderable for FlTensor2DRender {
                 Slider::new(&mut render_context.transparency, 0.0..=1.0).ui(ui);
             });
         });
+        self.transform.config_panel(ui, bag, &self.content);
     }
 }
 
@@ -557,6 +799,11 @@ pub struct FlDataFrameViewRenderContext {
     pub edge_accent_start: EdgeAccent,
     #[serde(default)]
     pub edge_accent_end: EdgeAccent,
+    #[serde(default)]
+    pub level_of_detail: LevelOfDetail,
+    /// 表示範囲の図形がこれより多いと密度表示に切り替える
+    #[serde(default = "default_density_threshold")]
+    pub density_threshold: usize,
 }
 
 impl FlDataFrameViewRenderContext {
@@ -571,12 +818,24 @@ impl FlDataFrameViewRenderContext {
             self.label_column = None;
         }
     }
+
+    fn style_columns(&self) -> StyleColumns {
+        StyleColumns {
+            stroke_color: self.color_scatter_column.clone(),
+            fill_color: self.fill_color_scatter_column.clone(),
+            label: self.label_column.clone(),
+        }
+    }
 }
 
 fn default_fill_transparency() -> f64 {
     0.9
 }
 
+fn default_density_threshold() -> usize {
+    20_000
+}
+
 impl Default for FlDataFrameViewRenderContext {
     fn default() -> Self {
         Self {
@@ -589,6 +848,8 @@ impl Default for FlDataFrameViewRenderContext {
             highlight_thickness: 3.0,
             edge_accent_start: EdgeAccent::None,
             edge_accent_end: EdgeAccent::None,
+            level_of_detail: LevelOfDetail::Auto,
+            density_threshold: default_density_threshold(),
         }
     }
 }
@@ -599,6 +860,8 @@ pub struct FlDataFrameViewRender {
     pub dataframe_view: FlDataFrameView,
     pub column: String,
     render_context: Arc<Mutex<FlDataFrameViewRenderContext>>,
+    #[serde(default)]
+    transform: RenderTransform,
 }
 
 impl DataRenderable for FlDataFrameViewRender {
@@ -615,167 +878,146 @@ impl DataRenderable for FlDataFrameViewRender {
     ) -> anyhow::Result<()> {
         puffin::profile_function!();
         let dataframe = self.dataframe_view.table.dataframe(bag)?;
-        let special_column = dataframe
-            .special_columns
-            .get(&self.column)
-            .with_context(|| format!("special column not found: {}", self.column))?;
-
-        let computed_dataframe = if let Some(DataFramePoll::Ready(computed_dataframe)) =
-            self.dataframe_view.table.computed_dataframe(ui.ctx(), bag)
-        {
-            computed_dataframe
-        } else {
-            dataframe
-                .value
-                .clone()
-                .with_row_index("__FleximRowId".into(), None)
-                .unwrap()
-        };
+        let transform = self
+            .transform
+            .resolve(bag, &self.dataframe_view.table.data_reference);
 
-        let target_series = computed_dataframe
-            .column(self.column.as_str())
-            .unwrap()
-            .as_series()
-            .unwrap()
-            .clone();
-        let stroke_color_series = self
-            .render_context
-            .lock()
-            .unwrap()
-            .color_scatter_column
-            .as_ref()
-            .map(|c| computed_dataframe.column(c.as_str()).unwrap().clone())
-            .map(|c| c.as_series().unwrap().clone());
-        let fill_color_series = self
-            .render_context
-            .lock()
-            .unwrap()
-            .fill_color_scatter_column
-            .as_ref()
-            .map(|c| computed_dataframe.column(c.as_str()).unwrap().clone())
-            .map(|c| c.as_series().unwrap().clone());
-        let indices = computed_dataframe
-            .column("__FleximRowId")
-            .unwrap()
-            .as_series()
-            .unwrap()
-            .iter()
-            .map(|v| v.extract::<u32>().unwrap() as u64)
-            .collect_vec();
-
-        let highlight = {
-            if let Some(state) = self.dataframe_view.table.state(ui, bag) {
-                let state = state.lock().unwrap();
-                let highlight = &state.highlight;
-                Some(
-                    computed_dataframe
-                        .column("__FleximRowId")
-                        .unwrap()
-                        .as_series()
-                        .unwrap()
-                        .iter()
-                        .map(|v| {
-                            let index = v.extract::<u32>().unwrap() as u64;
-                            highlight.contains(&index)
-                        })
-                        .collect_vec(),
-                )
-            } else {
-                None
+        if let Some(g) = self.dataframe_view.table.state(ui, bag) {
+            let mut table_state = g.lock().unwrap();
+            if table_state.uses_viewport() {
+                let viewport = transform
+                    .apply_inverse_rectangle(&state.visible_region(painter.clip_rect().size()));
+                if table_state.viewport.as_ref() != Some(&viewport) {
+                    table_state.viewport = Some(viewport);
+                }
             }
+        }
+
+        let (generation, computed_dataframe, geometry) = self.geometry(ui.ctx(), bag)?;
+        let render_context = self.render_context.lock().unwrap().clone();
+        let style = self.style(
+            ui.ctx(),
+            bag,
+            generation,
+            &computed_dataframe,
+            render_context.style_columns(),
+        )?;
+        let table_state = self.dataframe_view.table.state(ui, bag);
+
+        // 索引を使って表示範囲とポインタの周りにある図形だけを取り出す
+        let origin = painter.clip_rect().min;
+        let to_data_rect = |absolute: Rect| {
+            let rectangle = transform.apply_inverse_rectangle(&FlDataFrameRectangle {
+                x1: absolute.min.x as f64,
+                y1: absolute.min.y as f64,
+                x2: absolute.max.x as f64,
+                y2: absolute.max.y as f64,
+            });
+            Rect::from_two_pos(
+                Pos2::new(rectangle.x1 as f32, rectangle.y1 as f32),
+                Pos2::new(rectangle.x2 as f32, rectangle.y2 as f32),
+            )
         };
-        let shapes: Result<Vec<Option<Box<dyn SpecialColumnShape>>>, FlShapeConvertError> =
-            target_series
-                .iter()
-                .map(|x| match special_column {
-                    FlDataFrameSpecialColumn::Rectangle => {
-                        FlDataFrameRectangle::try_from(x.clone())
-                            .map(|x| Box::new(x) as Box<dyn SpecialColumnShape>)
-                    }
-                    FlDataFrameSpecialColumn::Segment => FlDataFrameSegment::try_from(x.clone())
-                        .map(|x| Box::new(x) as Box<dyn SpecialColumnShape>),
-                    _ => Err(FlShapeConvertError::CanNotConvert),
-                })
-                .map(|x| {
-                    x.map(Some).or_else(|e| match e {
-                        FlShapeConvertError::NullValue => Ok(None),
-                        _ => Err(e),
-                    })
-                })
-                .collect();
-        let shapes = shapes?;
-        let stroke_colors = stroke_color_series.map(|color_series| {
-            color_series
-                .iter()
-                .map(|value| serise_value_to_color(color_series.field().as_ref(), &value))
-                .collect_vec()
-        });
-        let fill_colors = fill_color_series.map(|color_series| {
-            color_series
-                .iter()
-                .map(|v| serise_value_to_color(color_series.field().as_ref(), &v))
-                .collect_vec()
-        });
-        let label_series = self
-            .render_context
-            .lock()
-            .unwrap()
-            .label_column
-            .as_ref()
-            .map(|c| computed_dataframe.column(c.as_str()).unwrap().clone())
-            .map(|c| c.as_series().unwrap().clone());
-        let labels = label_series
-            .map(|label_series| label_series.iter().map(|v| v.to_string()).collect_vec());
+        let visible = to_data_rect(
+            Rect::from_two_pos(
+                state.screen_to_absolute(Vec2::ZERO).to_pos2(),
+                state
+                    .screen_to_absolute(painter.clip_rect().size())
+                    .to_pos2(),
+            )
+            .expand(CULL_MARGIN / state.current_scale),
+        );
+        let visible_rows = geometry.query(visible);
+        // 拡大率が低く図形が多すぎる時は 1 つずつ描かずに画面上の密度として描く
+        let aggregated = render_context
+            .level_of_detail
+            .aggregates(visible_rows.len(), render_context.density_threshold);
+        let near_pointer = ui
+            .ctx()
+            .pointer_hover_pos()
+            .filter(|pos| !aggregated && painter.clip_rect().contains(*pos))
+            .map(|pos| {
+                let margin = render_context.highlight_thickness as f32 + HIT_MARGIN;
+                let mut reach = Vec2::splat(margin);
+                if render_context.label_column.is_some() {
+                    reach = reach.max(LABEL_REACH);
+                }
+                let absolute = state.screen_to_absolute(pos - origin);
+                geometry.query(to_data_rect(Rect::from_center_size(
+                    absolute.to_pos2(),
+                    reach * 2.0 / state.current_scale,
+                )))
+            })
+            .unwrap_or_default();
 
+        let mut edit_state = AnnotationEditState::load(ui.ctx(), self.id);
+        let editing = edit_state.enabled && ui.is_enabled();
         let mut hovered_index = None;
-        for (i, shape) in shapes
-            .iter()
-            .enumerate()
-            .filter_map(|(i, x)| Some((i, x.as_ref()?)))
-        {
-            let color = if let Some(colors) = &stroke_colors {
-                colors[i]
-            } else {
-                Color32::RED
+        let visualize_id = state.id;
+        let shape_rows = if aggregated {
+            draw_density(painter, state, &transform, &geometry, &visible_rows);
+            vec![]
+        } else {
+            visible_rows
+        };
+        for i in shape_rows {
+            let Some(shape) = geometry.shape(i) else {
+                continue;
             };
-            let fill_color = fill_colors.as_ref().map(|colors| colors[i]);
-
-            let label = labels.as_ref().map(|labels| labels[i].as_str());
-            let transparent = self.render_context.lock().unwrap().transparency;
-            let fill_transparent = self.render_context.lock().unwrap().fill_transparency;
-
-            let thickness = if highlight.as_ref().is_some_and(|h| h[i]) {
-                self.render_context.lock().unwrap().highlight_thickness
+            let shape = shape.as_shape();
+            let index = geometry.row_id(i);
+            let color = style
+                .stroke_colors
+                .as_ref()
+                .map_or(Color32::RED, |colors| colors[i]);
+            let fill_color = style.fill_colors.as_ref().map(|colors| colors[i]);
+            let label = style.labels.as_ref().map(|labels| labels[i].clone());
+            let highlighted = table_state
+                .as_ref()
+                .is_some_and(|state| state.lock().unwrap().highlight.contains(&index));
+
+            let thickness = if highlighted {
+                render_context.highlight_thickness
             } else {
-                self.render_context.lock().unwrap().normal_thickness
+                render_context.normal_thickness
             } as f32;
 
-            let edge_accent_start = self.render_context.lock().unwrap().edge_accent_start;
-            let edge_accent_end = self.render_context.lock().unwrap().edge_accent_end;
             let response = shape.render(
                 ui,
                 painter,
                 RenderParameter {
-                    stroke_color: calc_transparent_color(color, transparent),
+                    stroke_color: calc_transparent_color(color, render_context.transparency),
                     stroke_thickness: thickness,
-                    label: label.map(|s| s.to_string()),
-                    fill_color: fill_color.map(|c| calc_transparent_color(c, fill_transparent)),
-                    edge_accent_start,
-                    edge_accent_end,
+                    label,
+                    fill_color: fill_color
+                        .map(|c| calc_transparent_color(c, render_context.fill_transparency)),
+                    edge_accent_start: render_context.edge_accent_start,
+                    edge_accent_end: render_context.edge_accent_end,
+                    transform,
+                    interactive: near_pointer.binary_search(&i).is_ok(),
                 },
                 state,
             );
 
-            if let Some(g) = self.dataframe_view.table.state(ui, bag) {
+            if let Some(g) = &table_state {
                 let mut state = g.lock().unwrap();
                 if let Some(r) = response {
                     if r.hovered() {
-                        hovered_index = Some(indices[i]);
+                        hovered_index = Some(index);
                     }
 
-                    if r.clicked() {
+                    if r.double_clicked() && !editing {
+                        let segments = shape
+                            .measure_segments()
+                            .iter()
+                            .map(|line| transform_line(&transform, line))
+                            .collect_vec();
+                        if let Some(region) = segments_bounds(&segments) {
+                            request_zoom(ui.ctx(), visualize_id, ZoomRequest::Region(region));
+                        }
+                    }
+                    if r.clicked() && !editing {
                         let highlight = &mut state.highlight;
-                        let index = indices[i];
                         if highlight.contains(&index) {
                             highlight.remove(&index);
                         } else {
@@ -783,9 +1025,9 @@ impl DataRenderable for FlDataFrameViewRender {
                         }
                     }
                     r.on_hover_ui_at_pointer(|ui| {
-                        ui.label(format!("index: {}", indices[i]));
+                        ui.label(format!("index: {}", index));
                         let dataframe = &self.dataframe_view.table.dataframe(bag).unwrap().value;
-                        let row = dataframe.get_row(indices[i] as usize).unwrap();
+                        let row = dataframe.get_row(index as usize).unwrap();
                         for (c, v) in dataframe.get_column_names().iter().zip(row.0.iter()) {
                             ui.label(format!("{}: {}", c, v));
                         }
@@ -793,7 +1035,7 @@ impl DataRenderable for FlDataFrameViewRender {
                 }
             }
         }
-        if let Some(g) = self.dataframe_view.table.state(ui, bag) {
+        if let Some(g) = &table_state {
             let mut state = g.lock().unwrap();
             if let Some(hi) = hovered_index {
                 state.selected.replace(hi);
@@ -801,60 +1043,40 @@ impl DataRenderable for FlDataFrameViewRender {
                 state.selected.take();
             }
         }
+        if editing {
+            edit_annotations(
+                ui,
+                bag,
+                painter,
+                state,
+                &mut edit_state,
+                &self.dataframe_view.table.data_reference,
+                &dataframe,
+                &self.column,
+                &geometries(
+                    computed_dataframe
+                        .column(self.column.as_str())?
+                        .as_series()
+                        .context("not a series")?,
+                    geometry.row_ids(),
+                ),
+                &transform,
+            )?;
+            edit_state.store(ui.ctx(), self.id);
+        }
         Ok(())
     }
 
     fn measurable_segments(&self, ctx: &Context, bag: &Bag) -> anyhow::Result<Vec<Line>> {
-        let dataframe = self.dataframe_view.table.dataframe(bag)?;
-        let special_column = dataframe
-            .special_columns
-            .get(&self.column)
-            .with_context(|| format!("special column not found: {}", self.column))?;
-
-        let computed_dataframe = if let Some(DataFramePoll::Ready(computed_dataframe)) =
-            self.dataframe_view.table.computed_dataframe(ctx, bag)
-        {
-            computed_dataframe
-        } else {
-            dataframe
-                .value
-                .clone()
-                .with_row_index("__FleximRowId".into(), None)
-                .unwrap()
-        };
+        let (_, _, geometry) = self.geometry(ctx, bag)?;
+        let transform = self
+            .transform
+            .resolve(bag, &self.dataframe_view.table.data_reference);
 
-        let target_series = computed_dataframe
-            .column(self.column.as_str())
-            .unwrap()
-            .as_series()
-            .unwrap()
-            .clone();
-
-        let shapes: Result<Vec<Option<Box<dyn SpecialColumnShape>>>, FlShapeConvertError> =
-            target_series
-                .iter()
-                .map(|x| match special_column {
-                    FlDataFrameSpecialColumn::Rectangle => {
-                        FlDataFrameRectangle::try_from(x.clone())
-                            .map(|x| Box::new(x) as Box<dyn SpecialColumnShape>)
-                    }
-                    FlDataFrameSpecialColumn::Segment => FlDataFrameSegment::try_from(x.clone())
-                        .map(|x| Box::new(x) as Box<dyn SpecialColumnShape>),
-                    _ => Err(FlShapeConvertError::CanNotConvert),
-                })
-                .map(|x| {
-                    x.map(Some).or_else(|e| match e {
-                        FlShapeConvertError::NullValue => Ok(None),
-                        _ => Err(e),
-                    })
-                })
-                .collect();
-        let shapes = shapes?;
-
-        Ok(shapes
+        Ok(geometry
+            .measure_segments()
             .iter()
-            .filter_map(|x| x.as_ref())
-            .flat_map(|x| x.measure_segments())
+            .map(|line| transform_line(&transform, line))
             .collect_vec())
     }
 
@@ -1015,7 +1237,49 @@ impl DataRenderable for FlDataFrameViewRender {
                     ui.label("Normal Thickness");
                     Slider::new(&mut render_context.normal_thickness, 0.0..=10.0).ui(ui);
                 });
+                ui.horizontal(|ui| {
+                    ui.label("Level of Detail");
+                    ComboBox::from_id_salt("Level of Detail")
+                        .selected_text(render_context.level_of_detail.to_string())
+                        .show_ui(ui, |ui| {
+                            for level_of_detail in all::<LevelOfDetail>() {
+                                ui.selectable_value(
+                                    &mut render_context.level_of_detail,
+                                    level_of_detail,
+                                    level_of_detail.to_string(),
+                                );
+                            }
+                        });
+                });
+                ui.add_enabled_ui(
+                    render_context.level_of_detail == LevelOfDetail::Auto,
+                    |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("Density Threshold");
+                            DragValue::new(&mut render_context.density_threshold)
+                                .range(1..=10_000_000)
+                                .speed(100)
+                                .ui(ui)
+                                .on_hover_text(
+                                    "Draw a density heatmap when more shapes than this are visible",
+                                );
+                        });
+                    },
+                );
             });
+        self.transform
+            .config_panel(ui, bag, &self.dataframe_view.table.data_reference);
+
+        let mut edit_state = AnnotationEditState::load(ui.ctx(), self.id);
+        if ui
+            .checkbox(&mut edit_state.enabled, "Edit Annotations")
+            .on_hover_text(
+                "Drag a shape or its handles to edit, drag on empty space to create, Delete to remove",
+            )
+            .changed()
+        {
+            edit_state.store(ui.ctx(), self.id);
+        }
     }
 }
 
@@ -1028,10 +1292,169 @@ impl FlDataFrameViewRender {
             dataframe_view,
             column,
             render_context: Arc::new(Mutex::new(FlDataFrameViewRenderContext::default())),
+            transform: RenderTransform::default(),
+        }
+    }
+
+    pub fn with_color_scatter_column(self, column: impl Into<String>) -> Self {
+        self.render_context.lock().unwrap().color_scatter_column = Some(column.into());
+        self
+    }
+
+    fn cache_id(&self, bag: &Bag) -> anyhow::Result<Id> {
+        Ok(self
+            .dataframe_view
+            .table
+            .data_id(bag)?
+            .with(self.column.as_str()))
+    }
+
+    /// 描画と同じ経路で、現在のフィルタ結果の座標と色・ラベルを返す
+    pub fn buffers(
+        &self,
+        ctx: &Context,
+        bag: &Bag,
+    ) -> anyhow::Result<(Arc<GeometryBuffers>, Arc<StyleBuffers>)> {
+        let (generation, computed_dataframe, geometry) = self.geometry(ctx, bag)?;
+        let columns = self.render_context.lock().unwrap().style_columns();
+        let style = self.style(ctx, bag, generation, &computed_dataframe, columns)?;
+        Ok((geometry, style))
+    }
+
+    /// フィルタ結果の世代とフィルタ結果、その特殊列の座標
+    /// 座標はフィルタ結果の世代ごとにキャッシュする
+    fn geometry(
+        &self,
+        ctx: &Context,
+        bag: &Bag,
+    ) -> anyhow::Result<(Option<u64>, DataFrame, Arc<GeometryBuffers>)> {
+        let dataframe = self.dataframe_view.table.dataframe(bag)?;
+        let special_column = dataframe
+            .special_columns
+            .get(&self.column)
+            .with_context(|| format!("special column not found: {}", self.column))?;
+
+        let (generation, computed_dataframe) = match self
+            .dataframe_view
+            .table
+            .computed_dataframe_with_generation(ctx, bag)
+        {
+            Some((generation, computed_dataframe)) => (Some(generation), computed_dataframe),
+            None => (
+                None,
+                dataframe
+                    .value
+                    .clone()
+                    .with_row_index("__FleximRowId".into(), None)?,
+            ),
+        };
+
+        let id = self.cache_id(bag)?;
+        if let Some(geometry) =
+            ctx.memory_mut(|mem| mem.caches.cache::<GeometryCache>().geometry(id, generation))
+        {
+            return Ok((generation, computed_dataframe, geometry));
         }
+
+        puffin::profile_scope!("build geometry buffers");
+        let geometry = Arc::new(GeometryBuffers::from_dataframe(
+            &computed_dataframe,
+            &self.column,
+            special_column,
+        )?);
+        ctx.memory_mut(|mem| {
+            mem.caches
+                .cache::<GeometryCache>()
+                .insert_geometry(id, generation, geometry.clone())
+        });
+        Ok((generation, computed_dataframe, geometry))
+    }
+
+    /// 行ごとの色とラベル。フィルタ結果の世代と使う列ごとにキャッシュする
+    fn style(
+        &self,
+        ctx: &Context,
+        bag: &Bag,
+        generation: Option<u64>,
+        computed_dataframe: &DataFrame,
+        columns: StyleColumns,
+    ) -> anyhow::Result<Arc<StyleBuffers>> {
+        let id = self.cache_id(bag)?;
+        if let Some(style) = ctx.memory_mut(|mem| {
+            mem.caches
+                .cache::<GeometryCache>()
+                .style(id, generation, &columns)
+        }) {
+            return Ok(style);
+        }
+
+        puffin::profile_scope!("build style buffers");
+        let style = Arc::new(StyleBuffers::from_dataframe(computed_dataframe, &columns)?);
+        ctx.memory_mut(|mem| {
+            mem.caches
+                .cache::<GeometryCache>()
+                .insert_style(id, generation, columns, style.clone())
+        });
+        Ok(style)
+    }
+
+    fn highlighted_segments(&self, ctx: &Context, bag: &Bag) -> anyhow::Result<Vec<Line>> {
+        let dataframe = self.dataframe_view.table.dataframe(bag)?;
+        let special_column = dataframe
+            .special_columns
+            .get(&self.column)
+            .with_context(|| format!("special column not found: {}", self.column))?;
+        let Some(state) = ctx.memory_mut(|mem| {
+            mem.data
+                .get_temp::<Arc<Mutex<FlTableState>>>(self.dataframe_view.table.data_id(bag).ok()?)
+        }) else {
+            return Ok(vec![]);
+        };
+        let highlight = state.lock().unwrap().highlight.clone();
+        let series = dataframe
+            .value
+            .column(self.column.as_str())?
+            .as_series()
+            .context("not a series")?
+            .clone();
+        let transform = self
+            .transform
+            .resolve(bag, &self.dataframe_view.table.data_reference);
+
+        Ok(parse_shapes(&series, special_column)?
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| highlight.contains(&(*i as u64)))
+            .filter_map(|(_, shape)| shape.as_ref())
+            .flat_map(|shape| shape.measure_segments())
+            .map(|line| transform_line(&transform, &line))
+            .collect_vec())
     }
 }
 
+/// 特殊列の各値を図形にする。null の行は `None` になる
+fn parse_shapes(
+    series: &Series,
+    special_column: &FlDataFrameSpecialColumn,
+) -> Result<Vec<Option<Box<dyn SpecialColumnShape>>>, FlShapeConvertError> {
+    series
+        .iter()
+        .map(|x| match special_column {
+            FlDataFrameSpecialColumn::Rectangle => FlDataFrameRectangle::try_from(x.clone())
+                .map(|x| Box::new(x) as Box<dyn SpecialColumnShape>),
+            FlDataFrameSpecialColumn::Segment => FlDataFrameSegment::try_from(x.clone())
+                .map(|x| Box::new(x) as Box<dyn SpecialColumnShape>),
+            _ => Err(FlShapeConvertError::CanNotConvert),
+        })
+        .map(|x| {
+            x.map(Some).or_else(|e| match e {
+                FlShapeConvertError::NullValue => Ok(None),
+                _ => Err(e),
+            })
+        })
+        .collect()
+}
+
 fn visualize(
     ui: &mut Ui,
     bag: &Bag,
@@ -1043,6 +1466,14 @@ fn visualize(
         render
             .render(ui, bag, &mut painter, visualize_state)
             .unwrap();
+        inspect_renders(
+            ui,
+            bag,
+            visualize_state,
+            &response,
+            &mut painter,
+            std::iter::once(render),
+        );
 
         response
     });
@@ -1054,45 +1485,23 @@ fn stack_visualize(
     ui: &mut Ui,
     bag: &Bag,
     visualize_state: &mut VisualizeState,
+    compare: &mut StackCompareState,
     stack: &[Arc<DataRender>],
+    layers: &[LayerSettings],
 ) -> Response {
     assert_ne!(stack.len(), 0);
     let responses = ui.centered_and_justified(|ui| {
-        let stack_top = stack.first().unwrap();
         let (response, mut painter) = ui.allocate_painter(ui.available_size(), Sense::drag());
 
-        stack_top
-            .render(ui, bag, &mut painter, visualize_state)
+        compare
+            .render(ui, bag, &mut painter, visualize_state, stack, layers)
             .unwrap();
-        let mut segments = vec![];
-        segments.extend(stack_top.measurable_segments(ui.ctx(), bag).unwrap());
-        for (_i, render) in stack.iter().enumerate().skip(1) {
-            render
-                .render(ui, bag, &mut painter, visualize_state)
-                .unwrap();
-            segments.extend(render.measurable_segments(ui.ctx(), bag).unwrap());
-        }
-
-        let tile_origin_pos = response
-            .hover_pos()
-            .map(|pos| pos - response.rect.min.to_vec2());
-        let absolute_pos =
-            tile_origin_pos.map(|pos| visualize_state.screen_to_absolute(pos.to_vec2()));
-
-        // 検査を行っている部分のコード
-        let command = ui.ctx().input(|input| input.modifiers.command_only());
-        if let Some(absolute_pos) = absolute_pos {
-            if command {
-                inspection(
-                    &response.rect,
-                    visualize_state,
-                    &mut painter,
-                    ui,
-                    &segments,
-                    absolute_pos,
-                );
-            }
-        }
+        let drawn = stack
+            .iter()
+            .zip(drawn_layers(layers))
+            .filter(|(_, drawn)| *drawn)
+            .map(|(render, _)| render.as_ref());
+        inspect_renders(ui, bag, visualize_state, &response, &mut painter, drawn);
 
         response
     });
@@ -1100,6 +1509,46 @@ fn stack_visualize(
     responses.inner
 }
 
+/// 検査モードの時に、描画した `renders` の線分と座標変換を使ってポインタ位置の検査を行う
+fn inspect_renders<'a>(
+    ui: &mut Ui,
+    bag: &Bag,
+    visualize_state: &VisualizeState,
+    response: &Response,
+    painter: &mut Painter,
+    renders: impl Iterator<Item = &'a DataRender>,
+) {
+    if !visualize_state.inspecting {
+        return;
+    }
+    let Some(absolute_pos) = response.hover_pos().map(|pos| {
+        let tile_origin_pos = pos - response.rect.min.to_vec2();
+        visualize_state.screen_to_absolute(tile_origin_pos.to_vec2())
+    }) else {
+        return;
+    };
+
+    let mut segments = vec![];
+    let mut layer_transforms = vec![];
+    for render in renders {
+        segments.extend(render.measurable_segments(ui.ctx(), bag).unwrap());
+        let transform = render.transform(bag);
+        if !transform.is_identity() {
+            layer_transforms.push((render.reference().name, transform));
+        }
+    }
+
+    inspection(
+        &response.rect,
+        visualize_state,
+        painter,
+        ui,
+        &segments,
+        &layer_transforms,
+        absolute_pos,
+    );
+}
+
 /// 検査モードのUIを描画する関数
 fn inspection(
     view_rect: &Rect,
@@ -1107,12 +1556,19 @@ fn inspection(
     painter: &mut Painter,
     ui: &mut Ui,
     segments: &[Line],
+    layer_transforms: &[(String, FlAffineTransform)],
     absolute_pos: Vec2,
 ) {
     // TODO(higumachan): リファクタリングしたい
     // Display coordinates in inspection mode
+    // 変換されたレイヤーについては変換前の座標も併記する
     let text_pos = view_rect.min + visualize_state.absolute_to_screen(absolute_pos);
-    let coord_label = format!("x={:.1}, y={:.1}", absolute_pos.x, absolute_pos.y);
+    let coord_label = std::iter::once(format!("x={:.1}, y={:.1}", absolute_pos.x, absolute_pos.y))
+        .chain(layer_transforms.iter().map(|(name, transform)| {
+            let pos = inverse_transform_pos(transform, absolute_pos);
+            format!("{}: x={:.1}, y={:.1}", name, pos.x, pos.y)
+        }))
+        .join("\n");
 
     // Layout the text to measure its size
     let galley = painter.layout_no_wrap(coord_label.clone(), FontId::default(), Color32::BLACK);
@@ -1154,6 +1610,8 @@ fn inspection(
         }
     }
 
+    // 右クリックで計測結果を固定する
+    let pin = ui.input(|input| input.pointer.secondary_clicked());
     if ui.input(|input| input.pointer.primary_clicked()) {
         if let Some((pos, min_distance)) = d {
             if min_distance < 5.0 {
@@ -1293,9 +1751,91 @@ fn inspection(
             }
             _ => {}
         }
+
+        if pin {
+            // 選択した線分の近くに別の線分があればそのなす角、なければ点との距離
+            let measurement = match d.filter(|(pos, min_distance)| {
+                *min_distance < 5.0 && !same_line_parameter(&selected_segment, &segments[*pos])
+            }) {
+                Some((pos, _)) => Measurement::Angle {
+                    a: from_line(&selected_segment),
+                    b: from_line(&segments[pos]),
+                },
+                None => Measurement::PointToSegment {
+                    point: to_point(to),
+                    segment: from_line(&selected_segment),
+                },
+            };
+            visualize_state.measurements.pin(measurement);
+        }
+    } else {
+        // 線分を選択していない場合は右クリックした 2 点間の距離を測る
+        let start = ui
+            .ctx()
+            .data(|data| data.get_temp::<Pos2>(measure_start_id()));
+        let end = absolute_pos.to_pos2();
+        if let Some(start) = start {
+            Measurement::Distance {
+                from: to_point(start),
+                to: to_point(end),
+            }
+            .draw(painter, visualize_state, view_rect.min.to_vec2());
+        }
+        let cancel = ui.input(|input| input.key_pressed(Key::Escape));
+        ui.ctx().data_mut(|data| match (pin, start) {
+            (true, Some(start)) => {
+                visualize_state.measurements.pin(Measurement::Distance {
+                    from: to_point(start),
+                    to: to_point(end),
+                });
+                data.remove::<Pos2>(measure_start_id());
+            }
+            (true, None) => data.insert_temp(measure_start_id(), end),
+            (false, Some(_)) if cancel => data.remove::<Pos2>(measure_start_id()),
+            _ => {}
+        });
     }
 }
 
+/// 値を min-max で正規化し viridis で着色した PNG 画像を作る
+pub(crate) fn colormap_image(values: &Array2<f64>) -> FlImage {
+    let image = DynamicImage::ImageRgb8(colormap_pixels(values));
+
+    let mut image_png_bytes = Vec::new();
+    let mut cursor = Cursor::new(&mut image_png_bytes);
+    image
+        .write_to(&mut cursor, image::ImageOutputFormat::Png)
+        .unwrap();
+    FlImage::new(
+        image_png_bytes,
+        image.width() as usize,
+        image.height() as usize,
+    )
+}
+
+fn colormap_pixels(values: &Array2<f64>) -> RgbImage {
+    let cm = scarlet::colormap::ListedColorMap::viridis();
+    let max = values
+        .iter()
+        .copied()
+        .max_by_key(|t| UnwrapOrd(*t))
+        .unwrap();
+    let min = values
+        .iter()
+        .copied()
+        .min_by_key(|t| UnwrapOrd(*t))
+        .unwrap();
+    // 一定値の場合は 0 にする
+    let range = if max > min { max - min } else { 1.0 };
+    let normalize = move |v| (v - min) / range;
+    let transformed: Vec<RGBColor> = cm.transform(values.iter().map(|v| normalize(*v)));
+    let pixels: Vec<u8> = transformed
+        .into_iter()
+        .flat_map(|c| [c.int_r(), c.int_g(), c.int_b()])
+        .collect();
+    ImageBuffer::from_vec(values.shape()[1] as u32, values.shape()[0] as u32, pixels).unwrap()
+}
+
 fn draw_segment(
     painter: &mut Painter,
     segment: &Line,
@@ -1311,11 +1851,15 @@ fn draw_segment(
     painter.line_segment([from, to], stroke);
 }
 
-fn draw_image(
+/// `offset` と `size` は変換前のデータ座標で指定する
+/// 回転に対応するため、変換後の4隅を頂点とするメッシュとして描画する
+pub(crate) fn draw_image(
     painter: &mut Painter,
     image: &Image,
-    shift: Vec2,
+    state: &VisualizeState,
+    offset: Vec2,
     size: Vec2,
+    transform: &FlAffineTransform,
     tint_color: Color32,
 ) -> anyhow::Result<()> {
     match image
@@ -1323,11 +1867,8 @@ fn draw_image(
         .context("load image")?
     {
         TexturePoll::Ready { texture } => {
-            painter.image(
-                texture.id,
-                Rect::from_min_size(painter.clip_rect().min + shift, size),
-                Rect::from_min_size(Pos2::ZERO, Vec2::new(1.0, 1.0)),
-                tint_color,
+            draw_texture(
+                painter, texture.id, state, offset, size, transform, tint_color,
             );
         }
         TexturePoll::Pending { .. } => {}
@@ -1335,6 +1876,47 @@ fn draw_image(
     Ok(())
 }
 
+/// 描画できなかった理由などをペインの中央に表示する
+pub(crate) fn draw_message(painter: &Painter, message: &str) {
+    painter.text(
+        painter.clip_rect().center(),
+        Align2::CENTER_CENTER,
+        message,
+        FontId::default(),
+        painter.ctx().style().visuals.error_fg_color,
+    );
+}
+
+/// テクスチャ全体を `offset` から大きさ `size` の範囲に変換して描画する
+pub(crate) fn draw_texture(
+    painter: &mut Painter,
+    texture_id: TextureId,
+    state: &VisualizeState,
+    offset: Vec2,
+    size: Vec2,
+    transform: &FlAffineTransform,
+    tint_color: Color32,
+) {
+    let origin = painter.clip_rect().min;
+    let mut mesh = Mesh::with_texture(texture_id);
+    for uv in [
+        Pos2::new(0.0, 0.0),
+        Pos2::new(1.0, 0.0),
+        Pos2::new(1.0, 1.0),
+        Pos2::new(0.0, 1.0),
+    ] {
+        let pos = transform_pos(transform, offset + uv.to_vec2() * size);
+        mesh.vertices.push(egui::epaint::Vertex {
+            pos: origin + state.absolute_to_screen(pos),
+            uv,
+            color: tint_color,
+        });
+    }
+    mesh.add_triangle(0, 1, 2);
+    mesh.add_triangle(0, 2, 3);
+    painter.add(Shape::mesh(mesh));
+}
+
 fn calc_transparent_color(color: Color32, transparent: f64) -> Color32 {
     let alpha = 1.0 - transparent;
     let color_array = color
@@ -1350,7 +1932,7 @@ fn calc_transparent_color(color: Color32, transparent: f64) -> Color32 {
     )
 }
 
-fn serise_value_to_color(field: &Field, value: &AnyValue) -> Color32 {
+pub(crate) fn serise_value_to_color(field: &Field, value: &AnyValue) -> Color32 {
     match &field.dtype {
         DataType::Struct(inner_field) => {
             if FlDataFrameColor::validate_fields(inner_field) {
@@ -1436,4 +2018,81 @@ mod tests {
 
     #[test]
     fn it_works() {}
+
+    #[test]
+    fn unchanged_table_state_reuses_buffers() {
+        use flexim_data_type::{FlDataFrame, FlDataType, GenerationSelector};
+        use flexim_storage::{Storage, StorageQuery};
+        use flexim_table_widget::FlTableDrawContext;
+
+        let storage = Storage::default();
+        let bag_id = storage.create_bag("test".to_string());
+        let dataframe = FlDataFrame::try_with_special_columns(
+            polars::df!(
+                "Face" => [r#"{"x1": 0, "y1": 0, "x2": 10, "y2": 10}"#, r#"{"x1": 5, "y1": 5, "x2": 20, "y2": 20}"#],
+                "Name" => ["a", "b"],
+            )
+            .unwrap(),
+            [("Face".to_string(), FlDataFrameSpecialColumn::Rectangle)]
+                .into_iter()
+                .collect(),
+        )
+        .unwrap();
+        storage
+            .insert_data(
+                bag_id,
+                "faces".to_string(),
+                FlData::DataFrame(Arc::new(dataframe)),
+            )
+            .unwrap();
+        let bag = storage.get_bag(bag_id).unwrap();
+        let bag = bag.read().unwrap();
+        let render = FlDataFrameViewRender::new(
+            FlDataFrameView::new(FlDataReference::new(
+                "faces".to_string(),
+                GenerationSelector::Latest,
+                FlDataType::DataFrame,
+            )),
+            "Face".to_string(),
+        );
+        render.render_context.lock().unwrap().label_column = Some("Name".to_string());
+
+        let ctx = Context::default();
+        let frame = || {
+            let mut buffers = None;
+            let _ = ctx.run(Default::default(), |ctx| {
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    render
+                        .dataframe_view
+                        .table
+                        .draw(ui, &bag, &FlTableDrawContext::default());
+                });
+                buffers = Some(render.buffers(ctx, &bag).unwrap());
+            });
+            buffers.unwrap()
+        };
+        // 別スレッドのフィルタ計算が終わるまで待つ
+        frame();
+        for _ in 0..100 {
+            if render
+                .dataframe_view
+                .table
+                .computed_dataframe_with_generation(&ctx, &bag)
+                .is_some()
+            {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        let (mut geometry, mut style) = frame();
+        assert_eq!(geometry.len(), 2);
+
+        for _ in 0..3 {
+            let (next_geometry, next_style) = frame();
+            assert!(Arc::ptr_eq(&geometry, &next_geometry));
+            assert!(Arc::ptr_eq(&style, &next_style));
+            (geometry, style) = (next_geometry, next_style);
+        }
+    }
 }
@@ -0,0 +1,140 @@
+use crate::layer::{drawn_layers, LayerSettings};
+use crate::visualize::{DataRender, VisualizeState};
+use egui::{Context, Id, Pos2, Rect, Vec2};
+use flexim_config::Config;
+use flexim_storage::Bag;
+use geo::Line;
+use std::sync::Arc;
+
+/// 全体を表示する時に端に残す余白の割合
+const FIT_MARGIN: f32 = 0.95;
+
+/// 表示範囲の変更の要求
+#[derive(Debug, Clone, Copy)]
+pub enum ZoomRequest {
+    /// 描画しているレイヤー全体が収まるようにする
+    Fit,
+    /// 表示の中心を保って等倍にする
+    OneToOne,
+    /// テーブルで選択した行が収まるようにする
+    Highlighted,
+    /// 絶対座標の範囲が収まるようにする
+    Region(Rect),
+}
+
+fn request_id(id: Id) -> Id {
+    id.with("zoom request")
+}
+
+/// 次のフレームの描画後に `id` の可視化の表示範囲を変える
+pub fn request_zoom(ctx: &Context, id: Id, request: ZoomRequest) {
+    ctx.data_mut(|data| data.insert_temp(request_id(id), request));
+}
+
+pub(crate) fn take_zoom_request(ctx: &Context, id: Id) -> Option<ZoomRequest> {
+    ctx.data_mut(|data| {
+        let request = data.get_temp::<ZoomRequest>(request_id(id));
+        data.remove::<ZoomRequest>(request_id(id));
+        request
+    })
+}
+
+/// 線分全体を囲む範囲
+pub(crate) fn segments_bounds<'a>(segments: impl IntoIterator<Item = &'a Line>) -> Option<Rect> {
+    let points = segments
+        .into_iter()
+        .flat_map(|line| [line.start, line.end])
+        .map(|c| Pos2::new(c.x as f32, c.y as f32))
+        .collect::<Vec<_>>();
+    (!points.is_empty()).then(|| Rect::from_points(&points))
+}
+
+impl VisualizeState {
+    /// 絶対座標の `pos` が大きさ `view_size` の描画領域の中央に来るようにする
+    pub fn center_on(&mut self, pos: Vec2, view_size: Vec2) {
+        self.shift = view_size / 2.0 - pos * self.scale();
+    }
+
+    /// 絶対座標の `region` が大きさ `view_size` の描画領域に収まるようにする
+    pub fn fit(&mut self, region: Rect, view_size: Vec2, config: &Config) {
+        let size = region.size();
+        if size.x > 0.0 || size.y > 0.0 {
+            let scale = (view_size.x / size.x).min(view_size.y / size.y) * FIT_MARGIN;
+            self.current_scale = scale.clamp(config.zoom_lower_limit, config.zoom_upper_limit);
+        }
+        self.center_on(region.center().to_vec2(), view_size);
+    }
+
+    pub fn one_to_one(&mut self, view_size: Vec2) {
+        let center = self.screen_to_absolute(view_size / 2.0);
+        self.current_scale = 1.0;
+        self.center_on(center, view_size);
+    }
+
+    pub(crate) fn apply_zoom_request(
+        &mut self,
+        request: ZoomRequest,
+        ctx: &Context,
+        bag: &Bag,
+        contents: &[Arc<DataRender>],
+        layers: &[LayerSettings],
+        view_size: Vec2,
+    ) {
+        let drawn = || {
+            contents
+                .iter()
+                .zip(drawn_layers(layers))
+                .filter(|(_, drawn)| *drawn)
+                .map(|(render, _)| render)
+        };
+        let region = match request {
+            ZoomRequest::Fit => segments_bounds(
+                &drawn()
+                    .filter_map(|render| render.measurable_segments(ctx, bag).ok())
+                    .flatten()
+                    .collect::<Vec<_>>(),
+            ),
+            ZoomRequest::Highlighted => segments_bounds(
+                &drawn()
+                    .filter_map(|render| render.highlighted_segments(ctx, bag).ok())
+                    .flatten()
+                    .collect::<Vec<_>>(),
+            ),
+            ZoomRequest::Region(region) => Some(region),
+            ZoomRequest::OneToOne => {
+                self.one_to_one(view_size);
+                return;
+            }
+        };
+        if let Some(region) = region {
+            self.fit(region, view_size, &Config::get_global_ctx(ctx));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::visualize::Origin;
+    use geo::coord;
+
+    #[test]
+    fn fit_centers_region() {
+        let config = Config::default();
+        for origin in [Origin::TopLeft, Origin::BottomLeft] {
+            let mut state = VisualizeState::load(&Context::default(), Id::new("fit"));
+            state.origin = origin;
+            let segments = [
+                Line::new(coord!(x: 0.0, y: 0.0), coord!(x: 4000.0, y: 0.0)),
+                Line::new(coord!(x: 0.0, y: 0.0), coord!(x: 0.0, y: 2000.0)),
+            ];
+            let region = segments_bounds(&segments).unwrap();
+            let view_size = Vec2::new(800.0, 800.0);
+            state.fit(region, view_size, &config);
+
+            assert!((state.current_scale - 0.2 * FIT_MARGIN).abs() < 1e-6);
+            let center = state.absolute_to_screen(region.center().to_vec2());
+            assert!((center - view_size / 2.0).length() < 1e-3);
+        }
+    }
+}
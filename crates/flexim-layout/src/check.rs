@@ -81,6 +81,7 @@ mod tests {
                     FlImage::try_from_bytes(include_bytes!("../../../assets/logo.png").to_vec())
                         .unwrap(),
                 )),
+                transform: None,
             }],
             generation_counter: std::collections::HashMap::new(),
         }
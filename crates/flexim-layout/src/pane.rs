@@ -2,6 +2,8 @@ use flexim_data_type::{FlDataReference, FlDataType};
 use flexim_data_view::object::FlObjectView;
 use flexim_data_view::FlDataFrameView;
 use flexim_data_visualize::data_view::DataView;
+use flexim_data_visualize::layer::LayerSettings;
+use flexim_data_visualize::measurement::Measurements;
 use flexim_data_visualize::visualize::{DataRender, FlImageRender, FlTensor2DRender};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
@@ -25,11 +27,22 @@ impl PaneContent {
 pub struct Pane {
     pub name: String,
     pub content: PaneContent,
+    /// スタックタブのレイヤーとしての表示設定
+    #[serde(default)]
+    pub layer: LayerSettings,
+    /// 可視化のペインで固定した計測結果
+    #[serde(default)]
+    pub measurements: Measurements,
 }
 
 impl Pane {
     pub fn new(name: String, content: PaneContent) -> Self {
-        Self { name, content }
+        Self {
+            name,
+            content,
+            layer: LayerSettings::default(),
+            measurements: Measurements::default(),
+        }
     }
 }
 
@@ -42,7 +55,7 @@ pub fn into_pane_content(fl_data_reference: FlDataReference) -> anyhow::Result<P
             FlTensor2DRender::new(fl_data_reference).into(),
         ))),
         FlDataType::DataFrame => Ok(PaneContent::DataView(Arc::new(DataView::FlDataFrameView(
-            FlDataFrameView::new(fl_data_reference),
+            Box::new(FlDataFrameView::new(fl_data_reference)),
         )))),
         FlDataType::Object => Ok(PaneContent::DataView(Arc::new(DataView::FlObjectView(
             FlObjectView::new(fl_data_reference),
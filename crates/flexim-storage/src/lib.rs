@@ -1,11 +1,16 @@
 use anyhow::Context as _;
 use chrono::{DateTime, Utc};
-use flexim_data_type::{FlData, FlDataReference, GenerationSelector};
+use flexim_data_type::{FlAffineTransform, FlData, FlDataReference, GenerationSelector};
 use rand::random;
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, HashMap};
+use std::io::{Cursor, Read, Write};
 use std::sync::{Arc, RwLock};
 
+/// `.bag` ファイルの先頭に付ける識別子。これがないファイルは変換を持たない以前の形式として読む
+const BAG_FILE_MAGIC: &[u8; 8] = b"FLXMBAG\0";
+const BAG_FILE_VERSION: u32 = 1;
+
 pub trait StorageQuery {
     fn list_bags(&self) -> anyhow::Result<Vec<Arc<RwLock<Bag>>>>;
     fn get_bag(&self, bag_id: BagId) -> anyhow::Result<Arc<RwLock<Bag>>>;
@@ -29,6 +34,9 @@ pub struct ManagedData {
     pub generation: u64,
     pub name: String,
     pub data: FlData,
+    /// アップロード時に指定された表示用の変換
+    #[serde(default)]
+    pub transform: Option<FlAffineTransform>,
 }
 
 impl From<ManagedData> for FlDataReference {
@@ -50,7 +58,73 @@ pub struct Bag {
     pub generation_counter: HashMap<String, u64>,
 }
 
+/// 変換を持たない以前の形式の `ManagedData`
+#[derive(Deserialize)]
+struct LegacyManagedData {
+    generation: u64,
+    name: String,
+    data: FlData,
+}
+
+/// 変換を持たない以前の形式の `Bag`
+#[derive(Deserialize)]
+struct LegacyBag {
+    id: BagId,
+    name: String,
+    created_at: DateTime<Utc>,
+    data_list: Vec<LegacyManagedData>,
+    generation_counter: HashMap<String, u64>,
+}
+
+impl From<LegacyBag> for Bag {
+    fn from(value: LegacyBag) -> Self {
+        Self {
+            id: value.id,
+            name: value.name,
+            created_at: value.created_at,
+            data_list: value
+                .data_list
+                .into_iter()
+                .map(|data| ManagedData {
+                    generation: data.generation,
+                    name: data.name,
+                    data: data.data,
+                    transform: None,
+                })
+                .collect(),
+            generation_counter: value.generation_counter,
+        }
+    }
+}
+
 impl Bag {
+    /// `.bag` ファイルとして書き出す。先頭に識別子と形式のバージョンを付ける
+    pub fn write_to(&self, mut writer: impl Write) -> anyhow::Result<()> {
+        writer.write_all(BAG_FILE_MAGIC)?;
+        writer.write_all(&BAG_FILE_VERSION.to_le_bytes())?;
+        bincode::serialize_into(writer, self)?;
+        Ok(())
+    }
+
+    /// `.bag` ファイルを読み込む。識別子のない以前の形式のファイルも読める
+    pub fn read_from(mut reader: impl Read) -> anyhow::Result<Self> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != BAG_FILE_MAGIC {
+            let legacy: LegacyBag = bincode::deserialize_from(Cursor::new(magic).chain(reader))?;
+            return Ok(legacy.into());
+        }
+        let mut version = [0u8; 4];
+        reader.read_exact(&mut version)?;
+        let version = u32::from_le_bytes(version);
+        anyhow::ensure!(
+            version == BAG_FILE_VERSION,
+            "unsupported bag file version: {}",
+            version
+        );
+        Ok(bincode::deserialize_from(reader)?)
+    }
+
     pub fn data_groups(&self) -> BTreeMap<String, Vec<&ManagedData>> {
         let mut data_groups = BTreeMap::new();
         for data in &self.data_list {
@@ -64,12 +138,26 @@ impl Bag {
     }
 
     pub fn data_by_reference(&self, reference: &FlDataReference) -> anyhow::Result<FlData> {
+        Ok(self.managed_data_by_reference(reference)?.data.clone())
+    }
+
+    pub fn transform_by_reference(
+        &self,
+        reference: &FlDataReference,
+    ) -> anyhow::Result<Option<FlAffineTransform>> {
+        Ok(self.managed_data_by_reference(reference)?.transform)
+    }
+
+    fn managed_data_by_reference(
+        &self,
+        reference: &FlDataReference,
+    ) -> anyhow::Result<&ManagedData> {
         let mut name_filtered = self
             .data_list
             .iter()
             .filter(|data| data.name == reference.name);
 
-        Ok((match reference.generation {
+        match reference.generation {
             GenerationSelector::Latest => {
                 let data = name_filtered.max_by_key(|data| data.generation);
                 data.context("data not found")
@@ -78,9 +166,7 @@ impl Bag {
                 let data = name_filtered.find(|data| data.generation == generation);
                 data.context("data not found")
             }
-        })?
-        .data
-        .clone())
+        }
     }
 }
 
@@ -129,6 +215,16 @@ impl Storage {
     }
 
     pub fn insert_data(&self, bag_id: BagId, name: String, data: FlData) -> anyhow::Result<()> {
+        self.insert_data_with_transform(bag_id, name, data, None)
+    }
+
+    pub fn insert_data_with_transform(
+        &self,
+        bag_id: BagId,
+        name: String,
+        data: FlData,
+        transform: Option<FlAffineTransform>,
+    ) -> anyhow::Result<()> {
         log::info!("insert_data: bag_id={:?}, name={}", bag_id, name);
 
         let bags = self.bags.read().unwrap();
@@ -141,6 +237,7 @@ impl Storage {
             generation,
             name,
             data,
+            transform,
         });
         Ok(())
     }
@@ -161,14 +258,9 @@ impl Storage {
         let mut bag_groups = BTreeMap::new();
 
         for (name, bag_version) in bag_versions {
-            let (group_key, bag_name) = if name.contains('/') {
-                let mut parts = name.splitn(2, '/');
-                (
-                    parts.next().unwrap().to_string(),
-                    parts.next().unwrap().to_string(),
-                )
-            } else {
-                (name.to_string(), name.to_string())
+            let (group_key, bag_name) = match name.split_once('/') {
+                Some((group_key, bag_name)) => (group_key.to_string(), bag_name.to_string()),
+                None => (name.to_string(), name.to_string()),
             };
 
             let bag_versions = bag_groups.entry(group_key).or_insert(BTreeMap::new());
@@ -179,6 +271,11 @@ impl Storage {
     }
 }
 
+/// `bag_groups` で Bag をまとめるグループ名。`/` より前の部分で、`/` がなければ Bag の名前そのもの
+pub fn bag_group_name(name: &str) -> &str {
+    name.split_once('/').map_or(name, |(group, _)| group)
+}
+
 impl StorageQuery for Storage {
     fn list_bags(&self) -> anyhow::Result<Vec<Arc<RwLock<Bag>>>> {
         let bags = self.bags.read().unwrap();
@@ -200,3 +297,95 @@ fn gen_id() -> u64 {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flexim_data_type::FlImage;
+
+    /// 変換を追加する前の `ManagedData` と同じ並び
+    #[derive(Serialize)]
+    struct BaselineManagedData {
+        generation: u64,
+        name: String,
+        data: FlData,
+    }
+
+    /// 変換を追加する前の `Bag` と同じ並び
+    #[derive(Serialize)]
+    struct BaselineBag {
+        id: BagId,
+        name: String,
+        created_at: DateTime<Utc>,
+        data_list: Vec<BaselineManagedData>,
+        generation_counter: HashMap<String, u64>,
+    }
+
+    fn image() -> FlData {
+        FlImage::new(vec![1, 2, 3, 4], 1, 1).into()
+    }
+
+    #[test]
+    fn read_baseline_bag_file() {
+        let baseline = BaselineBag {
+            id: BagId::new(42),
+            name: "baseline".to_string(),
+            created_at: Utc::now(),
+            data_list: vec![
+                BaselineManagedData {
+                    generation: 0,
+                    name: "image".to_string(),
+                    data: image(),
+                },
+                BaselineManagedData {
+                    generation: 1,
+                    name: "image".to_string(),
+                    data: image(),
+                },
+            ],
+            generation_counter: [("image".to_string(), 2)].into_iter().collect(),
+        };
+        let bytes = bincode::serialize(&baseline).unwrap();
+
+        let bag = Bag::read_from(bytes.as_slice()).unwrap();
+        assert_eq!(bag.id, BagId::new(42));
+        assert_eq!(bag.name, "baseline");
+        assert_eq!(bag.data_list.len(), 2);
+        assert_eq!(bag.data_list[1].generation, 1);
+        assert!(bag.data_list.iter().all(|data| data.transform.is_none()));
+        assert_eq!(bag.generation_counter["image"], 2);
+    }
+
+    #[test]
+    fn write_and_read_bag_file_with_transform() {
+        let transform = FlAffineTransform {
+            offset_x: 5.0,
+            rotation: 90.0,
+            ..Default::default()
+        };
+        let bag = Bag {
+            id: BagId::new(7),
+            name: "current".to_string(),
+            created_at: Utc::now(),
+            data_list: vec![ManagedData {
+                generation: 0,
+                name: "image".to_string(),
+                data: image(),
+                transform: Some(transform),
+            }],
+            generation_counter: [("image".to_string(), 1)].into_iter().collect(),
+        };
+        let mut bytes = vec![];
+        bag.write_to(&mut bytes).unwrap();
+
+        let read = Bag::read_from(bytes.as_slice()).unwrap();
+        assert_eq!(read.id, bag.id);
+        assert_eq!(read.data_list[0].transform, Some(transform));
+    }
+
+    #[test]
+    fn group_name_is_prefix_before_slash() {
+        assert_eq!(bag_group_name("experiment/run/1"), "experiment");
+        assert_eq!(bag_group_name("experiment"), "experiment");
+    }
+}
@@ -87,7 +87,7 @@ impl FilteredDataFrameCache {
                 CacheState::Calculating(mut calc) => Calculating {
                     generation: calc.generation.wrapping_add(1),
                     previous: calc.previous.take(),
-                    previous_generation: Some(calc.generation),
+                    previous_generation: calc.previous_generation,
                 },
             }
         } else {
@@ -117,6 +117,29 @@ impl FilteredDataFrameCache {
     }
 }
 
+impl FilteredDataFrameCache {
+    /// `get` が返すデータフレームとその世代
+    pub fn get_with_generation(&self, id: Id) -> Option<(u64, DataFrame)> {
+        match self.cached_dataframes.get(&id)? {
+            CacheState::Ready {
+                generation,
+                dataframe,
+            } => Some((*generation, dataframe.clone())),
+            CacheState::Calculating(calc) => {
+                Some((calc.previous_generation?, calc.previous.clone()?))
+            }
+        }
+    }
+
+    /// `get` が返すデータフレームの世代
+    pub fn generation(&self, id: Id) -> Option<u64> {
+        match self.cached_dataframes.get(&id)? {
+            CacheState::Ready { generation, .. } => Some(*generation),
+            CacheState::Calculating(calc) => calc.previous.as_ref().and(calc.previous_generation),
+        }
+    }
+}
+
 impl CacheTrait for FilteredDataFrameCache {
     fn update(&mut self) {}
 
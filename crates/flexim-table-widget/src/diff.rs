@@ -0,0 +1,349 @@
+use anyhow::{ensure, Context as _};
+use enum_iterator::Sequence;
+use flexim_data_type::{FlDataFrame, FlDataFrameRectangle, FlDataFrameSpecialColumn};
+use itertools::Itertools;
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::{Display, Formatter};
+
+pub const DIFF_STATUS_COLUMN: &str = "diff_status";
+pub const DIFF_COLOR_COLUMN: &str = "diff_color";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Sequence)]
+pub enum DiffStatus {
+    Added,
+    Removed,
+    Changed,
+    Unchanged,
+}
+
+impl Display for DiffStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Added => write!(f, "added"),
+            Self::Removed => write!(f, "removed"),
+            Self::Changed => write!(f, "changed"),
+            Self::Unchanged => write!(f, "unchanged"),
+        }
+    }
+}
+
+impl DiffStatus {
+    /// オーバーレイ表示用の色 (r, g, b)
+    pub fn color(&self) -> (f64, f64, f64) {
+        match self {
+            Self::Added => (0.0, 0.8, 0.0),
+            Self::Removed => (1.0, 0.0, 0.0),
+            Self::Changed => (1.0, 0.6, 0.0),
+            Self::Unchanged => (0.5, 0.5, 0.5),
+        }
+    }
+}
+
+/// 2つの DataFrame の行の対応付け方法
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DiffMatching {
+    /// 同じ値を持つ行を対応させる
+    Key(String),
+    /// Rectangle の IoU が `threshold` 以上のものを IoU の大きい順に対応させる
+    Iou { column: String, threshold: f64 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffRow {
+    pub base: Option<usize>,
+    pub target: Option<usize>,
+    pub status: DiffStatus,
+}
+
+/// `target` の行順に並べ、最後に `base` にしかない行を並べる
+pub fn match_rows(
+    base: &FlDataFrame,
+    target: &FlDataFrame,
+    matching: &DiffMatching,
+) -> anyhow::Result<Vec<DiffRow>> {
+    ensure!(
+        base.value.get_column_names() == target.value.get_column_names()
+            && base.value.dtypes() == target.value.dtypes(),
+        "schemas of the dataframes differ"
+    );
+
+    let pairs = match matching {
+        DiffMatching::Key(column) => match_by_key(&base.value, &target.value, column)?,
+        DiffMatching::Iou { column, threshold } => {
+            ensure!(
+                target.special_columns.get(column) == Some(&FlDataFrameSpecialColumn::Rectangle),
+                "{} is not a rectangle column",
+                column
+            );
+            match_by_iou(&base.value, &target.value, column, *threshold)?
+        }
+    };
+
+    let mut rows = vec![];
+    for (target_index, base_index) in pairs.iter().enumerate() {
+        let status = match base_index {
+            None => DiffStatus::Added,
+            Some(b) if base.value.get_row(*b)? == target.value.get_row(target_index)? => {
+                DiffStatus::Unchanged
+            }
+            Some(_) => DiffStatus::Changed,
+        };
+        rows.push(DiffRow {
+            base: *base_index,
+            target: Some(target_index),
+            status,
+        });
+    }
+    let matched = pairs.iter().flatten().copied().collect::<HashSet<_>>();
+    for b in (0..base.value.height()).filter(|b| !matched.contains(b)) {
+        rows.push(DiffRow {
+            base: Some(b),
+            target: None,
+            status: DiffStatus::Removed,
+        });
+    }
+    Ok(rows)
+}
+
+/// target の各行に対応する base の行
+fn match_by_key(
+    base: &DataFrame,
+    target: &DataFrame,
+    column: &str,
+) -> anyhow::Result<Vec<Option<usize>>> {
+    let keys = |df: &DataFrame| -> anyhow::Result<Vec<Option<String>>> {
+        Ok(df
+            .column(column)
+            .with_context(|| format!("column not found: {}", column))?
+            .as_materialized_series()
+            .iter()
+            .map(|v| (!v.is_null()).then(|| v.to_string()))
+            .collect())
+    };
+    // 重複したキーは出現順に対応させる
+    let mut base_rows: HashMap<String, VecDeque<usize>> = HashMap::new();
+    for (i, key) in keys(base)?.into_iter().enumerate() {
+        if let Some(key) = key {
+            base_rows.entry(key).or_default().push_back(i);
+        }
+    }
+    Ok(keys(target)?
+        .into_iter()
+        .map(|key| base_rows.get_mut(&key?)?.pop_front())
+        .collect())
+}
+
+fn match_by_iou(
+    base: &DataFrame,
+    target: &DataFrame,
+    column: &str,
+    threshold: f64,
+) -> anyhow::Result<Vec<Option<usize>>> {
+    let rectangles = |df: &DataFrame| -> anyhow::Result<Vec<Option<FlDataFrameRectangle>>> {
+        Ok(df
+            .column(column)
+            .with_context(|| format!("column not found: {}", column))?
+            .as_materialized_series()
+            .iter()
+            .map(|v| FlDataFrameRectangle::try_from(v).ok())
+            .collect())
+    };
+    let base_rectangles = rectangles(base)?;
+    let target_rectangles = rectangles(target)?;
+
+    let mut candidates = vec![];
+    for (t, target_rectangle) in target_rectangles.iter().enumerate() {
+        let Some(target_rectangle) = target_rectangle else {
+            continue;
+        };
+        for (b, base_rectangle) in base_rectangles.iter().enumerate() {
+            let Some(base_rectangle) = base_rectangle else {
+                continue;
+            };
+            let iou = iou(base_rectangle, target_rectangle);
+            if iou >= threshold && iou > 0.0 {
+                candidates.push((iou, b, t));
+            }
+        }
+    }
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let mut pairs = vec![None; target.height()];
+    let mut used = vec![false; base.height()];
+    for (_, b, t) in candidates {
+        if pairs[t].is_none() && !used[b] {
+            pairs[t] = Some(b);
+            used[b] = true;
+        }
+    }
+    Ok(pairs)
+}
+
+pub fn iou(a: &FlDataFrameRectangle, b: &FlDataFrameRectangle) -> f64 {
+    let area = |r: &FlDataFrameRectangle| ((r.x2 - r.x1) * (r.y2 - r.y1)).abs();
+    let width = a.x1.max(a.x2).min(b.x1.max(b.x2)) - a.x1.min(a.x2).max(b.x1.min(b.x2));
+    let height = a.y1.max(a.y2).min(b.y1.max(b.y2)) - a.y1.min(a.y2).max(b.y1.min(b.y2));
+    let intersection = width.max(0.0) * height.max(0.0);
+    let union = area(a) + area(b) - intersection;
+    if union > 0.0 {
+        intersection / union
+    } else {
+        0.0
+    }
+}
+
+/// 差分の結果を `diff_status` と `diff_color` (Color 特殊カラム) を追加した DataFrame にする
+pub fn diff_dataframe(
+    base: &FlDataFrame,
+    target: &FlDataFrame,
+    rows: &[DiffRow],
+) -> anyhow::Result<FlDataFrame> {
+    let take =
+        |df: &DataFrame, indices: Vec<IdxSize>| df.take(&IdxCa::from_vec("".into(), indices));
+    let target_rows = rows
+        .iter()
+        .filter_map(|r| Some(r.target? as IdxSize))
+        .collect_vec();
+    let removed_rows = rows
+        .iter()
+        .filter(|r| r.target.is_none())
+        .filter_map(|r| Some(r.base? as IdxSize))
+        .collect_vec();
+    let mut dataframe = take(&target.value, target_rows)?;
+    dataframe.vstack_mut(&take(&base.value, removed_rows)?)?;
+
+    // target の行、base にしかない行の順に並んでいる
+    let statuses = rows
+        .iter()
+        .sorted_by_key(|r| r.target.is_none())
+        .map(|r| r.status)
+        .collect_vec();
+    let status = Series::new(
+        DIFF_STATUS_COLUMN.into(),
+        statuses.iter().map(|s| s.to_string()).collect_vec(),
+    );
+    let color_field = |name: &str, f: fn((f64, f64, f64)) -> f64| {
+        Series::new(
+            name.into(),
+            statuses.iter().map(|s| f(s.color())).collect_vec(),
+        )
+    };
+    let color = StructChunked::from_series(
+        DIFF_COLOR_COLUMN.into(),
+        statuses.len(),
+        [
+            color_field("r", |c| c.0),
+            color_field("g", |c| c.1),
+            color_field("b", |c| c.2),
+        ]
+        .iter(),
+    )?
+    .into_series();
+    dataframe.with_column(status)?;
+    dataframe.with_column(color)?;
+
+    let mut special_columns = target.special_columns.clone();
+    special_columns.insert(
+        DIFF_COLOR_COLUMN.to_string(),
+        FlDataFrameSpecialColumn::Color,
+    );
+    Ok(FlDataFrame::new(dataframe, special_columns))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detections(ids: &[i32], boxes: &[(f64, f64, f64, f64)]) -> FlDataFrame {
+        let field = |name: &str, f: fn(&(f64, f64, f64, f64)) -> f64| {
+            Series::new(name.into(), boxes.iter().map(f).collect_vec())
+        };
+        let face = StructChunked::from_series(
+            "face".into(),
+            boxes.len(),
+            [
+                field("x1", |b| b.0),
+                field("y1", |b| b.1),
+                field("x2", |b| b.2),
+                field("y2", |b| b.3),
+            ]
+            .iter(),
+        )
+        .unwrap()
+        .into_series();
+        let id = Series::new("id".into(), ids);
+        FlDataFrame::new(
+            DataFrame::new(vec![id.into(), face.into()]).unwrap(),
+            [("face".to_string(), FlDataFrameSpecialColumn::Rectangle)]
+                .into_iter()
+                .collect(),
+        )
+    }
+
+    fn statuses(rows: &[DiffRow]) -> Vec<DiffStatus> {
+        rows.iter().map(|r| r.status).collect()
+    }
+
+    #[test]
+    fn diff_by_key() {
+        let base = detections(
+            &[1, 2, 3],
+            &[(0., 0., 1., 1.), (0., 0., 2., 2.), (5., 5., 6., 6.)],
+        );
+        let target = detections(
+            &[2, 1, 4],
+            &[(0., 0., 2., 2.), (0., 0., 1.5, 1.), (9., 9., 10., 10.)],
+        );
+        let rows = match_rows(&base, &target, &DiffMatching::Key("id".to_string())).unwrap();
+        assert_eq!(
+            statuses(&rows),
+            vec![
+                DiffStatus::Unchanged,
+                DiffStatus::Changed,
+                DiffStatus::Added,
+                DiffStatus::Removed
+            ]
+        );
+        assert_eq!(rows[3].base, Some(2));
+
+        let diff = diff_dataframe(&base, &target, &rows).unwrap();
+        assert_eq!(diff.value.height(), 4);
+        assert_eq!(
+            diff.value
+                .column(DIFF_STATUS_COLUMN)
+                .unwrap()
+                .get(3)
+                .unwrap(),
+            AnyValue::String("removed")
+        );
+        assert_eq!(
+            diff.special_columns.get(DIFF_COLOR_COLUMN),
+            Some(&FlDataFrameSpecialColumn::Color)
+        );
+    }
+
+    #[test]
+    fn diff_by_iou() {
+        let base = detections(&[1, 2], &[(0., 0., 10., 10.), (20., 20., 30., 30.)]);
+        let target = detections(&[1, 2], &[(21., 20., 31., 30.), (50., 50., 60., 60.)]);
+        let matching = DiffMatching::Iou {
+            column: "face".to_string(),
+            threshold: 0.5,
+        };
+        let rows = match_rows(&base, &target, &matching).unwrap();
+        assert_eq!(rows[0].base, Some(1));
+        assert_eq!(
+            statuses(&rows),
+            vec![DiffStatus::Changed, DiffStatus::Added, DiffStatus::Removed]
+        );
+        let rectangle = |x1, x2| FlDataFrameRectangle {
+            x1,
+            y1: 0.0,
+            x2,
+            y2: 1.0,
+        };
+        assert!((iou(&rectangle(0.0, 2.0), &rectangle(1.0, 3.0)) - 1.0 / 3.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,185 @@
+use egui::ahash::HashSet;
+use enum_iterator::Sequence;
+use flexim_data_type::FlDataFrameSpecialColumn;
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, Sequence)]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Parquet,
+    Ipc,
+}
+
+impl Display for ExportFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Csv => write!(f, "CSV"),
+            Self::Parquet => write!(f, "Parquet"),
+            Self::Ipc => write!(f, "Arrow IPC"),
+        }
+    }
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Parquet => "parquet",
+            Self::Ipc => "arrow",
+        }
+    }
+
+    /// CSV は struct カラムを書き出せないため常に展開が必要
+    pub fn requires_flatten(&self) -> bool {
+        matches!(self, Self::Csv)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, Sequence)]
+pub enum ExportRows {
+    #[default]
+    Filtered,
+    Highlighted,
+}
+
+impl Display for ExportRows {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Filtered => write!(f, "Filtered rows"),
+            Self::Highlighted => write!(f, "Highlighted rows"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    pub rows: ExportRows,
+    pub flatten_special_columns: bool,
+}
+
+/// `compute_dataframe` の結果から書き出し用の DataFrame を作る
+pub fn prepare_export(
+    computed: &DataFrame,
+    special_columns: &HashMap<String, FlDataFrameSpecialColumn>,
+    highlight: &HashSet<u64>,
+    options: &ExportOptions,
+) -> PolarsResult<DataFrame> {
+    let row_ids = computed.column("__FleximRowId")?.idx()?;
+    let dataframe = match options.rows {
+        ExportRows::Filtered => computed.clone(),
+        ExportRows::Highlighted => {
+            let mask: BooleanChunked = row_ids
+                .into_iter()
+                .map(|id| id.is_some_and(|id| highlight.contains(&(id as u64))))
+                .collect();
+            computed.filter(&mask)?
+        }
+    };
+    let dataframe = dataframe.drop("__FleximRowId")?;
+
+    if options.flatten_special_columns || options.format.requires_flatten() {
+        flatten_special_columns(&dataframe, special_columns)
+    } else {
+        Ok(dataframe)
+    }
+}
+
+/// 特殊カラム (struct) を `{column}.{field}` という名前のカラムに展開する
+pub fn flatten_special_columns(
+    dataframe: &DataFrame,
+    special_columns: &HashMap<String, FlDataFrameSpecialColumn>,
+) -> PolarsResult<DataFrame> {
+    let mut columns = vec![];
+    for column in dataframe.get_columns() {
+        let name = column.name().to_string();
+        match (special_columns.get(&name), column.dtype()) {
+            (Some(_), DataType::Struct(_)) => {
+                for field in column.struct_()?.fields_as_series() {
+                    let field_name = format!("{}.{}", name, field.name());
+                    columns.push(field.with_name(field_name.into()).into());
+                }
+            }
+            _ => columns.push(column.clone()),
+        }
+    }
+    DataFrame::new(columns)
+}
+
+pub fn write_dataframe<W: Write>(
+    dataframe: &mut DataFrame,
+    format: ExportFormat,
+    writer: W,
+) -> PolarsResult<()> {
+    match format {
+        ExportFormat::Csv => CsvWriter::new(writer).finish(dataframe),
+        ExportFormat::Parquet => ParquetWriter::new(writer).finish(dataframe).map(|_| ()),
+        ExportFormat::Ipc => IpcWriter::new(writer).finish(dataframe),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polars::prelude::StructChunked;
+
+    fn sample() -> (DataFrame, HashMap<String, FlDataFrameSpecialColumn>) {
+        let x1 = Series::new("x1".into(), [0.0, 1.0, 2.0]);
+        let y1 = Series::new("y1".into(), [0.0, 1.0, 2.0]);
+        let x2 = Series::new("x2".into(), [1.0, 2.0, 3.0]);
+        let y2 = Series::new("y2".into(), [1.0, 2.0, 3.0]);
+        let face = StructChunked::from_series("face".into(), 3, [x1, y1, x2, y2].iter())
+            .unwrap()
+            .into_series();
+        let label = Series::new("label".into(), ["a", "b", "c"]);
+        let dataframe = DataFrame::new(vec![face.into(), label.into()])
+            .unwrap()
+            .with_row_index("__FleximRowId".into(), None)
+            .unwrap();
+        let special_columns = [("face".to_string(), FlDataFrameSpecialColumn::Rectangle)]
+            .into_iter()
+            .collect();
+        (dataframe, special_columns)
+    }
+
+    #[test]
+    fn export_highlighted_rows_flattened() {
+        let (dataframe, special_columns) = sample();
+        let highlight = [0_u64, 2].into_iter().collect();
+        let options = ExportOptions {
+            format: ExportFormat::Ipc,
+            rows: ExportRows::Highlighted,
+            flatten_special_columns: true,
+        };
+        let exported = prepare_export(&dataframe, &special_columns, &highlight, &options).unwrap();
+
+        assert_eq!(
+            exported.get_column_names_str(),
+            vec!["face.x1", "face.y1", "face.x2", "face.y2", "label"]
+        );
+        assert_eq!(exported.height(), 2);
+    }
+
+    #[test]
+    fn export_csv_always_flattens() {
+        let (dataframe, special_columns) = sample();
+        let options = ExportOptions::default();
+        let mut exported =
+            prepare_export(&dataframe, &special_columns, &HashSet::default(), &options).unwrap();
+        assert_eq!(exported.height(), 3);
+
+        let mut buf = vec![];
+        write_dataframe(&mut exported, options.format, &mut buf).unwrap();
+        let csv = String::from_utf8(buf).unwrap();
+        assert_eq!(
+            csv.lines().next(),
+            Some("face.x1,face.y1,face.x2,face.y2,label")
+        );
+        assert_eq!(csv.lines().count(), 4);
+    }
+}
@@ -0,0 +1,560 @@
+//! テーブルの行フィルタに使う小さな式言語
+//!
+//! `score > 0.5 && label != "bg"` や `Face.x2 - Face.x1 > 20` のような式を解釈し、
+//! DataFrame の各行に対する真偽値のマスクを計算する。
+//! 構造体カラムのフィールドは `Face.x1` のようにドットで参照できる。
+//! 空白などを含むカラム名は `` `column name` `` のようにバッククォートで囲む。
+
+use polars::prelude::*;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum ExpressionError {
+    #[error("unexpected character '{0}' at {1}")]
+    UnexpectedCharacter(char, usize),
+    #[error("unterminated quote at {0}")]
+    UnterminatedQuote(usize),
+    #[error("unexpected {0} at {1}")]
+    UnexpectedToken(String, usize),
+    #[error("unexpected end of expression")]
+    UnexpectedEnd,
+    #[error("unknown function: {0}")]
+    UnknownFunction(String),
+    #[error("column not found: {0}")]
+    ColumnNotFound(String),
+    #[error("expected boolean expression, found {0}")]
+    NotBoolean(String),
+    #[error("{0}")]
+    Polars(String),
+}
+
+impl From<PolarsError> for ExpressionError {
+    fn from(value: PolarsError) -> Self {
+        Self::Polars(value.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Function {
+    IsNull,
+    IsNotNull,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Column(String),
+    Number(f64),
+    String(String),
+    Boolean(bool),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(Function, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    String(String),
+    Ident(String),
+    LParen,
+    RParen,
+    Op(BinaryOp),
+    Not,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Number(v) => write!(f, "number {}", v),
+            Token::String(v) => write!(f, "string \"{}\"", v),
+            Token::Ident(v) => write!(f, "'{}'", v),
+            Token::LParen => write!(f, "'('"),
+            Token::RParen => write!(f, "')'"),
+            Token::Op(op) => write!(f, "operator {:?}", op),
+            Token::Not => write!(f, "'!'"),
+        }
+    }
+}
+
+/// 式をパースする
+pub fn parse(input: &str) -> Result<Expr, ExpressionError> {
+    let tokens = tokenize(input)?;
+    let mut parser = Parser { tokens, pos: 0 };
+    let expr = parser.or()?;
+    match parser.tokens.get(parser.pos) {
+        Some((token, at)) => Err(ExpressionError::UnexpectedToken(token.to_string(), *at)),
+        None => Ok(expr),
+    }
+}
+
+/// 式をパースし、DataFrame のスキーマに対して評価できるかを確認する
+pub fn validate(input: &str, dataframe: &DataFrame) -> Result<(), ExpressionError> {
+    parse(input)?.filter_mask(&dataframe.head(Some(0)))?;
+    Ok(())
+}
+
+fn tokenize(input: &str) -> Result<Vec<(Token, usize)>, ExpressionError> {
+    let chars: Vec<(usize, char)> = input.char_indices().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        let (at, c) = chars[i];
+        let next = chars.get(i + 1).map(|(_, c)| *c);
+        let (token, consumed) = match (c, next) {
+            (c, _) if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            ('(', _) => (Token::LParen, 1),
+            (')', _) => (Token::RParen, 1),
+            ('+', _) => (Token::Op(BinaryOp::Add), 1),
+            ('-', _) => (Token::Op(BinaryOp::Sub), 1),
+            ('*', _) => (Token::Op(BinaryOp::Mul), 1),
+            ('/', _) => (Token::Op(BinaryOp::Div), 1),
+            ('%', _) => (Token::Op(BinaryOp::Rem), 1),
+            ('=', Some('=')) => (Token::Op(BinaryOp::Eq), 2),
+            ('!', Some('=')) => (Token::Op(BinaryOp::NotEq), 2),
+            ('<', Some('=')) => (Token::Op(BinaryOp::LtEq), 2),
+            ('>', Some('=')) => (Token::Op(BinaryOp::GtEq), 2),
+            ('&', Some('&')) => (Token::Op(BinaryOp::And), 2),
+            ('|', Some('|')) => (Token::Op(BinaryOp::Or), 2),
+            ('<', _) => (Token::Op(BinaryOp::Lt), 1),
+            ('>', _) => (Token::Op(BinaryOp::Gt), 1),
+            ('!', _) => (Token::Not, 1),
+            ('"' | '\'' | '`', _) => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|(_, q)| *q == c)
+                    .ok_or(ExpressionError::UnterminatedQuote(at))?;
+                let value: String = chars[i + 1..i + 1 + end].iter().map(|(_, c)| c).collect();
+                let token = if c == '`' {
+                    Token::Ident(value)
+                } else {
+                    Token::String(value)
+                };
+                (token, end + 2)
+            }
+            (c, _) if c.is_ascii_digit() || c == '.' => {
+                let len = chars[i..]
+                    .iter()
+                    .take_while(|(_, c)| c.is_ascii_digit() || *c == '.')
+                    .count();
+                let value: String = chars[i..i + len].iter().map(|(_, c)| c).collect();
+                let value = value
+                    .parse::<f64>()
+                    .map_err(|_| ExpressionError::UnexpectedToken(value.clone(), at))?;
+                (Token::Number(value), len)
+            }
+            (c, _) if c.is_alphabetic() || c == '_' => {
+                let len = chars[i..]
+                    .iter()
+                    .take_while(|(_, c)| c.is_alphanumeric() || *c == '_' || *c == '.')
+                    .count();
+                let value: String = chars[i..i + len].iter().map(|(_, c)| c).collect();
+                (Token::Ident(value), len)
+            }
+            (c, _) => return Err(ExpressionError::UnexpectedCharacter(c, at)),
+        };
+        tokens.push((token, at));
+        i += consumed;
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(t, _)| t)
+    }
+
+    fn next(&mut self) -> Result<(Token, usize), ExpressionError> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or(ExpressionError::UnexpectedEnd)?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn next_op_in(&mut self, ops: &[BinaryOp]) -> Option<BinaryOp> {
+        match self.peek() {
+            Some(Token::Op(op)) if ops.contains(op) => {
+                let op = *op;
+                self.pos += 1;
+                Some(op)
+            }
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), ExpressionError> {
+        let (token, at) = self.next()?;
+        if token == expected {
+            Ok(())
+        } else {
+            Err(ExpressionError::UnexpectedToken(token.to_string(), at))
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, ExpressionError> {
+        let mut lhs = self.and()?;
+        while let Some(op) = self.next_op_in(&[BinaryOp::Or]) {
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.and()?));
+        }
+        Ok(lhs)
+    }
+
+    fn and(&mut self) -> Result<Expr, ExpressionError> {
+        let mut lhs = self.comparison()?;
+        while let Some(op) = self.next_op_in(&[BinaryOp::And]) {
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.comparison()?));
+        }
+        Ok(lhs)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, ExpressionError> {
+        let lhs = self.additive()?;
+        let ops = [
+            BinaryOp::Eq,
+            BinaryOp::NotEq,
+            BinaryOp::Lt,
+            BinaryOp::LtEq,
+            BinaryOp::Gt,
+            BinaryOp::GtEq,
+        ];
+        if let Some(op) = self.next_op_in(&ops) {
+            Ok(Expr::Binary(op, Box::new(lhs), Box::new(self.additive()?)))
+        } else {
+            Ok(lhs)
+        }
+    }
+
+    fn additive(&mut self) -> Result<Expr, ExpressionError> {
+        let mut lhs = self.multiplicative()?;
+        while let Some(op) = self.next_op_in(&[BinaryOp::Add, BinaryOp::Sub]) {
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.multiplicative()?));
+        }
+        Ok(lhs)
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr, ExpressionError> {
+        let mut lhs = self.unary()?;
+        while let Some(op) = self.next_op_in(&[BinaryOp::Mul, BinaryOp::Div, BinaryOp::Rem]) {
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.unary()?));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ExpressionError> {
+        match self.peek() {
+            Some(Token::Op(BinaryOp::Sub)) => {
+                self.pos += 1;
+                Ok(Expr::Unary(UnaryOp::Neg, Box::new(self.unary()?)))
+            }
+            Some(Token::Not) => {
+                self.pos += 1;
+                Ok(Expr::Unary(UnaryOp::Not, Box::new(self.unary()?)))
+            }
+            _ => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, ExpressionError> {
+        let (token, at) = self.next()?;
+        match token {
+            Token::Number(v) => Ok(Expr::Number(v)),
+            Token::String(v) => Ok(Expr::String(v)),
+            Token::LParen => {
+                let expr = self.or()?;
+                self.expect(Token::RParen)?;
+                Ok(expr)
+            }
+            Token::Ident(name) if self.peek() == Some(&Token::LParen) => {
+                self.pos += 1;
+                let function = match name.as_str() {
+                    "is_null" => Function::IsNull,
+                    "is_not_null" => Function::IsNotNull,
+                    _ => return Err(ExpressionError::UnknownFunction(name)),
+                };
+                let arg = self.or()?;
+                self.expect(Token::RParen)?;
+                Ok(Expr::Call(function, Box::new(arg)))
+            }
+            Token::Ident(name) => match name.as_str() {
+                "true" => Ok(Expr::Boolean(true)),
+                "false" => Ok(Expr::Boolean(false)),
+                _ => Ok(Expr::Column(name)),
+            },
+            token => Err(ExpressionError::UnexpectedToken(token.to_string(), at)),
+        }
+    }
+}
+
+impl Expr {
+    /// 式を評価した結果を行ごとのマスクとして返す
+    pub fn filter_mask(&self, dataframe: &DataFrame) -> Result<BooleanChunked, ExpressionError> {
+        let series = broadcast(self.evaluate(dataframe)?, dataframe.height());
+        let mask = series
+            .bool()
+            .map_err(|_| ExpressionError::NotBoolean(series.dtype().to_string()))?;
+        Ok(mask.clone())
+    }
+
+    /// 式を評価する。リテラルは長さ1の Series として扱う
+    pub fn evaluate(&self, dataframe: &DataFrame) -> Result<Series, ExpressionError> {
+        match self {
+            Expr::Column(name) => resolve_column(dataframe, name),
+            Expr::Number(v) => Ok(Series::new("literal".into(), [*v])),
+            Expr::String(v) => Ok(Series::new("literal".into(), [v.as_str()])),
+            Expr::Boolean(v) => Ok(Series::new("literal".into(), [*v])),
+            Expr::Unary(UnaryOp::Neg, expr) => {
+                let series = to_float(&expr.evaluate(dataframe)?)?;
+                Ok(&series * -1.0)
+            }
+            Expr::Unary(UnaryOp::Not, expr) => {
+                let series = expr.evaluate(dataframe)?;
+                Ok((!to_bool(&series)?).into_series())
+            }
+            Expr::Call(function, expr) => {
+                let series = expr.evaluate(dataframe)?;
+                Ok(match function {
+                    Function::IsNull => series.is_null(),
+                    Function::IsNotNull => series.is_not_null(),
+                }
+                .into_series())
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = lhs.evaluate(dataframe)?;
+                let rhs = rhs.evaluate(dataframe)?;
+                binary(*op, lhs, rhs, dataframe.height())
+            }
+        }
+    }
+}
+
+fn binary(
+    op: BinaryOp,
+    lhs: Series,
+    rhs: Series,
+    height: usize,
+) -> Result<Series, ExpressionError> {
+    match op {
+        BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => {
+            let lhs = to_float(&lhs)?;
+            let rhs = to_float(&rhs)?;
+            Ok(match op {
+                BinaryOp::Add => &lhs + &rhs,
+                BinaryOp::Sub => &lhs - &rhs,
+                BinaryOp::Mul => &lhs * &rhs,
+                BinaryOp::Div => &lhs / &rhs,
+                _ => &lhs % &rhs,
+            }?)
+        }
+        BinaryOp::And | BinaryOp::Or => {
+            let lhs = broadcast(lhs, height);
+            let rhs = broadcast(rhs, height);
+            let lhs = to_bool(&lhs)?;
+            let rhs = to_bool(&rhs)?;
+            Ok(if op == BinaryOp::And {
+                lhs & rhs
+            } else {
+                lhs | rhs
+            }
+            .into_series())
+        }
+        _ => {
+            let (lhs, rhs) = comparable(lhs, rhs)?;
+            let mask = match op {
+                BinaryOp::Eq => lhs.equal(&rhs),
+                BinaryOp::NotEq => lhs.not_equal(&rhs),
+                BinaryOp::Lt => lhs.lt(&rhs),
+                BinaryOp::LtEq => lhs.lt_eq(&rhs),
+                BinaryOp::Gt => lhs.gt(&rhs),
+                _ => lhs.gt_eq(&rhs),
+            }?;
+            Ok(mask.into_series())
+        }
+    }
+}
+
+/// 比較できるように両辺の型を揃える
+fn comparable(lhs: Series, rhs: Series) -> Result<(Series, Series), ExpressionError> {
+    let is_string = |s: &Series| matches!(s.dtype(), DataType::String | DataType::Categorical(..));
+    if is_string(&lhs) || is_string(&rhs) {
+        Ok((lhs.cast(&DataType::String)?, rhs.cast(&DataType::String)?))
+    } else if lhs.dtype() == &DataType::Boolean && rhs.dtype() == &DataType::Boolean {
+        Ok((lhs, rhs))
+    } else {
+        Ok((to_float(&lhs)?, to_float(&rhs)?))
+    }
+}
+
+fn resolve_column(dataframe: &DataFrame, name: &str) -> Result<Series, ExpressionError> {
+    if let Ok(column) = dataframe.column(name) {
+        return Ok(column.as_materialized_series().clone());
+    }
+
+    let not_found = || ExpressionError::ColumnNotFound(name.to_string());
+    let mut path = name.split('.');
+    let column = path.next().ok_or_else(not_found)?;
+    let mut series = dataframe
+        .column(column)
+        .map_err(|_| not_found())?
+        .as_materialized_series()
+        .clone();
+    for field in path {
+        series = series
+            .struct_()
+            .map_err(|_| not_found())?
+            .field_by_name(field)
+            .map_err(|_| not_found())?;
+    }
+    Ok(series)
+}
+
+fn to_float(series: &Series) -> Result<Series, ExpressionError> {
+    Ok(series.strict_cast(&DataType::Float64)?)
+}
+
+fn to_bool(series: &Series) -> Result<BooleanChunked, ExpressionError> {
+    series
+        .bool()
+        .cloned()
+        .map_err(|_| ExpressionError::NotBoolean(series.dtype().to_string()))
+}
+
+fn broadcast(series: Series, height: usize) -> Series {
+    if series.len() == 1 && height != 1 {
+        series.new_from_index(0, height)
+    } else {
+        series
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_dataframe() -> DataFrame {
+        let face = StructChunked::from_series(
+            "Face".into(),
+            3,
+            [
+                Series::new("x1".into(), [0.0, 10.0, 5.0]),
+                Series::new("x2".into(), [30.0, 20.0, 50.0]),
+            ]
+            .iter(),
+        )
+        .unwrap()
+        .into_series();
+        DataFrame::new(vec![
+            Series::new("score".into(), [0.9, 0.2, 0.7]).into(),
+            Series::new("label".into(), [Some("fg"), Some("bg"), None]).into(),
+            face.into(),
+        ])
+        .unwrap()
+    }
+
+    fn mask(input: &str) -> Vec<Option<bool>> {
+        parse(input)
+            .unwrap()
+            .filter_mask(&sample_dataframe())
+            .unwrap()
+            .into_iter()
+            .collect()
+    }
+
+    #[test]
+    fn parse_respects_precedence() {
+        assert_eq!(
+            parse("1 + 2 * 3 > 4 || !a").unwrap(),
+            Expr::Binary(
+                BinaryOp::Or,
+                Box::new(Expr::Binary(
+                    BinaryOp::Gt,
+                    Box::new(Expr::Binary(
+                        BinaryOp::Add,
+                        Box::new(Expr::Number(1.0)),
+                        Box::new(Expr::Binary(
+                            BinaryOp::Mul,
+                            Box::new(Expr::Number(2.0)),
+                            Box::new(Expr::Number(3.0)),
+                        )),
+                    )),
+                    Box::new(Expr::Number(4.0)),
+                )),
+                Box::new(Expr::Unary(
+                    UnaryOp::Not,
+                    Box::new(Expr::Column("a".to_string()))
+                )),
+            )
+        );
+    }
+
+    #[test]
+    fn evaluate_comparison_and_logic() {
+        assert_eq!(
+            mask(r#"score > 0.5 && label != "bg""#),
+            vec![Some(true), Some(false), None]
+        );
+        assert_eq!(
+            mask("is_null(label) || score < 0.5"),
+            vec![Some(false), Some(true), Some(true)]
+        );
+    }
+
+    #[test]
+    fn evaluate_struct_field_arithmetic() {
+        assert_eq!(
+            mask("Face.x2 - Face.x1 > 20"),
+            vec![Some(true), Some(false), Some(true)]
+        );
+    }
+
+    #[test]
+    fn report_errors() {
+        assert_eq!(parse("score >"), Err(ExpressionError::UnexpectedEnd));
+        assert_eq!(
+            parse("score > 1 1"),
+            Err(ExpressionError::UnexpectedToken("number 1".to_string(), 10))
+        );
+        assert_eq!(
+            validate("unknown > 1", &sample_dataframe()),
+            Err(ExpressionError::ColumnNotFound("unknown".to_string()))
+        );
+        assert_eq!(
+            validate("score + 1", &sample_dataframe()),
+            Err(ExpressionError::NotBoolean("f64".to_string()))
+        );
+    }
+}
@@ -1,20 +1,33 @@
 pub mod cache;
+pub mod diff;
+pub mod export;
+pub mod expression;
+pub mod shape_filter;
 
 use egui::ahash::{HashMap, HashSet, HashSetExt};
 
 use crate::cache::{DataFramePoll, FilteredDataFrameCache};
+use crate::export::ExportOptions;
+use crate::shape_filter::ShapeMetric;
 
 use egui::{
-    Align, Checkbox, Color32, ComboBox, Context, Event, Id, Key, Label, Layout, Modifiers, Rect,
-    Response, Sense, Slider, Ui, Widget,
+    Align, Checkbox, Color32, ComboBox, Context, Event, Id, Label, Layout, Modifiers,
+    PopupCloseBehavior, Rect, Response, RichText, ScrollArea, Sense, Slider, SliderClamping,
+    TextEdit, Ui, Widget,
 };
 use egui_extras::{Column, TableBuilder};
-use flexim_data_type::{FlDataFrame, FlDataFrameColor, FlDataFrameSpecialColumn, FlDataReference};
+use enum_iterator::{all, Sequence};
+use flexim_config::keymap::{is_capturing, KeyAction};
+use flexim_config::Config;
+use flexim_data_type::{
+    FlDataFrame, FlDataFrameColor, FlDataFrameRectangle, FlDataFrameSpecialColumn, FlDataReference,
+};
 use itertools::Itertools;
 use polars::prelude::*;
 use rand::random;
 use serde::{Deserialize, Serialize};
-use std::ops::{BitAnd, Deref, DerefMut};
+use std::fmt::{Display, Formatter};
+use std::ops::{BitAnd, DerefMut};
 
 use anyhow::Context as _;
 use flexim_storage::Bag;
@@ -23,7 +36,6 @@ use std::sync::Mutex;
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FlTable {
     id: Id,
-    previous_state: Option<FlTableState>,
     pub data_reference: FlDataReference,
 }
 
@@ -81,7 +93,6 @@ impl FlTable {
     pub fn new(data_reference: FlDataReference) -> Self {
         Self {
             id: Id::new("FlTable").with(random::<u64>()),
-            previous_state: None,
             data_reference,
         }
     }
@@ -94,6 +105,43 @@ impl FlTable {
         state
     }
 
+    /// 複数の Bag にある同じデータのテーブル間でフィルタ・ソートを同期する
+    pub fn synchronize_across_bags(&self, ctx: &Context, bags: &[&Bag]) {
+        let states = bags
+            .iter()
+            .filter_map(|bag| {
+                let id = self.data_id(bag).ok()?;
+                let state = ctx.memory_mut(|mem| mem.data.get_temp(id))?;
+                Some((id.with("synchronized filter settings"), state))
+            })
+            .collect_vec();
+        if states.len() >= 2 {
+            synchronize_table_states(ctx, &states);
+        }
+    }
+
+    /// 同じデータの別の世代 `previous` を表示していたテーブルのフィルタ・ソートを引き継ぐ
+    pub fn inherit_filter_settings(
+        &self,
+        ctx: &Context,
+        bag: &Bag,
+        previous: &FlDataReference,
+    ) -> anyhow::Result<()> {
+        let previous_id = Id::new("FlTable").with(bag.data_by_reference(previous)?.id());
+        let Some(previous_state) =
+            ctx.memory_mut(|mem| mem.data.get_temp::<Arc<Mutex<FlTableState>>>(previous_id))
+        else {
+            return Ok(());
+        };
+        let settings = previous_state.lock().unwrap().filter_settings();
+        let dataframe = self.dataframe(bag)?;
+        let mut state = FlTableState::new(&dataframe.value, &dataframe.special_columns);
+        state.apply_filter_settings(&settings);
+        let id = self.data_id(bag)?;
+        ctx.memory_mut(|mem| mem.data.insert_temp(id, Arc::new(Mutex::new(state))));
+        Ok(())
+    }
+
     pub fn dataframe(&self, bag: &Bag) -> anyhow::Result<Arc<FlDataFrame>> {
         bag.data_by_reference(&self.data_reference)
             .context("Failed to get data by reference")?
@@ -106,6 +154,18 @@ impl FlTable {
         let ctx = ui.ctx().clone();
 
         let mode = ui.input(ModifyMode::from_input);
+        let keymap = Config::get_global(ui).keymap;
+        let (copy_selection, focus_filter) =
+            if ui.ui_contains_pointer() && !ctx.wants_keyboard_input() && !is_capturing(&ctx) {
+                ui.input(|input| {
+                    (
+                        keymap.pressed(input, KeyAction::CopySelection),
+                        keymap.pressed(input, KeyAction::FocusFilter),
+                    )
+                })
+            } else {
+                (false, false)
+            };
 
         let dataframe = bag
             .data_by_reference(&self.data_reference)
@@ -115,32 +175,20 @@ impl FlTable {
         let state = ui.ctx().memory_mut(|mem| {
             mem.data
                 .get_temp_mut_or_insert_with(self.data_id(bag).unwrap(), || {
-                    Arc::new(Mutex::new(FlTableState::new(&dataframe.value)))
+                    Arc::new(Mutex::new(FlTableState::new(
+                        &dataframe.value,
+                        &dataframe.special_columns,
+                    )))
                 })
                 .clone()
         });
 
-        if self.previous_state.is_none()
-            || self.previous_state.as_ref().unwrap() != state.lock().unwrap().deref()
-        {
-            let id = self.data_id(bag).unwrap();
-            let generation = ui.ctx().memory_mut(move |mem| {
-                let cache = mem.caches.cache::<FilteredDataFrameCache>();
-                cache.insert_calculating(id)
-            });
-            let state = state.clone().lock().unwrap().clone();
-            let ctx = ui.ctx().clone();
-            std::thread::spawn({
-                let dataframe = dataframe.clone();
-                move || {
-                    let dataframe = compute_dataframe(&dataframe.value, &state);
-                    ctx.memory_mut(move |mem| {
-                        let cache = mem.caches.cache::<FilteredDataFrameCache>();
-                        cache.insert_computed(id, generation, dataframe);
-                    });
-                }
-            });
-        }
+        request_compute(
+            &ctx,
+            self.data_id(bag).unwrap(),
+            &dataframe,
+            &state.lock().unwrap(),
+        );
 
         let special_columns = &dataframe.special_columns;
         let columns = match &draw_context.show_columns {
@@ -157,9 +205,10 @@ impl FlTable {
             return;
         }
 
-        let dataframe = ui.ctx().memory_mut(|mem| {
+        let (dataframe, generation) = ui.ctx().memory_mut(|mem| {
             let cache = mem.caches.cache::<FilteredDataFrameCache>();
-            cache.get(self.data_id(bag).unwrap()).unwrap()
+            let id = self.data_id(bag).unwrap();
+            (cache.get(id).unwrap(), cache.generation(id))
         });
 
         if let DataFramePoll::Ready(dataframe) = dataframe {
@@ -172,6 +221,31 @@ impl FlTable {
                 ui.label(format!("{} filtered rows", dataframe.height()));
                 ui.label(format!("{} selected rows", state.highlight.len()));
             });
+            ui.horizontal(|ui| {
+                ui.label("Filter");
+                let response = TextEdit::singleline(&mut state.expression_filter)
+                    .hint_text(r#"score > 0.5 && label != "bg""#)
+                    .desired_width(f32::INFINITY)
+                    .ui(ui);
+                if focus_filter {
+                    response.request_focus();
+                }
+            });
+            if !state.expression_filter.trim().is_empty() {
+                if let Err(e) = expression::validate(
+                    &state.expression_filter,
+                    &self.dataframe(bag).unwrap().value,
+                ) {
+                    ui.colored_label(ui.visuals().error_fg_color, e.to_string());
+                }
+            }
+
+            if copy_selection {
+                // 選択中のセルの文字列をコピーする
+                ctx.input_mut(|inp| {
+                    inp.events.push(Event::Copy);
+                })
+            }
 
             let mut builder = TableBuilder::new(ui).vscroll(true).striped(true);
 
@@ -179,20 +253,40 @@ impl FlTable {
             for _col in &columns {
                 builder = builder.column(Column::auto().clip(true).resizable(true));
             }
-            let selected = &mut state.selected;
-            let builder = if let Some(selected) = selected {
-                log::trace!("selected: {}", *selected);
-                builder.scroll_to_row(*selected as usize, Some(Align::Center))
+            let selected_row = state.selected.and_then(|selected| {
+                selected_row_position(
+                    &ctx,
+                    self.data_id(bag).unwrap(),
+                    generation?,
+                    &dataframe,
+                    selected,
+                )
+            });
+            let builder = if let Some(selected_row) = selected_row {
+                log::trace!("selected row: {}", selected_row);
+                builder.scroll_to_row(selected_row, Some(Align::Center))
             } else {
                 builder
             };
             builder
                 .sense(Sense::click())
                 .drag_to_scroll(false)
-                .header(80.0, |mut header| {
+                .header(100.0, |mut header| {
                     for col in &columns {
                         header.col(|ui| {
-                            Label::new(col.to_string()).truncate().ui(ui);
+                            let title = match state.sort_indicator(col) {
+                                Some(indicator) => format!("{} {}", col, indicator),
+                                None => col.to_string(),
+                            };
+                            if Label::new(title)
+                                .truncate()
+                                .sense(Sense::click())
+                                .ui(ui)
+                                .on_hover_text("Click to sort, Shift + Click to add sort key")
+                                .clicked()
+                            {
+                                state.toggle_sort(col, mode.is_select());
+                            }
                             let filter = state.filters.get_mut(col).unwrap();
                             Checkbox::new(&mut filter.allow_null_value, "Allow Null").ui(ui);
                             filter.draw(Id::new(self.id).with(col), ui);
@@ -251,14 +345,6 @@ impl FlTable {
                             if mode.is_command() {
                                 response = response.on_hover_text("Copy to clipboard");
                             }
-                            if mode.is_select() && ctx.input(|inp| inp.keys_down.contains(&Key::C))
-                            {
-                                // Shift + S で選択
-                                // TODO(higumachan): Cmd + Shift + S で選択にしたいが現状はフックできない
-                                ctx.input_mut(|inp| {
-                                    inp.events.push(Event::Copy);
-                                })
-                            }
                             if response.clicked() {
                                 if mode.is_command() {
                                     let c = dataframe
@@ -290,6 +376,130 @@ impl FlTable {
 
         dataframe
     }
+
+    /// フィルタ・ソートの計算結果とその世代。世代が同じなら結果も同じ
+    pub fn computed_dataframe_with_generation(
+        &self,
+        ctx: &Context,
+        bag: &Bag,
+    ) -> Option<(u64, DataFrame)> {
+        let id = self.data_id(bag).ok()?;
+        ctx.memory_mut(|mem| {
+            mem.caches
+                .cache::<FilteredDataFrameCache>()
+                .get_with_generation(id)
+        })
+    }
+
+    /// 現在のフィルタ・ソートを適用した結果をファイルに書き出す
+    pub fn export(
+        &self,
+        ctx: &Context,
+        bag: &Bag,
+        options: &ExportOptions,
+        path: &std::path::Path,
+    ) -> anyhow::Result<()> {
+        let dataframe = self.dataframe(bag)?;
+        let id = self.data_id(bag)?;
+        let state = ctx
+            .memory_mut(|mem| mem.data.get_temp::<Arc<Mutex<FlTableState>>>(id))
+            .map(|s| s.lock().unwrap().clone())
+            .unwrap_or_else(|| FlTableState::new(&dataframe.value, &dataframe.special_columns));
+
+        let computed = compute_dataframe(&dataframe.value, &state);
+        let mut exported = export::prepare_export(
+            &computed,
+            &dataframe.special_columns,
+            &state.highlight,
+            options,
+        )?;
+        let writer = std::io::BufWriter::new(
+            std::fs::File::create(path)
+                .with_context(|| format!("Failed to create {}", path.display()))?,
+        );
+        export::write_dataframe(&mut exported, options.format, writer)?;
+        Ok(())
+    }
+}
+
+/// フィルタ・ソートの計算に使う入力。これが変わったときだけ再計算する
+#[derive(Debug, Clone, PartialEq)]
+struct ComputeInputs {
+    settings: FilterSettings,
+    viewport: Option<FlDataFrameRectangle>,
+}
+
+impl ComputeInputs {
+    fn new(state: &FlTableState) -> Self {
+        Self {
+            settings: state.filter_settings(),
+            viewport: state
+                .uses_viewport()
+                .then(|| state.viewport.clone())
+                .flatten(),
+        }
+    }
+}
+
+/// 前回計算したときから `state` のフィルタ・ソートが変わっていれば別スレッドで再計算する
+/// 再計算を始めた場合はその世代を返す
+fn request_compute(
+    ctx: &Context,
+    id: Id,
+    dataframe: &Arc<FlDataFrame>,
+    state: &FlTableState,
+) -> Option<u64> {
+    let inputs = ComputeInputs::new(state);
+    let inputs_id = id.with("computed inputs");
+    let generation = ctx.memory_mut(|mem| {
+        let unchanged = mem.data.get_temp::<ComputeInputs>(inputs_id).as_ref() == Some(&inputs);
+        let cache = mem.caches.cache::<FilteredDataFrameCache>();
+        if unchanged && cache.get(id).is_some() {
+            return None;
+        }
+        let generation = cache.insert_calculating(id);
+        mem.data.insert_temp(inputs_id, inputs);
+        Some(generation)
+    })?;
+
+    let state = state.clone();
+    let ctx = ctx.clone();
+    let dataframe = dataframe.clone();
+    std::thread::spawn(move || {
+        let dataframe = compute_dataframe(&dataframe.value, &state);
+        ctx.memory_mut(move |mem| {
+            let cache = mem.caches.cache::<FilteredDataFrameCache>();
+            cache.insert_computed(id, generation, dataframe);
+        });
+    });
+    Some(generation)
+}
+
+/// 前回の同期から設定が変わったテーブルのフィルタ・ソートを他のテーブルに反映する
+/// `states` の `Id` は前回同期した設定を覚えておくためのキー
+pub fn synchronize_table_states(ctx: &Context, states: &[(Id, Arc<Mutex<FlTableState>>)]) {
+    let settings = states
+        .iter()
+        .map(|(_, s)| s.lock().unwrap().filter_settings())
+        .collect_vec();
+    let changed = ctx.memory(|mem| {
+        states
+            .iter()
+            .zip(&settings)
+            .position(|((key, _), s)| mem.data.get_temp::<FilterSettings>(*key).as_ref() != Some(s))
+    });
+    let Some(source) = changed else {
+        return;
+    };
+
+    for (i, (key, state)) in states.iter().enumerate() {
+        let mut state = state.lock().unwrap();
+        if i != source {
+            state.apply_filter_settings(&settings[source]);
+        }
+        let applied = state.filter_settings();
+        ctx.memory_mut(|mem| mem.data.insert_temp(*key, applied));
+    }
 }
 
 fn color_column(row: &mut egui_extras::TableRow, color: FlDataFrameColor) -> (Rect, Response) {
@@ -323,13 +533,83 @@ fn compute_dataframe(dataframe: &DataFrame, state: &FlTableState) -> DataFrame {
         let filter = filter.filter.as_ref();
         let series = dataframe.column(col).unwrap().as_series().unwrap();
         if let Some(filter) = filter.as_ref() {
-            if let Some(m) = filter.apply(series) {
-                col_filter_mask =
-                    col_filter_mask.bitand(m.fill_null_with_values(allow_null_value).unwrap());
+            match filter.apply(series, state.viewport.as_ref()) {
+                Ok(Some(m)) => {
+                    col_filter_mask =
+                        col_filter_mask.bitand(m.fill_null_with_values(allow_null_value).unwrap());
+                }
+                Ok(None) => {}
+                Err(e) => log::debug!("ignore invalid filter on {}: {}", col, e),
             }
         }
     }
-    dataframe.filter(&col_filter_mask).unwrap()
+    if let Some(m) = expression_filter_mask(&dataframe, &state.expression_filter) {
+        col_filter_mask = col_filter_mask.bitand(m);
+    }
+    let dataframe = dataframe.filter(&col_filter_mask).unwrap();
+    sort_dataframe(dataframe, &state.sort)
+}
+
+fn expression_filter_mask(dataframe: &DataFrame, expression: &str) -> Option<BooleanChunked> {
+    if expression.trim().is_empty() {
+        return None;
+    }
+    match expression::parse(expression).and_then(|e| e.filter_mask(dataframe)) {
+        Ok(m) => Some(m.fill_null_with_values(false).unwrap()),
+        Err(e) => {
+            log::debug!("ignore invalid expression filter: {}", e);
+            None
+        }
+    }
+}
+
+fn sort_dataframe(dataframe: DataFrame, sort: &[ColumnSort]) -> DataFrame {
+    if sort.is_empty() {
+        return dataframe;
+    }
+    let by = sort.iter().map(|s| s.column.clone()).collect_vec();
+    let options = SortMultipleOptions::default()
+        .with_order_descending_multi(sort.iter().map(|s| s.descending))
+        .with_nulls_last(true)
+        .with_maintain_order(true);
+
+    match dataframe.sort(by, options) {
+        Ok(sorted) => sorted,
+        Err(e) => {
+            log::warn!("failed to sort dataframe: {}", e);
+            dataframe
+        }
+    }
+}
+
+/// 選択中の行の位置。計算結果の世代と選択が変わったときだけ探し直す
+fn selected_row_position(
+    ctx: &Context,
+    id: Id,
+    generation: u64,
+    dataframe: &DataFrame,
+    selected: u64,
+) -> Option<usize> {
+    let key = id.with("selected row position");
+    let cached = ctx.data(|data| data.get_temp::<(u64, u64, Option<usize>)>(key));
+    match cached {
+        Some((g, s, position)) if g == generation && s == selected => position,
+        _ => {
+            let position = row_position(dataframe, selected);
+            ctx.data_mut(|data| data.insert_temp(key, (generation, selected, position)));
+            position
+        }
+    }
+}
+
+fn row_position(dataframe: &DataFrame, row_id: u64) -> Option<usize> {
+    dataframe
+        .column("__FleximRowId")
+        .ok()?
+        .idx()
+        .ok()?
+        .into_iter()
+        .position(|v| v.map(|v| v as u64) == Some(row_id))
 }
 
 type ColumnName = String;
@@ -339,22 +619,138 @@ pub struct FlTableState {
     pub filters: HashMap<ColumnName, ColumnFilter>,
     pub highlight: HashSet<u64>,
     pub selected: Option<u64>,
+    #[serde(default)]
+    pub sort: Vec<ColumnSort>,
+    #[serde(default)]
+    pub expression_filter: String,
+    /// 表示中の領域 (データ座標系)。"In viewport" フィルタが有効な場合に描画側から更新される
+    #[serde(default)]
+    pub viewport: Option<FlDataFrameRectangle>,
+}
+
+/// 複数のテーブル間で共有するフィルタ・ソートの設定
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterSettings {
+    filters: Vec<(ColumnName, bool, Option<Filter>)>,
+    sort: Vec<ColumnSort>,
+    expression_filter: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ColumnSort {
+    pub column: ColumnName,
+    pub descending: bool,
 }
 
 impl FlTableState {
-    fn new(data_frame: &DataFrame) -> Self {
+    fn new(
+        data_frame: &DataFrame,
+        special_columns: &std::collections::HashMap<String, FlDataFrameSpecialColumn>,
+    ) -> Self {
         FlTableState {
             filters: data_frame
                 .iter()
                 .map(|series| {
-                    (
-                        series.0.name().to_string(),
-                        ColumnFilter::from_series(series),
-                    )
+                    let name = series.0.name().to_string();
+                    let filter = ColumnFilter::from_series(series, special_columns.get(&name));
+                    (name, filter)
                 })
                 .collect(),
             highlight: HashSet::new(),
             selected: None,
+            sort: vec![],
+            expression_filter: String::new(),
+            viewport: None,
+        }
+    }
+
+    pub fn filter_settings(&self) -> FilterSettings {
+        FilterSettings {
+            filters: self
+                .filters
+                .iter()
+                .map(|(c, f)| (c.clone(), f.allow_null_value, f.filter.clone()))
+                .sorted_by(|a, b| a.0.cmp(&b.0))
+                .collect(),
+            sort: self.sort.clone(),
+            expression_filter: self.expression_filter.clone(),
+        }
+    }
+
+    /// 同じ名前・同じ種類のフィルタを持つカラムにだけ設定を適用する
+    pub fn apply_filter_settings(&mut self, settings: &FilterSettings) {
+        for (column, allow_null_value, filter) in &settings.filters {
+            let Some(target) = self.filters.get_mut(column) else {
+                continue;
+            };
+            if let (Some(filter), Some(current)) = (filter, &target.filter) {
+                if std::mem::discriminant(filter) == std::mem::discriminant(current) {
+                    target.filter = Some(filter.clone());
+                    target.allow_null_value = *allow_null_value;
+                }
+            }
+        }
+        self.sort = settings
+            .sort
+            .iter()
+            .filter(|s| self.filters.contains_key(&s.column))
+            .cloned()
+            .collect();
+        self.expression_filter = settings.expression_filter.clone();
+    }
+
+    /// 表示領域に依存するフィルタが有効かどうか
+    pub fn uses_viewport(&self) -> bool {
+        self.filters.values().any(|f| {
+            matches!(
+                f.filter,
+                Some(Filter::Shape {
+                    intersects_viewport: true,
+                    ..
+                })
+            )
+        })
+    }
+
+    /// ソートキーを 昇順 -> 降順 -> 解除 の順に切り替える
+    /// `multi` が false の場合は他のソートキーを解除する
+    pub fn toggle_sort(&mut self, column: &str, multi: bool) {
+        match self.sort.iter().position(|s| s.column == column) {
+            Some(i) => {
+                if self.sort[i].descending {
+                    self.sort.remove(i);
+                } else {
+                    self.sort[i].descending = true;
+                }
+                if !multi {
+                    self.sort.retain(|s| s.column == column);
+                }
+            }
+            None => {
+                let sort = ColumnSort {
+                    column: column.to_string(),
+                    descending: false,
+                };
+                if multi {
+                    self.sort.push(sort);
+                } else {
+                    self.sort = vec![sort];
+                }
+            }
+        }
+    }
+
+    fn sort_indicator(&self, column: &str) -> Option<String> {
+        let i = self.sort.iter().position(|s| s.column == column)?;
+        let arrow = if self.sort[i].descending {
+            "⬇"
+        } else {
+            "⬆"
+        };
+        if self.sort.len() > 1 {
+            Some(format!("{}{}", arrow, i + 1))
+        } else {
+            Some(arrow.to_string())
         }
     }
 }
@@ -362,7 +758,9 @@ impl FlTableState {
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 struct Aggregated {
     min_max: Option<(f64, f64)>,
-    unique: Option<Vec<String>>,
+    unique_counts: Option<Vec<(String, usize)>>,
+    #[serde(default)]
+    shape_ranges: Vec<(ShapeMetric, f64, f64)>,
     dtype: DataType,
 }
 
@@ -371,6 +769,9 @@ pub struct ColumnFilter {
     allow_null_value: bool,
     filter: Option<Filter>,
     aggregated: Arc<Aggregated>,
+    /// 最後に確認したフィルタとその設定の誤り
+    #[serde(skip)]
+    validated: Option<(Filter, Option<String>)>,
 }
 
 impl Default for ColumnFilter {
@@ -380,27 +781,49 @@ impl Default for ColumnFilter {
             filter: None,
             aggregated: Arc::new(Aggregated {
                 min_max: None,
-                unique: None,
+                unique_counts: None,
+                shape_ranges: vec![],
                 dtype: DataType::Null,
             }),
+            validated: None,
         }
     }
 }
 
 impl ColumnFilter {
+    /// フィルタの設定の誤り
+    /// 正規表現のコンパイルなどを毎フレーム行わないよう、フィルタが変わったときだけ確認し直す
+    fn error(&mut self) -> Option<&str> {
+        if self.validated.as_ref().map(|(filter, _)| filter) != self.filter.as_ref() {
+            self.validated = self.filter.clone().map(|filter| {
+                let error = filter
+                    .validate(&self.aggregated.dtype)
+                    .err()
+                    .map(|e| format!("{:#}", e));
+                (filter, error)
+            });
+        }
+        self.validated.as_ref()?.1.as_deref()
+    }
+
     pub fn draw(&mut self, id: Id, ui: &mut Ui) {
         match &mut self.filter {
             Some(Filter::Range { min, max }) => {
                 let range = self.aggregated.min_max.map(|(min, max)| min..=max).unwrap();
 
-                let slider = Slider::new(min, range.clone()).text("min");
+                // 比較表示で他のテーブルから同期された値を保つため、編集時のみ範囲に制限する
+                let slider = Slider::new(min, range.clone())
+                    .text("min")
+                    .clamping(SliderClamping::Edits);
                 let slider = if self.aggregated.dtype.is_integer() {
                     slider.integer()
                 } else {
                     slider
                 };
                 ui.add(slider);
-                let slider = Slider::new(max, range.clone()).text("max");
+                let slider = Slider::new(max, range.clone())
+                    .text("max")
+                    .clamping(SliderClamping::Edits);
                 let slider = if self.aggregated.dtype.is_integer() {
                     slider.integer()
                 } else {
@@ -408,36 +831,132 @@ impl ColumnFilter {
                 };
                 ui.add(slider);
             }
-            Some(Filter::Search(search)) => {
-                ui.text_edit_singleline(search);
+            Some(Filter::Search {
+                query,
+                mode,
+                negate,
+            }) => {
+                ui.horizontal(|ui| {
+                    ComboBox::from_id_salt(id.with("search mode"))
+                        .selected_text(mode.to_string())
+                        .show_ui(ui, |ui| {
+                            for m in all::<SearchMode>() {
+                                ui.selectable_value(mode, m, m.to_string());
+                            }
+                        });
+                    ui.toggle_value(negate, "Not")
+                        .on_hover_text("Keep rows that do not match");
+                });
+                ui.text_edit_singleline(query);
+            }
+            Some(Filter::Shape {
+                metric,
+                min,
+                max,
+                intersects_viewport,
+                ..
+            }) => {
+                let ranges = &self.aggregated.shape_ranges;
+                let previous = *metric;
+                ComboBox::from_id_salt(id.with("shape metric"))
+                    .selected_text(metric.to_string())
+                    .show_ui(ui, |ui| {
+                        for (m, _, _) in ranges {
+                            ui.selectable_value(metric, *m, m.to_string());
+                        }
+                    });
+                if let Some((_, lower, upper)) = ranges.iter().find(|(m, _, _)| m == metric) {
+                    if *metric != previous {
+                        *min = *lower;
+                        *max = *upper;
+                    }
+                    ui.add(
+                        Slider::new(min, *lower..=*upper)
+                            .text("min")
+                            .clamping(SliderClamping::Edits),
+                    );
+                    ui.add(
+                        Slider::new(max, *lower..=*upper)
+                            .text("max")
+                            .clamping(SliderClamping::Edits),
+                    );
+                }
+                ui.checkbox(intersects_viewport, "In viewport")
+                    .on_hover_text("Keep only shapes intersecting the region shown in the viewer");
             }
             Some(Filter::Categorical(categories)) => {
-                let cat = categories
-                    .as_ref()
-                    .and_then(|t| t.iter().next().cloned())
-                    .unwrap_or("".to_string());
+                let unique_counts = self.aggregated.unique_counts.as_deref().unwrap_or_default();
+                let selected_text = match categories {
+                    None => "All".to_string(),
+                    Some(c) if c.len() == 1 => c.iter().next().unwrap().clone(),
+                    Some(c) => format!("{} selected", c.len()),
+                };
                 ComboBox::from_id_salt(id)
-                    .selected_text(cat)
+                    .selected_text(selected_text)
+                    .close_behavior(PopupCloseBehavior::CloseOnClickOutside)
                     .show_ui(ui, |ui| {
-                        let mut selected = None;
-                        for cat in self.aggregated.unique.as_ref().unwrap() {
-                            ui.selectable_value(&mut selected, Some(cat.clone()), cat);
-                        }
-                        if let Some(selected) = selected {
-                            let mut current_categories = HashSet::new();
-                            current_categories.insert(selected);
-                            *categories = Some(current_categories);
-                        } else {
-                            *categories = None;
-                        }
+                        let search_id = id.with("category search");
+                        let mut search = ui
+                            .data_mut(|data| data.get_temp::<String>(search_id))
+                            .unwrap_or_default();
+                        ui.horizontal(|ui| {
+                            if ui.button("All").clicked() {
+                                *categories = None;
+                            }
+                            if ui.button("None").clicked() {
+                                *categories = Some(HashSet::new());
+                            }
+                            TextEdit::singleline(&mut search)
+                                .hint_text("Search")
+                                .desired_width(80.0)
+                                .ui(ui);
+                        });
+                        ui.separator();
+                        ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                            for (cat, count) in unique_counts
+                                .iter()
+                                .filter(|(cat, _)| cat.contains(search.as_str()))
+                            {
+                                let mut checked = match categories {
+                                    None => true,
+                                    Some(c) => c.contains(cat),
+                                };
+                                if ui
+                                    .checkbox(&mut checked, format!("{} ({})", cat, count))
+                                    .changed()
+                                {
+                                    let current = categories.get_or_insert_with(|| {
+                                        unique_counts.iter().map(|(c, _)| c.clone()).collect()
+                                    });
+                                    if checked {
+                                        current.insert(cat.clone());
+                                    } else {
+                                        current.remove(cat);
+                                    }
+                                    if current.len() == unique_counts.len() {
+                                        *categories = None;
+                                    }
+                                }
+                            }
+                        });
+                        ui.data_mut(|data| data.insert_temp(search_id, search));
                     });
             }
             _ => {}
         }
+        if let Some(error) = self.error() {
+            Label::new(RichText::new(error).color(ui.visuals().error_fg_color))
+                .truncate()
+                .ui(ui)
+                .on_hover_text(error);
+        }
     }
 
-    fn from_series(series: &Series) -> Self {
+    fn from_series(series: &Series, special_column: Option<&FlDataFrameSpecialColumn>) -> Self {
         let dtype = series.dtype();
+        let shape_metrics = special_column
+            .map(ShapeMetric::applicable)
+            .unwrap_or_default();
         let aggregated = Aggregated {
             min_max: series.cast(&DataType::Float64).ok().and_then(|t| {
                 let series = t.f64().ok()?;
@@ -446,12 +965,36 @@ impl ColumnFilter {
 
                 Some((min, max))
             }),
-            unique: unique_series(series),
+            unique_counts: unique_counts_series(series),
+            shape_ranges: shape_metrics
+                .iter()
+                .filter_map(|m| {
+                    let (min, max) = m.min_max(series)?;
+                    Some((*m, min, max))
+                })
+                .collect(),
             dtype: series.dtype().clone(),
         };
 
         let aggregated = Arc::new(aggregated);
-        if dtype.is_numeric() {
+        if let Some(&metric) = shape_metrics.first() {
+            let (metric, min, max) = aggregated.shape_ranges.first().copied().unwrap_or((
+                metric,
+                f64::NEG_INFINITY,
+                f64::INFINITY,
+            ));
+            Self {
+                aggregated,
+                filter: Some(Filter::Shape {
+                    shape: special_column.unwrap().clone(),
+                    metric,
+                    min,
+                    max,
+                    intersects_viewport: false,
+                }),
+                ..Default::default()
+            }
+        } else if dtype.is_numeric() {
             let (min, max) = aggregated.min_max.unwrap();
             Self {
                 aggregated,
@@ -467,7 +1010,11 @@ impl ColumnFilter {
         } else if dtype == &DataType::String {
             Self {
                 aggregated,
-                filter: Some(Filter::Search(String::new())),
+                filter: Some(Filter::Search {
+                    query: String::new(),
+                    mode: SearchMode::default(),
+                    negate: false,
+                }),
                 ..Default::default()
             }
         } else if let DataType::Categorical(_d, _) = dtype {
@@ -486,26 +1033,88 @@ impl ColumnFilter {
     }
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, Sequence)]
+pub enum SearchMode {
+    #[default]
+    Literal,
+    CaseInsensitive,
+    Regex,
+    Exact,
+    StartsWith,
+}
+
+impl Display for SearchMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Literal => write!(f, "Contains"),
+            Self::CaseInsensitive => write!(f, "Contains (Aa)"),
+            Self::Regex => write!(f, "Regex"),
+            Self::Exact => write!(f, "Exact"),
+            Self::StartsWith => write!(f, "Starts With"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 enum Filter {
-    Search(String),
-    Range { min: f64, max: f64 },
+    Search {
+        query: String,
+        #[serde(default)]
+        mode: SearchMode,
+        #[serde(default)]
+        negate: bool,
+    },
+    Range {
+        min: f64,
+        max: f64,
+    },
     Categorical(Option<HashSet<String>>),
+    Shape {
+        shape: FlDataFrameSpecialColumn,
+        metric: ShapeMetric,
+        min: f64,
+        max: f64,
+        intersects_viewport: bool,
+    },
 }
 
 impl Filter {
-    fn apply(&self, series: &Series) -> Option<BooleanChunked> {
+    /// フィルタを適用したマスクを返す。フィルタが無効な状態(空の検索文字列など)の場合は `None` を返す
+    fn apply(
+        &self,
+        series: &Series,
+        viewport: Option<&FlDataFrameRectangle>,
+    ) -> anyhow::Result<Option<BooleanChunked>> {
         match self {
-            Filter::Search(search) => Some(series.str().ok()?.contains(search, true).ok()?),
+            Filter::Search { query, .. } if query.is_empty() => Ok(None),
+            Filter::Search {
+                query,
+                mode,
+                negate,
+            } => {
+                let series = series.str()?;
+                let mask = match mode {
+                    SearchMode::Literal => series.contains_literal(query)?,
+                    SearchMode::CaseInsensitive => series
+                        .to_lowercase()
+                        .contains_literal(&query.to_lowercase())?,
+                    SearchMode::Regex => series
+                        .contains(query, true)
+                        .context("invalid regular expression")?,
+                    SearchMode::Exact => series.equal(query.as_str()),
+                    SearchMode::StartsWith => series.starts_with(query),
+                };
+                Ok(Some(if *negate { !mask } else { mask }))
+            }
             Filter::Range { min, max } => {
-                let series = series.cast(&DataType::Float64).ok()?;
-                let series = series.f64().ok()?;
-                Some(series.gt_eq(*min).bitand(series.lt_eq(*max)))
+                let series = series.cast(&DataType::Float64)?;
+                let series = series.f64()?;
+                Ok(Some(series.gt_eq(*min).bitand(series.lt_eq(*max))))
             }
             Filter::Categorical(Some(categories)) => {
-                let series = series.cast(&DataType::String).unwrap();
-                let series = series.str().unwrap();
-                Some(
+                let series = series.cast(&DataType::String)?;
+                let series = series.str()?;
+                Ok(Some(
                     series
                         .into_iter()
                         .map(|t: Option<&str>| {
@@ -516,14 +1125,35 @@ impl Filter {
                             }
                         })
                         .collect(),
-                )
+                ))
+            }
+            Filter::Categorical(None) => Ok(None),
+            Filter::Shape {
+                shape,
+                metric,
+                min,
+                max,
+                intersects_viewport,
+            } => {
+                let values = metric.evaluate(series)?;
+                let mut mask = values.gt_eq(*min).bitand(values.lt_eq(*max));
+                if let (true, Some(viewport)) = (intersects_viewport, viewport) {
+                    mask = mask.bitand(shape_filter::intersects_viewport(series, shape, viewport)?);
+                }
+                Ok(Some(mask))
             }
-            Filter::Categorical(None) => None,
         }
     }
+
+    /// データに依存せずに検出できる設定の誤り(不正な正規表現など)を確認する
+    fn validate(&self, dtype: &DataType) -> anyhow::Result<()> {
+        self.apply(&Series::new_empty("".into(), dtype), None)?;
+        Ok(())
+    }
 }
 
-fn unique_series(series: &Series) -> Option<Vec<String>> {
+/// 値ごとの出現回数を、回数の多い順に返す
+fn unique_counts_series(series: &Series) -> Option<Vec<(String, usize)>> {
     let series = series.cast(&DataType::String).ok()?;
 
     Some(
@@ -531,15 +1161,339 @@ fn unique_series(series: &Series) -> Option<Vec<String>> {
             .str()
             .ok()?
             .into_iter()
-            .filter_map(|t| t.map(|s| s.to_string()))
-            .unique()
+            .flatten()
+            .counts()
+            .into_iter()
+            .map(|(value, count)| (value.to_string(), count))
+            .sorted_by(|(a_value, a_count), (b_value, b_count)| {
+                b_count.cmp(a_count).then_with(|| a_value.cmp(b_value))
+            })
             .collect(),
     )
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    fn sample_dataframe() -> DataFrame {
+        polars::df!(
+            "label" => ["b", "a", "b", "a"],
+            "score" => [0.1, 0.4, 0.3, 0.2],
+        )
+        .unwrap()
+    }
+
+    fn row_ids(dataframe: &DataFrame) -> Vec<u32> {
+        dataframe
+            .column("__FleximRowId")
+            .unwrap()
+            .idx()
+            .unwrap()
+            .into_no_null_iter()
+            .collect()
+    }
 
     #[test]
     fn it_works() {}
+
+    #[test]
+    fn toggle_sort_cycles_ascending_descending_none() {
+        let mut state = FlTableState::new(&sample_dataframe(), &Default::default());
+
+        state.toggle_sort("score", false);
+        assert_eq!(state.sort_indicator("score").as_deref(), Some("⬆"));
+        state.toggle_sort("score", false);
+        assert_eq!(state.sort_indicator("score").as_deref(), Some("⬇"));
+        state.toggle_sort("score", false);
+        assert!(state.sort.is_empty());
+    }
+
+    #[test]
+    fn toggle_sort_without_shift_replaces_keys() {
+        let mut state = FlTableState::new(&sample_dataframe(), &Default::default());
+
+        state.toggle_sort("label", false);
+        state.toggle_sort("score", true);
+        assert_eq!(state.sort.len(), 2);
+        assert_eq!(state.sort_indicator("score").as_deref(), Some("⬆2"));
+
+        state.toggle_sort("score", false);
+        assert_eq!(
+            state.sort,
+            vec![ColumnSort {
+                column: "score".to_string(),
+                descending: true,
+            }]
+        );
+    }
+
+    #[test]
+    fn categorical_filter_counts_and_selection() {
+        let dataframe = sample_dataframe();
+        let series = dataframe.column("label").unwrap().as_materialized_series();
+        assert_eq!(
+            unique_counts_series(series),
+            Some(vec![("a".to_string(), 2), ("b".to_string(), 2)])
+        );
+
+        assert!(Filter::Categorical(None)
+            .apply(series, None)
+            .unwrap()
+            .is_none());
+        let none = Filter::Categorical(Some(HashSet::new()))
+            .apply(series, None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(none.sum(), Some(0));
+        let only_a = Filter::Categorical(Some(["a".to_string()].into_iter().collect()))
+            .apply(series, None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(only_a.sum(), Some(2));
+    }
+
+    #[test]
+    fn search_filter_modes() {
+        let series = Series::new("name".into(), [Some("Apple"), Some("pineapple"), None]);
+        let search = |query: &str, mode: SearchMode, negate: bool| {
+            Filter::Search {
+                query: query.to_string(),
+                mode,
+                negate,
+            }
+            .apply(&series, None)
+            .unwrap()
+            .unwrap()
+            .into_iter()
+            .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            search("apple", SearchMode::Literal, false),
+            vec![Some(false), Some(true), None]
+        );
+        assert_eq!(
+            search("apple", SearchMode::CaseInsensitive, false),
+            vec![Some(true), Some(true), None]
+        );
+        assert_eq!(
+            search("^[A-Z]", SearchMode::Regex, false),
+            vec![Some(true), Some(false), None]
+        );
+        assert_eq!(
+            search("Apple", SearchMode::Exact, true),
+            vec![Some(false), Some(true), None]
+        );
+        assert_eq!(
+            search("pine", SearchMode::StartsWith, false),
+            vec![Some(false), Some(true), None]
+        );
+    }
+
+    #[test]
+    fn invalid_regex_is_reported() {
+        let filter = Filter::Search {
+            query: "(unclosed".to_string(),
+            mode: SearchMode::Regex,
+            negate: false,
+        };
+        assert!(filter.validate(&DataType::String).is_err());
+        assert!(Filter::Search {
+            query: "(unclosed".to_string(),
+            mode: SearchMode::Literal,
+            negate: false,
+        }
+        .validate(&DataType::String)
+        .is_ok());
+    }
+
+    #[test]
+    fn filter_error_is_revalidated_when_filter_changes() {
+        let mut filter = ColumnFilter::from_series(&Series::new("name".into(), ["apple"]), None);
+        assert_eq!(filter.error(), None);
+
+        let Some(Filter::Search { query, mode, .. }) = &mut filter.filter else {
+            panic!("expected a search filter");
+        };
+        *query = "(unclosed".to_string();
+        *mode = SearchMode::Regex;
+        assert!(filter.error().is_some());
+
+        let Some(Filter::Search { mode, .. }) = &mut filter.filter else {
+            unreachable!();
+        };
+        *mode = SearchMode::Literal;
+        assert_eq!(filter.error(), None);
+    }
+
+    #[test]
+    fn rectangle_filter_by_area_and_viewport() {
+        let x1 = Series::new("x1".into(), [0.0, 10.0, 100.0]);
+        let y1 = Series::new("y1".into(), [0.0, 10.0, 100.0]);
+        let x2 = Series::new("x2".into(), [2.0, 20.0, 110.0]);
+        let y2 = Series::new("y2".into(), [2.0, 20.0, 110.0]);
+        let face = StructChunked::from_series("face".into(), 3, [x1, y1, x2, y2].iter())
+            .unwrap()
+            .into_series();
+        let dataframe = DataFrame::new(vec![face.into()]).unwrap();
+        let special_columns = [("face".to_string(), FlDataFrameSpecialColumn::Rectangle)]
+            .into_iter()
+            .collect();
+        let mut state = FlTableState::new(&dataframe, &special_columns);
+
+        let filter = state.filters.get_mut("face").unwrap();
+        assert_eq!(
+            filter.aggregated.shape_ranges.first(),
+            Some(&(ShapeMetric::Area, 4.0, 100.0))
+        );
+        let Some(Filter::Shape {
+            min,
+            intersects_viewport,
+            ..
+        }) = &mut filter.filter
+        else {
+            panic!("expected shape filter");
+        };
+        *min = 50.0;
+        *intersects_viewport = true;
+        assert!(state.uses_viewport());
+        assert_eq!(row_ids(&compute_dataframe(&dataframe, &state)), vec![1, 2]);
+
+        state.viewport = Some(FlDataFrameRectangle {
+            x1: 0.0,
+            y1: 0.0,
+            x2: 50.0,
+            y2: 50.0,
+        });
+        assert_eq!(row_ids(&compute_dataframe(&dataframe, &state)), vec![1]);
+    }
+
+    #[test]
+    fn segment_length_and_viewport_intersection() {
+        let x1 = Series::new("x1".into(), [0.0, 0.0]);
+        let y1 = Series::new("y1".into(), [0.0, 0.0]);
+        let x2 = Series::new("x2".into(), [3.0, 100.0]);
+        let y2 = Series::new("y2".into(), [4.0, 0.0]);
+        let segment = StructChunked::from_series("segment".into(), 2, [x1, y1, x2, y2].iter())
+            .unwrap()
+            .into_series();
+
+        let length = ShapeMetric::Length.evaluate(&segment).unwrap();
+        assert_eq!(
+            length.into_iter().collect_vec(),
+            vec![Some(5.0), Some(100.0)]
+        );
+
+        // 端点は表示領域外だが線分が領域を横切る
+        let viewport = FlDataFrameRectangle {
+            x1: 40.0,
+            y1: -1.0,
+            x2: 60.0,
+            y2: 1.0,
+        };
+        let mask = shape_filter::intersects_viewport(
+            &segment,
+            &FlDataFrameSpecialColumn::Segment,
+            &viewport,
+        )
+        .unwrap();
+        assert_eq!(
+            mask.into_iter().collect_vec(),
+            vec![Some(false), Some(true)]
+        );
+    }
+
+    #[test]
+    fn synchronize_copies_changed_filter_settings() {
+        let ctx = Context::default();
+        let dataframe = sample_dataframe();
+        let a = Arc::new(Mutex::new(FlTableState::new(
+            &dataframe,
+            &Default::default(),
+        )));
+        let b = Arc::new(Mutex::new(FlTableState::new(
+            &dataframe,
+            &Default::default(),
+        )));
+        let states = [(Id::new("a"), a.clone()), (Id::new("b"), b.clone())];
+        synchronize_table_states(&ctx, &states);
+
+        b.lock().unwrap().expression_filter = "score > 0.2".to_string();
+        b.lock().unwrap().toggle_sort("label", false);
+        synchronize_table_states(&ctx, &states);
+        assert_eq!(a.lock().unwrap().expression_filter, "score > 0.2");
+        assert_eq!(a.lock().unwrap().sort, b.lock().unwrap().sort);
+
+        a.lock().unwrap().toggle_sort("label", false);
+        synchronize_table_states(&ctx, &states);
+        assert!(b.lock().unwrap().sort[0].descending);
+    }
+
+    #[test]
+    fn compute_dataframe_applies_expression_filter() {
+        let dataframe = sample_dataframe();
+        let mut state = FlTableState::new(&dataframe, &Default::default());
+        state.expression_filter = r#"score >= 0.2 && label == "a""#.to_string();
+        assert_eq!(row_ids(&compute_dataframe(&dataframe, &state)), vec![1, 3]);
+
+        state.expression_filter = "score >".to_string();
+        assert_eq!(row_ids(&compute_dataframe(&dataframe, &state)).len(), 4);
+    }
+
+    #[test]
+    fn compute_dataframe_sorts_by_multiple_columns() {
+        let dataframe = sample_dataframe();
+        let mut state = FlTableState::new(&dataframe, &Default::default());
+        state.toggle_sort("label", false);
+        state.toggle_sort("score", true);
+        state.toggle_sort("score", true);
+
+        let computed = compute_dataframe(&dataframe, &state);
+        assert_eq!(row_ids(&computed), vec![1, 3, 2, 0]);
+        assert_eq!(row_position(&computed, 2), Some(2));
+    }
+
+    #[test]
+    fn selected_row_position_is_cached_per_generation() {
+        let ctx = Context::default();
+        let id = Id::new("selected row position test");
+        let dataframe = sample_dataframe();
+        let mut state = FlTableState::new(&dataframe, &Default::default());
+        state.toggle_sort("score", true);
+        let sorted = compute_dataframe(&dataframe, &state);
+        let unsorted = compute_dataframe(
+            &dataframe,
+            &FlTableState::new(&dataframe, &Default::default()),
+        );
+
+        assert_eq!(row_ids(&sorted), vec![0, 3, 2, 1]);
+
+        assert_eq!(selected_row_position(&ctx, id, 1, &sorted, 1), Some(3));
+        // 同じ世代と選択なら探し直さない
+        assert_eq!(selected_row_position(&ctx, id, 1, &unsorted, 1), Some(3));
+        assert_eq!(selected_row_position(&ctx, id, 2, &unsorted, 1), Some(1));
+        assert_eq!(selected_row_position(&ctx, id, 2, &unsorted, 3), Some(3));
+    }
+
+    #[test]
+    fn request_compute_only_when_filter_settings_change() {
+        let ctx = Context::default();
+        let id = Id::new("request compute test");
+        let dataframe = Arc::new(FlDataFrame::new(sample_dataframe(), Default::default()));
+        let mut state = FlTableState::new(&dataframe.value, &dataframe.special_columns);
+
+        let first = request_compute(&ctx, id, &dataframe, &state);
+        assert!(first.is_some());
+        assert_eq!(request_compute(&ctx, id, &dataframe, &state), None);
+
+        state.highlight.insert(0);
+        state.selected = Some(1);
+        assert_eq!(request_compute(&ctx, id, &dataframe, &state), None);
+
+        state.toggle_sort("score", false);
+        let second = request_compute(&ctx, id, &dataframe, &state);
+        assert!(second > first);
+        assert_eq!(request_compute(&ctx, id, &dataframe, &state), None);
+    }
 }
@@ -0,0 +1,162 @@
+use enum_iterator::{all, Sequence};
+use flexim_data_type::{FlDataFrameRectangle, FlDataFrameSpecialColumn};
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// Rectangle / Segment の特殊カラムから導出する値
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Sequence)]
+pub enum ShapeMetric {
+    Area,
+    Width,
+    Height,
+    AspectRatio,
+    Length,
+    Angle,
+}
+
+impl Display for ShapeMetric {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Area => write!(f, "Area"),
+            Self::Width => write!(f, "Width"),
+            Self::Height => write!(f, "Height"),
+            Self::AspectRatio => write!(f, "Aspect Ratio"),
+            Self::Length => write!(f, "Length"),
+            Self::Angle => write!(f, "Angle"),
+        }
+    }
+}
+
+impl ShapeMetric {
+    pub fn is_applicable(&self, special_column: &FlDataFrameSpecialColumn) -> bool {
+        match special_column {
+            FlDataFrameSpecialColumn::Rectangle => matches!(
+                self,
+                Self::Area | Self::Width | Self::Height | Self::AspectRatio
+            ),
+            FlDataFrameSpecialColumn::Segment => matches!(self, Self::Length | Self::Angle),
+            FlDataFrameSpecialColumn::Color => false,
+        }
+    }
+
+    pub fn applicable(special_column: &FlDataFrameSpecialColumn) -> Vec<Self> {
+        all::<Self>()
+            .filter(|m| m.is_applicable(special_column))
+            .collect()
+    }
+
+    fn compute(&self, x1: f64, y1: f64, x2: f64, y2: f64) -> f64 {
+        let (dx, dy) = (x2 - x1, y2 - y1);
+        match self {
+            Self::Area => (dx * dy).abs(),
+            Self::Width => dx.abs(),
+            Self::Height => dy.abs(),
+            Self::AspectRatio => dx.abs() / dy.abs(),
+            Self::Length => dx.hypot(dy),
+            // x軸からの角度 (度)
+            Self::Angle => dy.atan2(dx).to_degrees(),
+        }
+    }
+
+    /// 各行の値を計算する。null の行や計算できない値 (高さ0の縦横比など) は null になる
+    pub fn evaluate(&self, series: &Series) -> PolarsResult<Float64Chunked> {
+        Ok(coordinates(series)?
+            .map(|c| {
+                let (x1, y1, x2, y2) = c?;
+                Some(self.compute(x1, y1, x2, y2)).filter(|v| v.is_finite())
+            })
+            .collect())
+    }
+
+    /// 有限な値の最小値と最大値
+    pub fn min_max(&self, series: &Series) -> Option<(f64, f64)> {
+        let values = self.evaluate(series).ok()?;
+        Some((values.min()?, values.max()?))
+    }
+}
+
+/// 図形が `viewport` と交差しているかどうか
+pub fn intersects_viewport(
+    series: &Series,
+    special_column: &FlDataFrameSpecialColumn,
+    viewport: &FlDataFrameRectangle,
+) -> PolarsResult<BooleanChunked> {
+    let (vx1, vx2) = (viewport.x1.min(viewport.x2), viewport.x1.max(viewport.x2));
+    let (vy1, vy2) = (viewport.y1.min(viewport.y2), viewport.y1.max(viewport.y2));
+    Ok(coordinates(series)?
+        .map(|c| {
+            let (x1, y1, x2, y2) = c?;
+            Some(match special_column {
+                FlDataFrameSpecialColumn::Segment => {
+                    segment_intersects_rect((x1, y1), (x2, y2), (vx1, vy1), (vx2, vy2))
+                }
+                _ => {
+                    x1.min(x2) <= vx2 && vx1 <= x1.max(x2) && y1.min(y2) <= vy2 && vy1 <= y1.max(y2)
+                }
+            })
+        })
+        .collect())
+}
+
+/// Liang-Barsky 法で線分を矩形にクリップできるか判定する
+fn segment_intersects_rect(
+    p1: (f64, f64),
+    p2: (f64, f64),
+    min: (f64, f64),
+    max: (f64, f64),
+) -> bool {
+    let (dx, dy) = (p2.0 - p1.0, p2.1 - p1.1);
+    let mut t0 = 0.0_f64;
+    let mut t1 = 1.0_f64;
+    for (p, q) in [
+        (-dx, p1.0 - min.0),
+        (dx, max.0 - p1.0),
+        (-dy, p1.1 - min.1),
+        (dy, max.1 - p1.1),
+    ] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return false;
+            }
+        } else {
+            let t = q / p;
+            if p < 0.0 {
+                t0 = t0.max(t);
+            } else {
+                t1 = t1.min(t);
+            }
+            if t0 > t1 {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+type Coordinates = (f64, f64, f64, f64);
+
+fn coordinates(series: &Series) -> PolarsResult<impl Iterator<Item = Option<Coordinates>>> {
+    let validity = series.is_not_null();
+    let structs = series.struct_()?;
+    let field = |name: &str| -> PolarsResult<Float64Chunked> {
+        Ok(structs
+            .field_by_name(name)?
+            .cast(&DataType::Float64)?
+            .f64()?
+            .clone())
+    };
+    let (x1, y1, x2, y2) = (field("x1")?, field("y1")?, field("x2")?, field("y2")?);
+    let values = validity
+        .into_iter()
+        .zip(x1.iter().zip(y1.iter()))
+        .zip(x2.iter().zip(y2.iter()))
+        .map(|((valid, (x1, y1)), (x2, y2))| {
+            if valid != Some(true) {
+                return None;
+            }
+            Some((x1?, y1?, x2?, y2?))
+        })
+        .collect::<Vec<_>>();
+    Ok(values.into_iter())
+}
@@ -1,7 +1,7 @@
 from pydantic import BaseModel
 
 from flexim_py.client import create_bag, append_data
-from flexim_py.data_type import ImageData, DataFrameData, Tensor2DData
+from flexim_py.data_type import ImageData, DataFrameData, Tensor2DData, Transform
 
 
 class Bag(BaseModel):
@@ -19,7 +19,12 @@ class Bag(BaseModel):
     def __exit__(self, exc_type, exc_val, exc_tb):
         self.connected_id = None
 
-    def append_data(self, name: str, data: ImageData | DataFrameData | Tensor2DData):
+    def append_data(
+            self,
+            name: str,
+            data: ImageData | DataFrameData | Tensor2DData,
+            transform: Transform | None = None,
+    ):
         if self.connected_id is None:
             raise RuntimeError("Bag is not connected")
-        append_data(self.connected_id, name, data)
+        append_data(self.connected_id, name, data, transform)
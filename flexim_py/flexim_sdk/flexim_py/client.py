@@ -9,7 +9,7 @@ from grpc import Channel
 from pydantic import BaseModel, ConfigDict
 
 from flexim_py.data_type import ImageData, DataFrameData, Tensor2DData, SpecialColumn, Rectangle, Segment, Color, \
-    ObjectData
+    ObjectData, Transform
 from flexim_py.pb import connect_pb2, connect_pb2_grpc
 from flexim_py.utility import batched
 from flexim_py._flexim_py_lib import start_localstorage_server
@@ -63,7 +63,12 @@ def create_bag(name: str) -> int:
     return response.id
 
 
-def append_data(bag_id: int, name: str, data: ImageData | DataFrameData | Tensor2DData):
+def append_data(
+        bag_id: int,
+        name: str,
+        data: ImageData | DataFrameData | Tensor2DData,
+        transform: Transform | None = None,
+):
     global global_client
 
     if not _validate_data(data):
@@ -81,6 +86,7 @@ def append_data(bag_id: int, name: str, data: ImageData | DataFrameData | Tensor
                     name=name,
                     data_type=_data_type_to_proto(data),
                     special_columns=_dataframe_special_columns(data) if data.type == "DataFrame" else {},
+                    transform=_transform_to_proto(transform) if transform is not None else None,
                 ),
             )
         ]
@@ -106,6 +112,16 @@ def _data_type_to_proto(
         raise RuntimeError(f"Unknown data type {type(data)}")
 
 
+def _transform_to_proto(transform: Transform) -> connect_pb2.AppendDataRequest.DataMeta.Transform:
+    return connect_pb2.AppendDataRequest.DataMeta.Transform(
+        offset_x=transform.offset_x,
+        offset_y=transform.offset_y,
+        scale_x=transform.scale_x,
+        scale_y=transform.scale_y,
+        rotation=transform.rotation,
+    )
+
+
 def _dataframe_special_columns(
         data: DataFrameData,
 ) -> dict[str, connect_pb2.AppendDataRequest.DataMeta.SpecialColumn]:
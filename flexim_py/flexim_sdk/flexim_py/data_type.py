@@ -38,6 +38,15 @@ class Color(BaseModel):
     b: float
 
 
+class Transform(BaseModel):
+    """表示時にデータへ適用するアフィン変換 (拡大縮小、回転 (度数法)、平行移動の順)"""
+    offset_x: float = 0.0
+    offset_y: float = 0.0
+    scale_x: float = 1.0
+    scale_y: float = 1.0
+    rotation: float = 0.0
+
+
 class ImageData(BaseModel):
     type: Literal["Image"] = "Image"
     image: npt.NDArray[np.uint8]
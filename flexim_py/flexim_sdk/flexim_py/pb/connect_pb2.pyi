@@ -34,7 +34,7 @@ class CreateBagResponse(_message.Message):
 class AppendDataRequest(_message.Message):
     __slots__ = ("meta", "data_bytes")
     class DataMeta(_message.Message):
-        __slots__ = ("bag_id", "name", "data_type", "special_columns")
+        __slots__ = ("bag_id", "name", "data_type", "special_columns", "transform")
         class SpecialColumn(int, metaclass=_enum_type_wrapper.EnumTypeWrapper):
             __slots__ = ()
             Rectangle: _ClassVar[AppendDataRequest.DataMeta.SpecialColumn]
@@ -54,15 +54,30 @@ class AppendDataRequest(_message.Message):
             key: str
             value: AppendDataRequest.DataMeta.SpecialColumn
             def __init__(self, key: _Optional[str] = ..., value: _Optional[_Union[AppendDataRequest.DataMeta.SpecialColumn, str]] = ...) -> None: ...
+        class Transform(_message.Message):
+            __slots__ = ("offset_x", "offset_y", "scale_x", "scale_y", "rotation")
+            OFFSET_X_FIELD_NUMBER: _ClassVar[int]
+            OFFSET_Y_FIELD_NUMBER: _ClassVar[int]
+            SCALE_X_FIELD_NUMBER: _ClassVar[int]
+            SCALE_Y_FIELD_NUMBER: _ClassVar[int]
+            ROTATION_FIELD_NUMBER: _ClassVar[int]
+            offset_x: float
+            offset_y: float
+            scale_x: float
+            scale_y: float
+            rotation: float
+            def __init__(self, offset_x: _Optional[float] = ..., offset_y: _Optional[float] = ..., scale_x: _Optional[float] = ..., scale_y: _Optional[float] = ..., rotation: _Optional[float] = ...) -> None: ...
         BAG_ID_FIELD_NUMBER: _ClassVar[int]
         NAME_FIELD_NUMBER: _ClassVar[int]
         DATA_TYPE_FIELD_NUMBER: _ClassVar[int]
         SPECIAL_COLUMNS_FIELD_NUMBER: _ClassVar[int]
+        TRANSFORM_FIELD_NUMBER: _ClassVar[int]
         bag_id: int
         name: str
         data_type: DataType
         special_columns: _containers.ScalarMap[str, AppendDataRequest.DataMeta.SpecialColumn]
-        def __init__(self, bag_id: _Optional[int] = ..., name: _Optional[str] = ..., data_type: _Optional[_Union[DataType, str]] = ..., special_columns: _Optional[_Mapping[str, AppendDataRequest.DataMeta.SpecialColumn]] = ...) -> None: ...
+        transform: AppendDataRequest.DataMeta.Transform
+        def __init__(self, bag_id: _Optional[int] = ..., name: _Optional[str] = ..., data_type: _Optional[_Union[DataType, str]] = ..., special_columns: _Optional[_Mapping[str, AppendDataRequest.DataMeta.SpecialColumn]] = ..., transform: _Optional[_Union[AppendDataRequest.DataMeta.Transform, _Mapping]] = ...) -> None: ...
     META_FIELD_NUMBER: _ClassVar[int]
     DATA_BYTES_FIELD_NUMBER: _ClassVar[int]
     meta: AppendDataRequest.DataMeta
@@ -18,7 +18,7 @@ from flexim_py.data_type import (
     Rectangle,
     DataFrameData,
     Segment,
-    SpecialColumn, Tensor2DData, Color, ObjectData,
+    SpecialColumn, Tensor2DData, Color, ObjectData, Transform,
 )
 
 test_df = pandas.DataFrame(
@@ -181,6 +181,21 @@ def test_append_color_data():
         )
 
 
+def test_append_data_with_transform():
+    with Bag(name="test_bag_with_transform") as bag:
+        bag.append_data(
+            "python-table-data",
+            DataFrameData.from_pandas(
+                test_df,
+                {
+                    "c": SpecialColumn.Rectangle,
+                    "d": SpecialColumn.Segment,
+                },
+            ),
+            transform=Transform(offset_x=10.0, offset_y=20.0, scale_x=0.5, scale_y=0.5, rotation=90.0),
+        )
+
+
 @pytest.mark.skip(reason="まだrustのライブラリをこちらに持って来れていないため")
 def test_dataframe_encode_and_decode():
     df = pandas.DataFrame(
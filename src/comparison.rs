@@ -0,0 +1,78 @@
+use crate::{collect_stack_tabs, App, TreeBehavior, UpdateAppEvent};
+use chrono::Local;
+use egui::{Context, Id, Ui};
+use egui_tiles::{Tile, Tree};
+use flexim_data_visualize::data_view::DataView;
+use flexim_layout::pane::{Pane, PaneContent};
+use flexim_storage::Bag;
+use itertools::Itertools;
+use std::sync::{Arc, RwLock};
+
+fn comparison_bags(app: &App) -> Vec<Arc<RwLock<Bag>>> {
+    app.comparison_bag_ids
+        .iter()
+        .filter_map(|&id| app.storage.get_bag(id).ok())
+        .collect_vec()
+}
+
+/// 同じレイアウトを複数の Bag について横に並べて表示する
+/// 可視化の `VisualizeState` はタイルごとに共有されるため、ズーム・パンは自動的に同期される
+pub fn comparison_view(app: &mut App, ui: &mut Ui) {
+    let bags = comparison_bags(app);
+    ui.horizontal(|ui| {
+        ui.label(format!("Comparing {} bags", bags.len()));
+        if ui.button("Exit").clicked() {
+            app.send_event(UpdateAppEvent::ExitComparison);
+        }
+    });
+    ui.separator();
+
+    let Some(root) = app.tree.root() else {
+        return;
+    };
+    ui.columns(bags.len(), |columns| {
+        for (i, (ui, bag)) in columns.iter_mut().zip(&bags).enumerate() {
+            ui.push_id(i, |ui| {
+                {
+                    let bag = bag.read().unwrap();
+                    ui.label(format!(
+                        "{} {}",
+                        bag.name,
+                        bag.created_at
+                            .with_timezone(&Local)
+                            .format("%Y-%m-%d %H:%M:%S")
+                    ));
+                }
+                // タブの切り替えなどを全ての列で共有するため、描画後のタイルを書き戻す
+                let mut tree =
+                    Tree::new(Id::new("comparison").with(i), root, app.tree.tiles.clone());
+                let mut behavior = TreeBehavior {
+                    current_bag: bag.clone(),
+                    stack_tabs: collect_stack_tabs(ui, &app.tree),
+                    current_tile_id: &mut app.current_tile_id,
+                };
+                tree.ui(&mut behavior, ui);
+                app.tree.tiles = tree.tiles;
+            });
+        }
+    });
+
+    synchronize_tables(app, ui.ctx(), &bags);
+}
+
+/// 比較中の Bag 間でテーブルのフィルタ・ソートを同期する
+fn synchronize_tables(app: &App, ctx: &Context, bags: &[Arc<RwLock<Bag>>]) {
+    let bags = bags.iter().map(|b| b.read().unwrap()).collect_vec();
+    let bags = bags.iter().map(|b| &**b).collect_vec();
+    for tile in app.tree.tiles.tiles() {
+        if let Tile::Pane(Pane {
+            content: PaneContent::DataView(view),
+            ..
+        }) = tile
+        {
+            if let DataView::FlDataFrameView(view) = view.as_ref() {
+                view.table.synchronize_across_bags(ctx, &bags);
+            }
+        }
+    }
+}
@@ -0,0 +1,365 @@
+use crate::{App, UpdateAppEvent};
+use anyhow::Context as _;
+use egui::{Button, ComboBox, Context, DragValue, Grid, Id, Ui, Window};
+use flexim_data_type::{
+    FlDataFrame, FlDataFrameSpecialColumn, FlDataReference, FlDataType, GenerationSelector,
+};
+use flexim_data_visualize::data_view::DataView;
+use flexim_data_visualize::visualize::{DataRender, FlDataFrameViewRender};
+use flexim_layout::pane::{into_pane_content, PaneContent};
+use flexim_storage::{BagId, StorageQuery};
+use flexim_table_widget::diff::{diff_dataframe, match_rows, DiffMatching, DIFF_COLOR_COLUMN};
+use itertools::Itertools;
+use std::sync::{Arc, Mutex};
+
+/// 比較対象の DataFrame
+#[derive(Debug, Clone, PartialEq)]
+struct DiffSource {
+    bag_id: BagId,
+    label: String,
+    reference: FlDataReference,
+}
+
+/// target の全カラムと矩形のカラム
+type DiffColumns = (Vec<String>, Vec<String>);
+
+/// バックグラウンドで作った差分。現在の Bag への追加は UI スレッドで行う
+#[derive(Debug, Clone)]
+struct ComputedDiff {
+    bag_id: BagId,
+    name: String,
+    diff: FlDataFrame,
+    overlay_column: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct DiffDialog {
+    open: bool,
+    /// 開いた時とコンボボックスを開いた時に更新する
+    sources: Option<Vec<DiffSource>>,
+    /// target を選び直すまで使い回す
+    columns: Option<(DiffSource, DiffColumns)>,
+    base: Option<DiffSource>,
+    target: Option<DiffSource>,
+    use_iou: bool,
+    key_column: String,
+    iou_column: String,
+    iou_threshold: f64,
+    error: Option<String>,
+    /// 差分を計算中か
+    computing: bool,
+    computed: Option<Result<ComputedDiff, String>>,
+}
+
+impl Default for DiffDialog {
+    fn default() -> Self {
+        Self {
+            open: false,
+            sources: None,
+            columns: None,
+            base: None,
+            target: None,
+            use_iou: false,
+            key_column: String::new(),
+            iou_column: String::new(),
+            iou_threshold: 0.5,
+            error: None,
+            computing: false,
+            computed: None,
+        }
+    }
+}
+
+fn diff_dialog_state(ctx: &Context) -> Arc<Mutex<DiffDialog>> {
+    ctx.memory_mut(|mem| {
+        mem.data
+            .get_temp_mut_or_default::<Arc<Mutex<DiffDialog>>>(Id::new("diff dialog"))
+            .clone()
+    })
+}
+
+pub fn open_diff_dialog(ctx: &Context) {
+    let state = diff_dialog_state(ctx);
+    let mut state = state.lock().unwrap();
+    state.open = true;
+    state.sources = None;
+}
+
+/// 全ての Bag の DataFrame (世代ごと)
+fn diff_sources(app: &App) -> Vec<DiffSource> {
+    let Ok(bags) = app.storage.list_bags() else {
+        return vec![];
+    };
+    bags.iter()
+        .flat_map(|bag| {
+            let bag = bag.read().unwrap();
+            bag.data_list
+                .iter()
+                .filter(|d| d.data.data_type() == FlDataType::DataFrame)
+                .map(|d| DiffSource {
+                    bag_id: bag.id,
+                    label: format!("{} / {} #{}", bag.name, d.name, d.generation),
+                    reference: FlDataReference::from(d.clone()),
+                })
+                .collect_vec()
+        })
+        .sorted_by(|a, b| a.label.cmp(&b.label))
+        .collect()
+}
+
+fn load_dataframe(app: &App, source: &DiffSource) -> anyhow::Result<Arc<FlDataFrame>> {
+    let bag = app.storage.get_bag(source.bag_id)?;
+    let bag = bag.read().unwrap();
+    bag.data_by_reference(&source.reference)?
+        .as_data_frame()
+        .context("not a dataframe")
+}
+
+fn target_columns(app: &App, target: &DiffSource) -> DiffColumns {
+    let Ok(df) = load_dataframe(app, target) else {
+        return DiffColumns::default();
+    };
+    let rectangle_columns = df
+        .special_columns
+        .iter()
+        .filter(|(_, c)| **c == FlDataFrameSpecialColumn::Rectangle)
+        .map(|(name, _)| name.clone())
+        .sorted()
+        .collect_vec();
+    let all_columns = df
+        .value
+        .get_column_names()
+        .iter()
+        .map(|c| c.to_string())
+        .collect_vec();
+    (all_columns, rectangle_columns)
+}
+
+/// コンボボックスを開いたら `true` を返す
+fn source_combo_box(
+    ui: &mut Ui,
+    label: &str,
+    value: &mut Option<DiffSource>,
+    sources: &[DiffSource],
+) -> bool {
+    ui.label(label);
+    let response = ComboBox::from_id_salt(Id::new("diff source").with(label))
+        .width(300.0)
+        .selected_text(value.as_ref().map(|s| s.label.as_str()).unwrap_or(""))
+        .show_ui(ui, |ui| {
+            for source in sources {
+                ui.selectable_value(value, Some(source.clone()), source.label.as_str());
+            }
+        })
+        .response;
+    ui.end_row();
+    response.clicked()
+}
+
+/// base と target の差分を現在の Bag に `{target} diff` として追加し、テーブルとオーバーレイを開く
+pub fn diff_dialog(app: &App, ctx: &Context) {
+    let shared_state = diff_dialog_state(ctx);
+    let mut state = shared_state.lock().unwrap();
+    if let Some(computed) = state.computed.take() {
+        match computed
+            .map_err(anyhow::Error::msg)
+            .and_then(|computed| insert_diff(app, computed))
+        {
+            Ok(()) => {
+                state.error = None;
+                state.open = false;
+            }
+            // 計算中にダイアログを閉じていてもエラーが見えるように開き直す
+            Err(e) => {
+                state.error = Some(format!("{:#}", e));
+                state.open = true;
+            }
+        }
+    }
+    if !state.open {
+        return;
+    }
+    let state = &mut *state;
+    let sources = state.sources.get_or_insert_with(|| diff_sources(app));
+    if let Some(target) = &state.target {
+        if !matches!(&state.columns, Some((key, _)) if key == target) {
+            state.columns = Some((target.clone(), target_columns(app, target)));
+        }
+    }
+    let no_columns = DiffColumns::default();
+    let columns = match (&state.target, &state.columns) {
+        (Some(_), Some((_, columns))) => columns,
+        _ => &no_columns,
+    };
+
+    let mut open = state.open;
+    let mut create = false;
+    let mut refresh_sources = false;
+    Window::new("DataFrame Diff")
+        .open(&mut open)
+        .show(ctx, |ui| {
+            Grid::new("diff sources").show(ui, |ui| {
+                refresh_sources |= source_combo_box(ui, "Base", &mut state.base, sources);
+                refresh_sources |= source_combo_box(ui, "Target", &mut state.target, sources);
+            });
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut state.use_iou, false, "Key Column");
+                ui.radio_value(&mut state.use_iou, true, "Rectangle IoU");
+            });
+            let (all_columns, rectangle_columns) = columns;
+            if state.use_iou {
+                ui.horizontal(|ui| {
+                    ui.label("Column");
+                    ComboBox::from_id_salt("diff iou column")
+                        .selected_text(state.iou_column.as_str())
+                        .show_ui(ui, |ui| {
+                            for c in rectangle_columns {
+                                ui.selectable_value(&mut state.iou_column, c.clone(), c.as_str());
+                            }
+                        });
+                    ui.label("Threshold");
+                    ui.add(
+                        DragValue::new(&mut state.iou_threshold)
+                            .range(0.0..=1.0)
+                            .speed(0.01),
+                    );
+                });
+            } else {
+                ui.horizontal(|ui| {
+                    ui.label("Column");
+                    ComboBox::from_id_salt("diff key column")
+                        .selected_text(state.key_column.as_str())
+                        .show_ui(ui, |ui| {
+                            for c in all_columns {
+                                ui.selectable_value(&mut state.key_column, c.clone(), c.as_str());
+                            }
+                        });
+                });
+            }
+            if let Some(error) = &state.error {
+                ui.colored_label(ui.visuals().error_fg_color, error);
+            }
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(!state.computing, Button::new("Create Diff"))
+                    .clicked()
+                {
+                    create = true;
+                }
+                if state.computing {
+                    ui.spinner();
+                }
+            });
+        });
+    state.open = open;
+    if refresh_sources {
+        state.sources = None;
+    }
+
+    if create {
+        match create_diff(app, ctx, &shared_state, state) {
+            Ok(()) => {
+                state.error = None;
+                state.computing = true;
+            }
+            Err(e) => state.error = Some(format!("{:#}", e)),
+        }
+    }
+}
+
+/// 行の対応付けは矩形の数によっては時間がかかるので、バックグラウンドで計算する
+fn create_diff(
+    app: &App,
+    ctx: &Context,
+    shared_state: &Arc<Mutex<DiffDialog>>,
+    state: &DiffDialog,
+) -> anyhow::Result<()> {
+    let bag_id = app.current_bag_id.context("no bag selected")?;
+    let base = state.base.as_ref().context("base is not selected")?;
+    let target = state.target.as_ref().context("target is not selected")?;
+    let matching = if state.use_iou {
+        anyhow::ensure!(!state.iou_column.is_empty(), "column is not selected");
+        DiffMatching::Iou {
+            column: state.iou_column.clone(),
+            threshold: state.iou_threshold,
+        }
+    } else {
+        anyhow::ensure!(!state.key_column.is_empty(), "column is not selected");
+        DiffMatching::Key(state.key_column.clone())
+    };
+
+    let base_dataframe = load_dataframe(app, base)?;
+    let target_dataframe = load_dataframe(app, target)?;
+    let name = format!("{} diff", target.reference.name);
+
+    let shared_state = shared_state.clone();
+    let ctx = ctx.clone();
+    std::thread::spawn(move || {
+        let computed = compute_diff(&base_dataframe, &target_dataframe, &matching)
+            .map(|(diff, overlay_column)| ComputedDiff {
+                bag_id,
+                name,
+                diff,
+                overlay_column,
+            })
+            .map_err(|e| format!("{:#}", e));
+        let mut state = shared_state.lock().unwrap();
+        state.computing = false;
+        state.computed = Some(computed);
+        ctx.request_repaint();
+    });
+    Ok(())
+}
+
+/// 差分と、オーバーレイに使うカラム
+fn compute_diff(
+    base: &FlDataFrame,
+    target: &FlDataFrame,
+    matching: &DiffMatching,
+) -> anyhow::Result<(FlDataFrame, Option<String>)> {
+    let rows = match_rows(base, target, matching)?;
+    let diff = diff_dataframe(base, target, &rows)?;
+    let overlay_column = match matching {
+        DiffMatching::Iou { column, .. } => Some(column.clone()),
+        DiffMatching::Key(_) => diff
+            .special_columns
+            .iter()
+            .filter(|(_, c)| c.visualizable_attribute())
+            .map(|(name, _)| name.clone())
+            .min(),
+    };
+    Ok((diff, overlay_column))
+}
+
+fn insert_diff(app: &App, computed: ComputedDiff) -> anyhow::Result<()> {
+    let ComputedDiff {
+        bag_id,
+        name,
+        diff,
+        overlay_column,
+    } = computed;
+    app.storage.insert_data(bag_id, name.clone(), diff.into())?;
+
+    let content = into_pane_content(FlDataReference::new(
+        name.clone(),
+        GenerationSelector::Latest,
+        FlDataType::DataFrame,
+    ))?;
+    if let (PaneContent::DataView(view), Some(column)) = (&content, overlay_column) {
+        if let DataView::FlDataFrameView(view) = view.as_ref() {
+            let render = FlDataFrameViewRender::new((**view).clone(), column.clone())
+                .with_color_scatter_column(DIFF_COLOR_COLUMN);
+            app.send_event(UpdateAppEvent::InsertTile {
+                title: format!("{} {}", column, name),
+                content: PaneContent::Visualize(Arc::new(DataRender::from(render))),
+            });
+        }
+    }
+    app.send_event(UpdateAppEvent::InsertTile {
+        title: name,
+        content,
+    });
+    Ok(())
+}
@@ -0,0 +1,197 @@
+use crate::{App, UpdateAppEvent};
+use egui::ahash::HashMap;
+use egui::{Context, Id, Modal};
+use egui_tiles::{Tile, Tree};
+use flexim_config::keymap::{is_capturing, KeyAction};
+use flexim_config::Config;
+use flexim_data_visualize::data_view::DataView;
+use flexim_layout::pane::{Pane, PaneContent};
+use flexim_table_widget::{FilterSettings, FlTableState};
+use std::sync::{Arc, Mutex};
+
+const MAX_HISTORY: usize = 100;
+
+/// 取り消し可能な操作の直前の状態
+enum HistoryEntry {
+    /// タイルの追加・削除、表示切り替え、レイアウトの切り替え、アノテーションの編集
+    Tree(Tree<Pane>),
+    /// テーブルのフィルタ・ソート
+    Filter { id: Id, settings: FilterSettings },
+}
+
+/// 現在の Bag のタイル構成とテーブルの状態に対する取り消し・やり直しの履歴
+#[derive(Default)]
+pub struct History {
+    undo: Vec<HistoryEntry>,
+    redo: Vec<HistoryEntry>,
+    /// 変更を検出するための各テーブルの直前の設定
+    filter_settings: HashMap<Id, FilterSettings>,
+}
+
+impl History {
+    fn push(&mut self, entry: HistoryEntry) {
+        self.undo.push(entry);
+        if self.undo.len() > MAX_HISTORY {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+
+    /// タイル構成を変更する直前の状態を記録する
+    pub fn record_tree(&mut self, tree: Tree<Pane>) {
+        self.push(HistoryEntry::Tree(tree));
+    }
+
+    /// タイル構成は Bag ごとに持つため、Bag を切り替えたら破棄する
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.filter_settings.clear();
+    }
+}
+
+fn table_state(ctx: &Context, id: Id) -> Option<Arc<Mutex<FlTableState>>> {
+    ctx.memory_mut(|mem| mem.data.get_temp(id))
+}
+
+/// `entry` の状態に戻し、戻す前の状態を返す
+fn restore(app: &mut App, ctx: &Context, entry: HistoryEntry) -> Option<HistoryEntry> {
+    match entry {
+        HistoryEntry::Tree(tree) => {
+            let current = std::mem::replace(&mut app.tree, tree);
+            if app
+                .current_tile_id
+                .is_some_and(|id| app.tree.tiles.get(id).is_none())
+            {
+                app.current_tile_id = None;
+            }
+            Some(HistoryEntry::Tree(current))
+        }
+        HistoryEntry::Filter { id, settings } => {
+            let state = table_state(ctx, id)?;
+            let mut state = state.lock().unwrap();
+            let current = state.filter_settings();
+            state.apply_filter_settings(&settings);
+            app.history
+                .filter_settings
+                .insert(id, state.filter_settings());
+            Some(HistoryEntry::Filter {
+                id,
+                settings: current,
+            })
+        }
+    }
+}
+
+pub fn undo(app: &mut App, ctx: &Context) {
+    let Some(entry) = app.history.undo.pop() else {
+        return;
+    };
+    if let Some(entry) = restore(app, ctx, entry) {
+        app.history.redo.push(entry);
+    }
+}
+
+pub fn redo(app: &mut App, ctx: &Context) {
+    let Some(entry) = app.history.redo.pop() else {
+        return;
+    };
+    if let Some(entry) = restore(app, ctx, entry) {
+        app.history.undo.push(entry);
+    }
+}
+
+pub fn handle_shortcuts(app: &App, ctx: &Context) {
+    if ctx.wants_keyboard_input() || is_capturing(ctx) {
+        return;
+    }
+    let keymap = Config::get_global_ctx(ctx).keymap;
+    if ctx.input(|input| keymap.pressed(input, KeyAction::Redo)) {
+        app.send_event(UpdateAppEvent::Redo);
+    } else if ctx.input(|input| keymap.pressed(input, KeyAction::Undo)) {
+        app.send_event(UpdateAppEvent::Undo);
+    }
+}
+
+/// テーブルのフィルタ・ソートの変更を履歴に記録する
+/// スライダーのドラッグ中や文字の入力中などの途中経過は記録せず、
+/// 操作が終わった (テキスト入力ではフォーカスが外れた) 時点でまとめて 1 つの履歴にする
+pub fn track_filter_changes(app: &mut App, ctx: &Context) {
+    if ctx.input(|input| input.pointer.any_down()) || ctx.wants_keyboard_input() {
+        return;
+    }
+    let Some(bag) = app.current_bag() else {
+        return;
+    };
+    let bag = bag.read().unwrap();
+    let mut changes = vec![];
+    for tile in app.tree.tiles.tiles() {
+        let Tile::Pane(Pane {
+            content: PaneContent::DataView(view),
+            ..
+        }) = tile
+        else {
+            continue;
+        };
+        let DataView::FlDataFrameView(view) = view.as_ref() else {
+            continue;
+        };
+        let Ok(id) = view.table.data_id(&bag) else {
+            continue;
+        };
+        let Some(state) = table_state(ctx, id) else {
+            continue;
+        };
+        let settings = state.lock().unwrap().filter_settings();
+        match app.history.filter_settings.insert(id, settings.clone()) {
+            Some(previous) if previous != settings => changes.push(HistoryEntry::Filter {
+                id,
+                settings: previous,
+            }),
+            _ => {}
+        }
+    }
+    for change in changes {
+        app.history.push(change);
+    }
+}
+
+/// 確認が必要な操作のダイアログ。OK で保留していたイベントを送る
+pub fn confirmation_dialog(app: &mut App, ctx: &Context) {
+    let Some((message, _)) = &app.pending_confirmation else {
+        return;
+    };
+    let mut confirmed = None;
+    let response = Modal::new(Id::new("confirmation")).show(ctx, |ui| {
+        ui.label(message.as_str());
+        ui.separator();
+        ui.horizontal(|ui| {
+            if ui.button("OK").clicked() {
+                confirmed = Some(true);
+            }
+            if ui.button("Cancel").clicked() {
+                confirmed = Some(false);
+            }
+        });
+    });
+    if response.should_close() && confirmed.is_none() {
+        confirmed = Some(false);
+    }
+    if let Some(confirmed) = confirmed {
+        if let Some((_, event)) = app.pending_confirmation.take() {
+            if confirmed {
+                app.send_event(*event);
+            }
+        }
+    }
+}
+
+impl UpdateAppEvent {
+    /// 確認ダイアログで OK が押されてから `event` を実行する
+    pub fn confirm(message: impl Into<String>, event: UpdateAppEvent) -> Self {
+        Self::Confirm {
+            message: message.into(),
+            event: Box::new(event),
+        }
+    }
+}
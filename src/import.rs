@@ -0,0 +1,351 @@
+use crate::App;
+use anyhow::Context as _;
+use egui::{Align2, Color32, ComboBox, Context, FontId, Grid, Id, LayerId, Order};
+use flexim_data_type::{
+    FlData, FlDataFrame, FlDataFrameSpecialColumn, FlImage, FlObject, FlTensor2D,
+};
+use flexim_layout::FlLayout;
+use flexim_storage::{Bag, Storage};
+use itertools::Itertools;
+use polars::prelude::{CsvReadOptions, DataFrame, DataType, IpcReader, ParquetReader, SerReader};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+pub const IMPORT_EXTENSIONS: &[&str] = &[
+    "png", "jpg", "jpeg", "csv", "parquet", "arrow", "ipc", "feather", "npy", "json",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TableFormat {
+    Csv,
+    Parquet,
+    Ipc,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportKind {
+    Image,
+    Table(TableFormat),
+    Tensor,
+    Object,
+}
+
+impl ImportKind {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" | "jpg" | "jpeg" => Some(Self::Image),
+            "csv" => Some(Self::Table(TableFormat::Csv)),
+            "parquet" => Some(Self::Table(TableFormat::Parquet)),
+            "arrow" | "ipc" | "feather" => Some(Self::Table(TableFormat::Ipc)),
+            "npy" => Some(Self::Tensor),
+            "json" => Some(Self::Object),
+            _ => None,
+        }
+    }
+}
+
+pub fn read_table(path: &Path, format: TableFormat) -> anyhow::Result<DataFrame> {
+    let dataframe = match format {
+        TableFormat::Csv => CsvReadOptions::default()
+            .with_has_header(true)
+            .try_into_reader_with_file_path(Some(path.to_path_buf()))?
+            .finish()?,
+        TableFormat::Parquet => ParquetReader::new(std::fs::File::open(path)?).finish()?,
+        TableFormat::Ipc => IpcReader::new(std::fs::File::open(path)?).finish()?,
+    };
+    Ok(dataframe)
+}
+
+/// テーブル以外のファイルを読み込む
+fn read_data(path: &Path, kind: ImportKind) -> anyhow::Result<FlData> {
+    let bytes = std::fs::read(path)?;
+    Ok(match kind {
+        ImportKind::Image => FlImage::try_from_encoded(bytes)?.into(),
+        ImportKind::Tensor => FlTensor2D::try_from_npy(&bytes)?.into(),
+        ImportKind::Object => FlObject::new(serde_json::from_slice(&bytes)?).into(),
+        ImportKind::Table(_) => unreachable!("tables are imported through the dialog"),
+    })
+}
+
+fn data_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "imported".to_string())
+}
+
+/// 特殊カラムの指定を待っているテーブル
+#[derive(Clone)]
+struct PendingTable {
+    name: String,
+    dataframe: DataFrame,
+    special_columns: Vec<(String, Option<FlDataFrameSpecialColumn>)>,
+    error: Option<String>,
+}
+
+impl PendingTable {
+    fn new(name: String, dataframe: DataFrame) -> Self {
+        let special_columns = dataframe
+            .get_columns()
+            .iter()
+            .filter(|c| matches!(c.dtype(), DataType::String | DataType::Struct(_)))
+            .map(|c| {
+                (
+                    c.name().to_string(),
+                    FlDataFrameSpecialColumn::guess(c.as_materialized_series()),
+                )
+            })
+            .collect();
+        Self {
+            name,
+            dataframe,
+            special_columns,
+            error: None,
+        }
+    }
+
+    fn into_data_frame(self) -> anyhow::Result<FlDataFrame> {
+        let special_columns = self
+            .special_columns
+            .into_iter()
+            .filter_map(|(name, special_column)| Some((name, special_column?)))
+            .collect();
+        FlDataFrame::try_with_special_columns(self.dataframe, special_columns)
+    }
+}
+
+type PendingTables = Arc<Mutex<VecDeque<PendingTable>>>;
+
+fn pending_tables(ctx: &Context) -> PendingTables {
+    ctx.memory_mut(|mem| {
+        mem.data
+            .get_temp_mut_or_insert_with(Id::new("import dialog"), PendingTables::default)
+            .clone()
+    })
+}
+
+type ImportErrors = Arc<Mutex<Vec<String>>>;
+
+fn import_errors(ctx: &Context) -> ImportErrors {
+    ctx.memory_mut(|mem| {
+        mem.data
+            .get_temp_mut_or_insert_with(Id::new("import errors"), ImportErrors::default)
+            .clone()
+    })
+}
+
+/// 読み込みの失敗をログに残し、ユーザーに見えるようにダイアログへ積む
+pub fn report_import_error(ctx: &Context, error: anyhow::Error) {
+    log::error!("{:#}", error);
+    import_errors(ctx)
+        .lock()
+        .unwrap()
+        .push(format!("{:#}", error));
+}
+
+/// ファイルを現在の Bag に読み込む。テーブルは特殊カラムを指定するダイアログを経由する
+pub fn import_files(app: &App, ctx: &Context, paths: impl IntoIterator<Item = PathBuf>) {
+    let Some(bag_id) = app.current_bag_id else {
+        report_import_error(
+            ctx,
+            anyhow::anyhow!("no bag selected: create or select a bag before importing"),
+        );
+        return;
+    };
+    for path in paths {
+        let result = match ImportKind::from_path(&path) {
+            Some(ImportKind::Table(format)) => read_table(&path, format).map(|dataframe| {
+                pending_tables(ctx)
+                    .lock()
+                    .unwrap()
+                    .push_back(PendingTable::new(data_name(&path), dataframe));
+            }),
+            Some(kind) => read_data(&path, kind)
+                .and_then(|data| app.storage.insert_data(bag_id, data_name(&path), data)),
+            None => Err(anyhow::anyhow!("unsupported file type")),
+        };
+        if let Err(e) = result.with_context(|| format!("failed to import {}", path.display())) {
+            report_import_error(ctx, e);
+        }
+    }
+}
+
+fn import_error_dialog(ctx: &Context) {
+    let import_errors = import_errors(ctx);
+    let mut import_errors = import_errors.lock().unwrap();
+    if import_errors.is_empty() {
+        return;
+    }
+
+    let mut open = true;
+    let mut dismiss = false;
+    egui::Window::new("Import Error")
+        .open(&mut open)
+        .show(ctx, |ui| {
+            for error in import_errors.iter() {
+                ui.colored_label(ui.visuals().error_fg_color, error);
+            }
+            ui.separator();
+            if ui.button("OK").clicked() {
+                dismiss = true;
+            }
+        });
+    if dismiss || !open {
+        import_errors.clear();
+    }
+}
+
+pub fn import_dialog(app: &App, ctx: &Context) {
+    import_error_dialog(ctx);
+
+    let pending_tables = pending_tables(ctx);
+    let mut pending_tables = pending_tables.lock().unwrap();
+    let Some(table) = pending_tables.front_mut() else {
+        return;
+    };
+
+    let mut open = true;
+    let mut import = false;
+    egui::Window::new("Import Table")
+        .open(&mut open)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Name");
+                ui.text_edit_singleline(&mut table.name);
+            });
+            ui.label(format!(
+                "{} rows × {} columns",
+                table.dataframe.height(),
+                table.dataframe.width()
+            ));
+            ui.separator();
+            ui.label("Special columns");
+            Grid::new("import special columns").show(ui, |ui| {
+                for (name, special_column) in table.special_columns.iter_mut() {
+                    ui.label(name.as_str());
+                    ComboBox::from_id_salt(Id::new("import special column").with(name.as_str()))
+                        .selected_text(match special_column {
+                            Some(s) => format!("{:?}", s),
+                            None => "None".to_string(),
+                        })
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(special_column, None, "None");
+                            for s in [
+                                FlDataFrameSpecialColumn::Rectangle,
+                                FlDataFrameSpecialColumn::Segment,
+                                FlDataFrameSpecialColumn::Color,
+                            ] {
+                                let label = format!("{:?}", s);
+                                ui.selectable_value(special_column, Some(s), label);
+                            }
+                        });
+                    ui.end_row();
+                }
+            });
+            if let Some(error) = &table.error {
+                ui.colored_label(ui.visuals().error_fg_color, error);
+            }
+            ui.separator();
+            if ui.button("Import").clicked() {
+                import = true;
+            }
+        });
+
+    if import {
+        let result = match app.current_bag_id {
+            Some(bag_id) => table.clone().into_data_frame().and_then(|dataframe| {
+                app.storage
+                    .insert_data(bag_id, table.name.clone(), dataframe.into())
+            }),
+            None => Err(anyhow::anyhow!("no bag selected")),
+        };
+        match result {
+            Ok(()) => {
+                pending_tables.pop_front();
+            }
+            Err(e) => table.error = Some(format!("{:#}", e)),
+        }
+    } else if !open {
+        pending_tables.pop_front();
+    }
+}
+
+pub fn load_bag_file(storage: &Storage, path: &Path) -> anyhow::Result<()> {
+    let buf_reader = std::io::BufReader::new(std::fs::File::open(path)?);
+    let bag = Bag::read_from(buf_reader).context("invalid bag file")?;
+    anyhow::ensure!(storage.load_bag(bag), "bag already exists");
+    Ok(())
+}
+
+pub fn read_layout_file(path: &Path) -> anyhow::Result<Vec<FlLayout>> {
+    let buf_reader = std::io::BufReader::new(std::fs::File::open(path)?);
+    Ok(serde_json::from_reader(buf_reader)?)
+}
+
+/// 同名のレイアウトは先に登録されている方を残す
+pub fn add_layouts(app: &mut App, layouts: Vec<FlLayout>) {
+    app.layouts.extend(layouts);
+    app.layouts = app
+        .layouts
+        .iter()
+        .unique_by(|l| &l.name)
+        .cloned()
+        .collect_vec();
+}
+
+/// ウィンドウにドロップされたファイルを読み込む
+/// `.bag` は Bag として、レイアウトの JSON はレイアウトとして、それ以外は現在の Bag のデータとして読み込む
+pub fn handle_dropped_files(app: &mut App, ctx: &Context) {
+    preview_hovered_files(ctx);
+
+    let dropped_files = ctx.input(|input| input.raw.dropped_files.clone());
+    let mut data_files = vec![];
+    for path in dropped_files.into_iter().filter_map(|f| f.path) {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("bag") => {
+                if let Err(e) = load_bag_file(&app.storage, &path) {
+                    report_import_error(
+                        ctx,
+                        e.context(format!("failed to load {}", path.display())),
+                    );
+                }
+            }
+            Some("json") => match read_layout_file(&path) {
+                Ok(layouts) => add_layouts(app, layouts),
+                Err(_) => data_files.push(path),
+            },
+            _ => data_files.push(path),
+        }
+    }
+    if !data_files.is_empty() {
+        import_files(app, ctx, data_files);
+    }
+}
+
+fn preview_hovered_files(ctx: &Context) {
+    let hovered_files = ctx.input(|input| input.raw.hovered_files.clone());
+    if hovered_files.is_empty() {
+        return;
+    }
+    let text = hovered_files
+        .iter()
+        .filter_map(|f| f.path.as_ref())
+        .map(|p| p.display().to_string())
+        .join("\n");
+
+    let painter = ctx.layer_painter(LayerId::new(Order::Foreground, Id::new("file_drop_target")));
+    let screen_rect = ctx.screen_rect();
+    painter.rect_filled(screen_rect, 0.0, Color32::from_black_alpha(192));
+    painter.text(
+        screen_rect.center(),
+        Align2::CENTER_CENTER,
+        format!("Drop to load:\n{}", text),
+        FontId::proportional(16.0),
+        Color32::WHITE,
+    );
+}
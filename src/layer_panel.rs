@@ -0,0 +1,125 @@
+use crate::{find_stack, App};
+use egui::{Button, CollapsingHeader, Label, Slider, Ui, Widget};
+use egui_tiles::{Container, Tile, TileId};
+use flexim_data_visualize::layer::LayerSettings;
+use flexim_layout::pane::Pane;
+
+/// 現在のタイルを含むスタックタブのレイヤーを操作するパネル
+/// 上に描画されるレイヤーから順に並べる
+pub fn layer_panel(app: &mut App, ui: &mut Ui) {
+    let Some(tile_id) = app.current_tile_id else {
+        return;
+    };
+    let Some((container_id, layers)) = find_stack(&app.tree, tile_id) else {
+        return;
+    };
+
+    let mut swap = None;
+    let mut changed = None;
+    CollapsingHeader::new("Layers")
+        .default_open(true)
+        .show(ui, |ui| {
+            for (i, &layer_id) in layers.iter().enumerate().rev() {
+                let Some(Tile::Pane(pane)) = app.tree.tiles.get_mut(layer_id) else {
+                    continue;
+                };
+                ui.push_id(layer_id, |ui| {
+                    ui.horizontal(|ui| {
+                        if let Some(previous) = layer_row(ui, pane) {
+                            changed = Some((layer_id, previous));
+                        }
+                        ui.add_enabled_ui(!pane.layer.locked, |ui| {
+                            if ui
+                                .add_enabled(i + 1 < layers.len(), Button::new("⬆"))
+                                .on_hover_text("Bring forward")
+                                .clicked()
+                            {
+                                swap = Some((layer_id, layers[i + 1]));
+                            }
+                            if ui
+                                .add_enabled(i > 0, Button::new("⬇"))
+                                .on_hover_text("Send backward")
+                                .clicked()
+                            {
+                                swap = Some((layer_id, layers[i - 1]));
+                            }
+                        });
+                    });
+                });
+            }
+        });
+
+    // 変更はこのフレームで反映済みなので、変更前の設定に戻したタイル構成を履歴に残す
+    if let Some((layer_id, previous)) = changed {
+        let mut tree = app.tree.clone();
+        if let Some(Tile::Pane(pane)) = tree.tiles.get_mut(layer_id) {
+            pane.layer = previous;
+        }
+        app.history.record_tree(tree);
+    }
+    if let Some((a, b)) = swap {
+        app.history.record_tree(app.tree.clone());
+        swap_layers(app, container_id, a, b);
+    }
+}
+
+/// 設定を変更したら変更前の設定を返す
+/// 不透明度はドラッグ中の途中経過を記録せず、ドラッグが終わった時点でまとめて 1 つの変更にする
+fn layer_row(ui: &mut Ui, pane: &mut Pane) -> Option<LayerSettings> {
+    let layer = &mut pane.layer;
+    let previous = layer.clone();
+    let mut changed = None;
+    if ui
+        .selectable_label(layer.locked, if layer.locked { "🔒" } else { "🔓" })
+        .on_hover_text("Lock")
+        .clicked()
+    {
+        layer.locked = !layer.locked;
+        changed = Some(previous.clone());
+    }
+    ui.add_enabled_ui(!layer.locked, |ui| {
+        if ui
+            .selectable_label(layer.visible, if layer.visible { "👁" } else { "‿" })
+            .on_hover_text("Visible")
+            .clicked()
+        {
+            layer.visible = !layer.visible;
+            changed = Some(previous.clone());
+        }
+        if ui
+            .selectable_label(layer.solo, "S")
+            .on_hover_text("Solo")
+            .clicked()
+        {
+            layer.solo = !layer.solo;
+            changed = Some(previous.clone());
+        }
+        let response = Slider::new(&mut layer.opacity, 0.0..=1.0)
+            .show_value(false)
+            .ui(ui)
+            .on_hover_text("Opacity");
+        if response.drag_started() {
+            ui.data_mut(|data| data.insert_temp(response.id, previous.clone()));
+        }
+        if response.drag_stopped() {
+            let started = ui.data_mut(|data| data.remove_temp::<LayerSettings>(response.id));
+            if let Some(started) = started.filter(|started| *started != *layer) {
+                changed = Some(started);
+            }
+        } else if response.changed() && !response.dragged() {
+            changed = Some(previous.clone());
+        }
+    });
+    Label::new(pane.name.as_str()).truncate().ui(ui);
+    changed
+}
+
+/// タブの並び順がそのまま描画順になる
+fn swap_layers(app: &mut App, container_id: TileId, a: TileId, b: TileId) {
+    if let Some(Tile::Container(Container::Tabs(tabs))) = app.tree.tiles.get_mut(container_id) {
+        let position = |id| tabs.children.iter().position(|&c| c == id);
+        if let (Some(i), Some(j)) = (position(a), position(b)) {
+            tabs.children.swap(i, j);
+        }
+    }
+}
@@ -1,10 +1,15 @@
+use crate::diff::open_diff_dialog;
+use crate::import::{
+    add_layouts, import_files, load_bag_file, read_layout_file, report_import_error,
+    IMPORT_EXTENSIONS,
+};
 use crate::{App, Managed, UpdateAppEvent};
 use chrono::Local;
 use egui::menu::menu_custom_button;
 use egui::scroll_area::ScrollBarVisibility;
 use egui::{
     global_theme_preference_switch, Button, CollapsingHeader, Id, Image, Label, Response,
-    ScrollArea, Sense, Ui, Vec2, Widget,
+    ScrollArea, SelectableLabel, Sense, Ui, Vec2, Widget,
 };
 use egui_tiles::Tile;
 use flexim_config::ConfigWindow;
@@ -12,7 +17,7 @@ use flexim_data_type::{FlDataReference, FlDataType};
 use flexim_layout::check::check_applicable;
 use flexim_layout::pane::{into_pane_content, Pane, PaneContent};
 use flexim_layout::FlLayout;
-use flexim_storage::{Bag, StorageQuery};
+use flexim_storage::{bag_group_name, Bag, StorageQuery};
 use flexim_utility::left_and_right_layout;
 use itertools::Itertools;
 use std::ops::DerefMut;
@@ -63,16 +68,10 @@ pub fn left_panel(app: &mut App, ui: &mut Ui) {
                     fd
                 };
                 if let Some(path) = fd.pick_file() {
-                    let buf_reader = std::io::BufReader::new(std::fs::File::open(&path).unwrap());
-                    let add_layouts: Vec<FlLayout> = serde_json::from_reader(buf_reader).unwrap();
-
-                    app.layouts.extend(add_layouts);
-                    app.layouts = app
-                        .layouts
-                        .iter()
-                        .unique_by(|l| &l.name)
-                        .cloned()
-                        .collect_vec();
+                    match read_layout_file(&path) {
+                        Ok(layouts) => add_layouts(app, layouts),
+                        Err(e) => log::error!("failed to load layout: {:#}", e),
+                    }
 
                     ui.ctx().memory_mut(|mem| {
                         mem.data.insert_persisted(
@@ -88,7 +87,10 @@ pub fn left_panel(app: &mut App, ui: &mut Ui) {
             }
 
             if ui.button("Clear Bags").clicked() {
-                app.storage.clear_bags();
+                app.send_event(UpdateAppEvent::confirm(
+                    "Remove all bags? This cannot be undone.",
+                    UpdateAppEvent::ClearBags,
+                ));
             }
         },
     );
@@ -126,11 +128,11 @@ fn data_bag_list_view(app: &App, ui: &mut Ui) {
                         fd
                     };
                     if let Some(file_path) = fd.pick_file() {
-                        let buf_reader =
-                            std::io::BufReader::new(std::fs::File::open(&file_path).unwrap());
-                        let bag: Bag = bincode::deserialize_from(buf_reader).unwrap();
-                        if !app.storage.load_bag(bag) {
-                            log::error!("bag already exists");
+                        if let Err(e) = load_bag_file(&app.storage, &file_path) {
+                            report_import_error(
+                                ui.ctx(),
+                                e.context(format!("failed to load {}", file_path.display())),
+                            );
                         }
 
                         ui.ctx().memory_mut(|mem| {
@@ -218,11 +220,24 @@ fn bag_view(app: &App, ui: &mut Ui, bag: &Bag, label: String) {
             if ui.button("+").clicked() {
                 app.send_event(UpdateAppEvent::SwitchBag(bag.id));
             }
+            let comparing = app.comparison_bag_ids.contains(&bag.id);
+            let comparable = comparing
+                || app
+                    .comparison_group()
+                    .is_none_or(|group| group == bag_group_name(&bag.name));
+            if ui
+                .add_enabled(comparable, SelectableLabel::new(comparing, "⚖"))
+                .on_hover_text("Compare side by side")
+                .on_disabled_hover_text("Only bags in the same group can be compared")
+                .clicked()
+            {
+                app.send_event(UpdateAppEvent::ToggleCompareBag(bag.id));
+            }
             if ui.button("💾").clicked() {
                 if let Some(file_path) = rfd::FileDialog::new().save_file() {
                     let mut buf_writer =
                         std::io::BufWriter::new(std::fs::File::create(file_path).unwrap());
-                    bincode::serialize_into(&mut buf_writer, bag).unwrap();
+                    bag.write_to(&mut buf_writer).unwrap();
                 }
             }
         },
@@ -233,7 +248,43 @@ fn data_list_view(app: &App, ui: &mut Ui) {
     let width = ui.available_width();
     default_scroll_area(ui, "data_list").show(ui, |ui| {
         ui.set_width(width);
-        ui.label("Data");
+        left_and_right_layout(
+            ui,
+            app,
+            |_app, ui| {
+                ui.label("Data");
+            },
+            |app, ui| {
+                let import_file_path_id = Id::new("import_file_path");
+
+                if ui
+                    .add_enabled(app.current_bag_id.is_some(), Button::new("📂"))
+                    .on_hover_text("Import files")
+                    .clicked()
+                {
+                    let fd = rfd::FileDialog::new().add_filter("Data", IMPORT_EXTENSIONS);
+                    let file_path =
+                        ui.memory_mut(|mem| mem.data.get_persisted::<PathBuf>(import_file_path_id));
+                    let fd = if let Some(path) = file_path.as_ref() {
+                        fd.set_directory(path)
+                    } else {
+                        fd
+                    };
+                    if let Some(file_paths) = fd.pick_files() {
+                        if let Some(parent) = file_paths.first().and_then(|p| p.parent()) {
+                            let parent = parent.to_owned();
+                            ui.ctx().memory_mut(|mem| {
+                                mem.data.insert_persisted(import_file_path_id, parent);
+                            });
+                        }
+                        import_files(app, ui.ctx(), file_paths);
+                    }
+                }
+                if ui.button("Δ").on_hover_text("DataFrame Diff").clicked() {
+                    open_diff_dialog(ui.ctx());
+                }
+            },
+        );
         if let Some(bind) = app.current_bag() {
             let bag = bind.read().unwrap();
             for (name, data_group) in &bag.data_groups() {
@@ -309,6 +360,7 @@ fn data_view_list_view(app: &App, ui: &mut Ui) {
             Tile::Pane(Pane {
                 content: PaneContent::DataView(v),
                 name,
+                ..
             }) => Some(Managed::new(*tile_id, name.clone(), v.clone())),
             _ => None,
         })
@@ -376,6 +428,7 @@ fn visualize_list_view(app: &App, ui: &mut Ui) {
             Tile::Pane(Pane {
                 content: PaneContent::Visualize(v),
                 name,
+                ..
             }) => Some(Managed::new(*tile_id, name.clone(), v.clone())),
             _ => None,
         })
@@ -450,7 +503,10 @@ fn layout_list_view(app: &App, ui: &mut Ui) {
                             ui.button("🚫").on_hover_text("Not applicable");
                         }
                         if ui.button("➖").clicked() {
-                            app.send_event(UpdateAppEvent::RemoveLayout(l.id));
+                            app.send_event(UpdateAppEvent::confirm(
+                                format!("Remove layout \"{}\"?", l.name),
+                                UpdateAppEvent::RemoveLayout(l.id),
+                            ));
                         }
                     } else {
                         ui.button("🚫").on_hover_text("No bag selected");
@@ -1,3 +1,8 @@
+mod comparison;
+mod diff;
+mod history;
+mod import;
+mod layer_panel;
 mod left_panel;
 
 use std::default::Default;
@@ -5,27 +10,30 @@ use std::default::Default;
 use eframe::{run_native, Frame};
 use egui::ahash::{HashMap, HashMapExt};
 
+use crate::history::History;
 use crate::left_panel::left_panel;
 use egui::{Context, Id, Response, Ui, ViewportCommand};
 use egui_extras::install_image_loaders;
-use egui_tiles::{Container, SimplificationOptions, Tile, TileId, Tiles, Tree, UiResponse};
-use flexim_config::ConfigWindow;
+use egui_tiles::{Container, SimplificationOptions, Tabs, Tile, TileId, Tiles, Tree, UiResponse};
+use flexim_config::keymap::{is_capturing, KeyAction};
+use flexim_config::{Config, ConfigWindow};
 use flexim_connect::grpc::flexim_connect_server::FleximConnectServer;
 use flexim_connect::server::FleximConnectServerImpl;
 use flexim_data_type::{
-    FlDataFrame, FlDataFrameColor, FlDataFrameRectangle, FlDataFrameSpecialColumn, FlDataReference,
-    FlDataType, FlImage, FlObject, FlTensor2D, GenerationSelector,
+    FlDataFrame, FlDataFrameSpecialColumn, FlDataReference, FlDataType, FlImage, FlObject,
+    FlTensor2D, GenerationSelector,
 };
+use flexim_data_visualize::annotation::take_annotation_edits;
+use flexim_data_visualize::data_view::DataView;
+use flexim_data_visualize::layer::LayerSettings;
 use flexim_data_visualize::visualize::{DataRender, FlImageRender, VisualizeState};
 use flexim_font::setup_custom_fonts;
 use flexim_layout::pane::{Pane, PaneContent};
 use flexim_layout::FlLayout;
-use flexim_storage::{Bag, BagId, Storage, StorageQuery};
+use flexim_storage::{bag_group_name, Bag, BagId, Storage, StorageQuery};
 use itertools::Itertools;
 use ndarray::Array2;
-use polars::prelude::{
-    Column, CsvReadOptions, IntoSeries, NamedFrom, SerReader, Series, StructChunked,
-};
+use polars::prelude::{CsvReadOptions, SerReader};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::fmt::{Debug, Formatter};
@@ -55,6 +63,7 @@ impl<D> Managed<D> {
 #[derive(Clone)]
 struct StackTab {
     contents: Vec<Arc<DataRender>>,
+    layers: Vec<LayerSettings>,
 }
 
 impl Debug for StackTab {
@@ -90,9 +99,10 @@ impl egui_tiles::Behavior<Pane> for TreeBehavior<'_> {
         match &pane.content {
             PaneContent::Visualize(content) => {
                 let mut state = VisualizeState::load(ui.ctx(), id);
+                state.measurements = pane.measurements.clone();
                 let bag = self.current_bag.read().unwrap();
                 if let Some(stack_tab) = self.stack_tabs.get(&tile_id) {
-                    state.show(ui, &bag, &stack_tab.contents);
+                    state.show_layers(ui, &bag, &stack_tab.contents, &stack_tab.layers);
                 } else {
                     state.show(ui, &bag, &[content.clone()]);
                 }
@@ -129,12 +139,25 @@ impl egui_tiles::Behavior<Pane> for TreeBehavior<'_> {
 pub enum UpdateAppEvent {
     ClearBags,
     SwitchBag(BagId),
-    InsertTile { title: String, content: PaneContent },
+    InsertTile {
+        title: String,
+        content: PaneContent,
+    },
     RemoveTile(TileId),
     UpdateTileVisibility(TileId, bool),
     SwitchLayout(FlLayout),
     RemoveLayout(Id),
     SaveLayout(FlLayout),
+    ToggleCompareBag(BagId),
+    ExitComparison,
+    /// 同じフレームで送られたイベントを反映した後に取り消す
+    Undo,
+    Redo,
+    /// 取り消せない操作の前に確認する
+    Confirm {
+        message: String,
+        event: Box<UpdateAppEvent>,
+    },
 }
 
 pub struct App {
@@ -143,8 +166,12 @@ pub struct App {
     pub current_bag_id: Option<BagId>,
     pub current_tile_id: Option<TileId>,
     pub layouts: Vec<FlLayout>,
+    /// 2つ以上あれば比較モードで表示する
+    pub comparison_bag_ids: Vec<BagId>,
+    pub history: History,
     events: Arc<Mutex<Vec<UpdateAppEvent>>>,
     panel_context: HashMap<BagId, Tree<Pane>>,
+    pending_confirmation: Option<(String, Box<UpdateAppEvent>)>,
 }
 
 impl App {
@@ -154,6 +181,15 @@ impl App {
     pub fn current_bag(&self) -> Option<Arc<RwLock<Bag>>> {
         self.storage.get_bag(self.current_bag_id?).ok()
     }
+    fn bag_group(&self, bag_id: BagId) -> Option<String> {
+        let bag = self.storage.get_bag(bag_id).ok()?;
+        let bag = bag.read().unwrap();
+        Some(bag_group_name(&bag.name).to_string())
+    }
+    /// 比較中の Bag のグループ名。比較モードには同じグループの Bag だけを並べる
+    pub fn comparison_group(&self) -> Option<String> {
+        self.bag_group(*self.comparison_bag_ids.first()?)
+    }
 }
 
 impl eframe::App for App {
@@ -169,7 +205,9 @@ impl eframe::App for App {
             });
             egui::CentralPanel::default().show(ctx, |ui| {
                 puffin::profile_scope!("center panel");
-                if let Some(current_bag) = self.current_bag() {
+                if self.comparison_bag_ids.len() >= 2 {
+                    comparison::comparison_view(self, ui);
+                } else if let Some(current_bag) = self.current_bag() {
                     let mut behavior = TreeBehavior {
                         current_bag,
                         stack_tabs: collect_stack_tabs(ui, &self.tree),
@@ -178,7 +216,14 @@ impl eframe::App for App {
                     self.tree.ui(&mut behavior, ui);
                 }
             });
-            ConfigWindow::show(ctx)
+            ConfigWindow::show(ctx);
+            import::import_dialog(self, ctx);
+            diff::diff_dialog(self, ctx);
+            import::handle_dropped_files(self, ctx);
+            commit_annotation_edits(self, ctx);
+            history::confirmation_dialog(self, ctx);
+            history::track_filter_changes(self, ctx);
+            handle_shortcuts(self, ctx);
         }
         end_of_frame(ctx, self);
     }
@@ -349,8 +394,11 @@ fn main() -> Result<(), eframe::Error> {
         tree,
         storage,
         layouts: vec![],
+        comparison_bag_ids: vec![],
         current_bag_id: Some(bag_id),
         panel_context: HashMap::new(),
+        history: History::default(),
+        pending_confirmation: None,
         current_tile_id: None,
         events: Arc::new(Mutex::new(vec![])),
     };
@@ -367,12 +415,14 @@ fn main() -> Result<(), eframe::Error> {
 }
 
 fn end_of_frame(ctx: &Context, app: &mut App) {
-    let mut events = app.events.lock().unwrap();
-    for event in events.drain(0..) {
+    let events = std::mem::take(&mut *app.events.lock().unwrap());
+    for event in events {
         match event {
             UpdateAppEvent::ClearBags => {
+                app.history.clear();
                 app.storage.clear_bags();
                 app.current_bag_id = None;
+                app.comparison_bag_ids.clear();
             }
             UpdateAppEvent::SwitchBag(new_bag_id) => {
                 if let Some(current_bag_id) = app.current_bag_id {
@@ -390,6 +440,7 @@ fn end_of_frame(ctx: &Context, app: &mut App) {
                 }
 
                 app.current_bag_id = Some(new_bag_id);
+                app.history.clear();
 
                 let bag = app.storage.get_bag(new_bag_id).unwrap();
                 let bag = bag.read().unwrap();
@@ -402,19 +453,23 @@ fn end_of_frame(ctx: &Context, app: &mut App) {
                 )));
             }
             UpdateAppEvent::InsertTile { content, title } => {
+                app.history.record_tree(app.tree.clone());
                 let tile_id = insert_root_tile(&mut app.tree, &title, content);
                 app.current_tile_id = Some(tile_id);
             }
             UpdateAppEvent::RemoveTile(tile_id) => {
+                app.history.record_tree(app.tree.clone());
                 app.tree.tiles.remove(tile_id);
                 if app.current_tile_id == Some(tile_id) {
                     app.current_tile_id = None;
                 }
             }
             UpdateAppEvent::UpdateTileVisibility(tile_id, visible) => {
+                app.history.record_tree(app.tree.clone());
                 app.tree.tiles.set_visible(tile_id, visible);
             }
             UpdateAppEvent::SwitchLayout(layout) => {
+                app.history.record_tree(app.tree.clone());
                 app.tree = layout.tree;
             }
             UpdateAppEvent::RemoveLayout(id) => {
@@ -423,23 +478,253 @@ fn end_of_frame(ctx: &Context, app: &mut App) {
             UpdateAppEvent::SaveLayout(layout) => {
                 app.layouts.push(layout);
             }
+            UpdateAppEvent::ToggleCompareBag(bag_id) => {
+                if let Some(i) = app.comparison_bag_ids.iter().position(|&id| id == bag_id) {
+                    app.comparison_bag_ids.remove(i);
+                } else {
+                    let same_group = app
+                        .bag_group(bag_id)
+                        .is_some_and(|group| app.comparison_group().is_none_or(|g| g == group));
+                    if same_group {
+                        app.comparison_bag_ids.push(bag_id);
+                    } else {
+                        log::warn!("only bags in the same group can be compared");
+                    }
+                }
+            }
+            UpdateAppEvent::ExitComparison => {
+                app.comparison_bag_ids.clear();
+            }
+            UpdateAppEvent::Undo => history::undo(app, ctx),
+            UpdateAppEvent::Redo => history::redo(app, ctx),
+            UpdateAppEvent::Confirm { message, event } => {
+                app.pending_confirmation = Some((message, event));
+            }
+        }
+    }
+}
+
+fn latest_generation(app: &App, bag_id: BagId, name: &str) -> Option<u64> {
+    let bag = app.storage.get_bag(bag_id).ok()?;
+    let bag = bag.read().unwrap();
+    bag.generation_counter.get(name).map(|g| g - 1)
+}
+
+/// 可視化で編集された DataFrame を新しい世代として追加し、編集元の世代を表示していたタイルを切り替える
+/// 取り消すと編集元の世代の表示に戻る
+fn commit_annotation_edits(app: &mut App, ctx: &Context) {
+    for edit in take_annotation_edits(ctx) {
+        let name = edit.reference.name.clone();
+        let previous = match edit.reference.generation {
+            GenerationSelector::Generation(generation) => Some(generation),
+            GenerationSelector::Latest => latest_generation(app, edit.bag_id, &name),
+        };
+        if let Err(e) =
+            save_annotation_edit(&app.storage, edit.bag_id, &edit.reference, edit.dataframe)
+        {
+            log::error!("failed to save edited annotations: {:#}", e);
+            continue;
+        }
+        let (Some(previous), Some(generation), Ok(bag)) = (
+            previous,
+            latest_generation(app, edit.bag_id, &name),
+            app.storage.get_bag(edit.bag_id),
+        ) else {
+            continue;
+        };
+        let bag = bag.read().unwrap();
+        let reference = |generation| {
+            FlDataReference::new(
+                name.clone(),
+                GenerationSelector::Generation(generation),
+                edit.reference.data_type.clone(),
+            )
+        };
+        if edit.reference.generation == GenerationSelector::Latest {
+            // 最新の世代を表示しているタイルはそのままで、取り消し用に編集元の世代を指す状態を記録する
+            let mut before = app.tree.clone();
+            retarget_tiles(
+                &mut before,
+                ctx,
+                &bag,
+                &edit.reference,
+                &reference(previous),
+            );
+            app.history.record_tree(before);
+        } else {
+            app.history.record_tree(app.tree.clone());
+            retarget_tiles(
+                &mut app.tree,
+                ctx,
+                &bag,
+                &edit.reference,
+                &reference(generation),
+            );
+        }
+    }
+}
+
+/// 編集した DataFrame を編集元の世代と同じ表示用の変換をつけて新しい世代として保存する
+fn save_annotation_edit(
+    storage: &Storage,
+    bag_id: BagId,
+    source: &FlDataReference,
+    dataframe: FlDataFrame,
+) -> anyhow::Result<()> {
+    let transform = storage
+        .get_bag(bag_id)?
+        .read()
+        .unwrap()
+        .transform_by_reference(source)?;
+    storage.insert_data_with_transform(bag_id, source.name.clone(), dataframe.into(), transform)
+}
+
+fn retarget_tiles(
+    tree: &mut Tree<Pane>,
+    ctx: &Context,
+    bag: &Bag,
+    from: &FlDataReference,
+    to: &FlDataReference,
+) {
+    for tile in tree.tiles.tiles_mut() {
+        if let Tile::Pane(pane) = tile {
+            retarget_pane(pane, ctx, bag, from, to);
+        }
+    }
+}
+
+fn retarget_pane(
+    pane: &mut Pane,
+    ctx: &Context,
+    bag: &Bag,
+    from: &FlDataReference,
+    to: &FlDataReference,
+) {
+    match &mut pane.content {
+        PaneContent::Visualize(render) => {
+            if let Some(retargeted) = render.retarget(from, to) {
+                *render = Arc::new(retargeted);
+            }
+        }
+        PaneContent::DataView(view) => {
+            if let Some(retargeted) = view.retarget(from, to) {
+                if let DataView::FlDataFrameView(v) = &retargeted {
+                    if let Err(e) = v.table.inherit_filter_settings(ctx, bag, from) {
+                        log::warn!("failed to inherit table state: {:#}", e);
+                    }
+                }
+                *view = Arc::new(retargeted);
+            }
         }
     }
 }
 
+/// 選択中のタイルが表示しているデータの世代を `step` だけ進める
+fn step_generation(app: &mut App, ctx: &Context, step: isize) {
+    let (Some(tile_id), Some(bag)) = (app.current_tile_id, app.current_bag()) else {
+        return;
+    };
+    let Some(Tile::Pane(pane)) = app.tree.tiles.get(tile_id) else {
+        return;
+    };
+    let from = match &pane.content {
+        PaneContent::Visualize(render) => render.reference(),
+        PaneContent::DataView(view) => view.reference(),
+    };
+    let bag = bag.read().unwrap();
+    let generations = bag
+        .data_groups()
+        .get(&from.name)
+        .map(|data| data.iter().map(|d| d.generation).collect_vec())
+        .unwrap_or_default();
+    let current = match from.generation {
+        GenerationSelector::Generation(generation) => Some(generation),
+        GenerationSelector::Latest => generations.last().copied(),
+    };
+    let Some(next) = generations
+        .iter()
+        .position(|g| Some(*g) == current)
+        .and_then(|i| i.checked_add_signed(step))
+        .and_then(|i| generations.get(i))
+    else {
+        return;
+    };
+    let to = FlDataReference::new(
+        from.name.clone(),
+        GenerationSelector::Generation(*next),
+        from.data_type.clone(),
+    );
+    app.history.record_tree(app.tree.clone());
+    if let Some(Tile::Pane(pane)) = app.tree.tiles.get_mut(tile_id) {
+        retarget_pane(pane, ctx, &bag, &from, &to);
+    }
+}
+
+/// 左のパネルに並んでいる順で次の Bag に切り替える
+fn switch_to_next_bag(app: &App) {
+    let Ok(bag_groups) = app.storage.bag_groups() else {
+        return;
+    };
+    let bag_ids = bag_groups
+        .values()
+        .flat_map(|versions| versions.values())
+        .flatten()
+        .map(|bag| bag.read().unwrap().id)
+        .collect_vec();
+    let next = match app
+        .current_bag_id
+        .and_then(|id| bag_ids.iter().position(|b| *b == id))
+    {
+        Some(i) => bag_ids.get((i + 1) % bag_ids.len()),
+        None => bag_ids.first(),
+    };
+    if let Some(next) = next {
+        app.send_event(UpdateAppEvent::SwitchBag(*next));
+    }
+}
+
+fn handle_shortcuts(app: &mut App, ctx: &Context) {
+    if ctx.wants_keyboard_input() || is_capturing(ctx) {
+        return;
+    }
+    let keymap = Config::get_global_ctx(ctx).keymap;
+    let (next_generation, previous_generation, next_bag) = ctx.input(|input| {
+        (
+            keymap.pressed(input, KeyAction::NextGeneration),
+            keymap.pressed(input, KeyAction::PreviousGeneration),
+            keymap.pressed(input, KeyAction::NextBag),
+        )
+    });
+    if next_generation {
+        step_generation(app, ctx, 1);
+    }
+    if previous_generation {
+        step_generation(app, ctx, -1);
+    }
+    if next_bag && app.comparison_bag_ids.len() < 2 {
+        switch_to_next_bag(app);
+    }
+    history::handle_shortcuts(app, ctx);
+}
+
 fn right_panel(app: &mut App, ui: &mut Ui) {
     puffin::profile_function!();
 
+    layer_panel::layer_panel(app, ui);
+
     if let Some(bag) = app.current_bag() {
         let bag = bag.read().unwrap();
         if let Some(tile_id) = app.current_tile_id {
             if let Some(tile) = app.tree.tiles.get(tile_id) {
                 match tile {
                     Tile::Pane(Pane {
+                        name,
                         content: PaneContent::Visualize(data),
+                        measurements,
                         ..
                     }) => {
                         data.config_panel(ui, &bag);
+                        measurements.panel(ui, name);
                     }
                     Tile::Pane(Pane {
                         content: PaneContent::DataView(data),
@@ -461,10 +746,7 @@ fn right_panel(app: &mut App, ui: &mut Ui) {
 fn create_tree() -> egui_tiles::Tree<Pane> {
     let mut next_view_nr = 0;
     let mut gen_pane = |name: String, image: Arc<DataRender>| {
-        let pane = Pane {
-            name,
-            content: PaneContent::Visualize(image),
-        };
+        let pane = Pane::new(name, PaneContent::Visualize(image));
         next_view_nr += 1;
         pane
     };
@@ -502,65 +784,68 @@ fn create_tree() -> egui_tiles::Tree<Pane> {
     egui_tiles::Tree::new("flexim", root, tiles)
 }
 
+/// `tabs` が2つ以上の可視化ペインだけを持つ場合、その子タイルを描画順に返す
+fn stack_children(tree: &Tree<Pane>, tabs: &Tabs) -> Option<Vec<TileId>> {
+    let children = tabs
+        .children
+        .iter()
+        .copied()
+        .filter(|&c| tree.is_visible(c))
+        .collect_vec();
+    let is_stack = children.len() >= 2
+        && children.iter().all(|&c| {
+            matches!(
+                tree.tiles.get(c),
+                Some(Tile::Pane(Pane {
+                    content: PaneContent::Visualize(_),
+                    ..
+                }))
+            )
+        });
+    is_stack.then_some(children)
+}
+
+/// `tile_id` を含むスタックタブのコンテナとレイヤーのタイル
+fn find_stack(tree: &Tree<Pane>, tile_id: TileId) -> Option<(TileId, Vec<TileId>)> {
+    tree.tiles.iter().find_map(|(&container_id, t)| match t {
+        Tile::Container(Container::Tabs(tabs)) if tabs.children.contains(&tile_id) => {
+            Some((container_id, stack_children(tree, tabs)?))
+        }
+        _ => None,
+    })
+}
+
 fn collect_stack_tabs(_ui: &mut Ui, tree: &Tree<Pane>) -> HashMap<TileId, StackTab> {
     let mut stack_tabs = HashMap::new();
     for t in tree.tiles.tiles() {
         if let Tile::Container(Container::Tabs(tabs)) = t {
-            // all tab is pane
-            let child_tiles = tabs
-                .children
+            let Some(children) = stack_children(tree, tabs) else {
+                continue;
+            };
+            let (contents, layers) = children
                 .iter()
-                .filter(|&&c| tree.is_visible(c))
-                .map(|&c| (c, tree.tiles.get(c)))
-                .collect_vec();
-            if child_tiles.len() >= 2
-                && child_tiles.iter().all(|(_, t)| {
-                    t.map(|t| {
-                        matches!(
-                            t,
-                            Tile::Pane(Pane {
-                                content: PaneContent::Visualize(_),
-                                ..
-                            })
-                        )
-                    })
-                    .unwrap_or(false)
+                .map(|&c| match tree.tiles.get(c) {
+                    Some(Tile::Pane(Pane {
+                        content: PaneContent::Visualize(content),
+                        layer,
+                        ..
+                    })) => (content.clone(), layer.clone()),
+                    _ => unreachable!(),
                 })
-            {
-                for (id, _) in child_tiles.iter() {
-                    for (_, t) in child_tiles.iter() {
-                        match t {
-                            Some(Tile::Pane(Pane {
-                                name: _,
-                                content: PaneContent::Visualize(content),
-                            })) => {
-                                stack_tabs
-                                    .entry(*id)
-                                    .and_modify(|m: &mut Vec<Arc<DataRender>>| {
-                                        m.push(content.clone())
-                                    })
-                                    .or_insert(vec![content.clone()]);
-                            }
-                            _ => unreachable!(),
-                        }
-                    }
-                }
+                .unzip();
+            let stack_tab = StackTab { contents, layers };
+            for &id in &children {
+                stack_tabs.insert(id, stack_tab.clone());
             }
         }
     }
-
-    HashMap::from_iter(
-        stack_tabs
-            .into_iter()
-            .map(|(k, v)| (k, StackTab { contents: v })),
-    )
+    stack_tabs
 }
 
 fn insert_root_tile(tree: &mut Tree<Pane>, name: &str, pane_content: PaneContent) -> TileId {
-    let tile_id = tree.tiles.insert_pane(Pane {
-        name: name.to_string(),
-        content: pane_content,
-    });
+    let tile_id = tree
+        .tiles
+        .insert_pane(Pane::new(name.to_string(), pane_content));
     if let Some(root) = tree.root() {
         let root = tree.tiles.get_mut(root).unwrap();
         match root {
@@ -586,28 +871,13 @@ fn load_sample_data() -> FlDataFrame {
     let data = Cursor::new(data);
     // let mut df = CsvReader::new(data).with_has_header(true).finish().unwrap();
 
-    let mut df = CsvReadOptions::default()
+    let df = CsvReadOptions::default()
         .with_has_header(true)
         .into_reader_with_file_handle(data)
         .finish()
         .unwrap();
 
-    let mut df = df
-        .apply("Face", |s| read_rectangle(s, "Face"))
-        .unwrap()
-        .clone();
-
-    let mut df = df
-        .apply("Segment", |s| read_segment(s, "Segment"))
-        .unwrap()
-        .clone();
-
-    let df = df
-        .apply("Color", |s| read_color(s, "Color"))
-        .unwrap()
-        .clone();
-
-    FlDataFrame::new(
+    FlDataFrame::try_with_special_columns(
         df,
         [
             ("Face".to_string(), FlDataFrameSpecialColumn::Rectangle),
@@ -617,29 +887,20 @@ fn load_sample_data() -> FlDataFrame {
         .into_iter()
         .collect(),
     )
+    .unwrap()
 }
 
 fn load_long_sample_data() -> FlDataFrame {
     let data = Vec::from(include_bytes!("../assets/long_sample.csv"));
     let data = Cursor::new(data);
     // let mut df = CsvReader::new(data).has_header(true).finish().unwrap();
-    let mut df = CsvReadOptions::default()
+    let df = CsvReadOptions::default()
         .with_has_header(true)
         .into_reader_with_file_handle(data)
         .finish()
         .unwrap();
 
-    let mut df = df
-        .apply("Face", |s| read_rectangle(s, "Face"))
-        .unwrap()
-        .clone();
-
-    let df = df
-        .apply("Segment", |s| read_segment(s, "Segment"))
-        .unwrap()
-        .clone();
-
-    FlDataFrame::new(
+    FlDataFrame::try_with_special_columns(
         df,
         [
             ("Face".to_string(), FlDataFrameSpecialColumn::Rectangle),
@@ -648,29 +909,20 @@ fn load_long_sample_data() -> FlDataFrame {
         .into_iter()
         .collect(),
     )
+    .unwrap()
 }
 
 fn load_long_sample_data2() -> FlDataFrame {
     let data = Vec::from(include_bytes!("../assets/long_sample2.csv"));
     let data = Cursor::new(data);
 
-    let mut df = CsvReadOptions::default()
+    let df = CsvReadOptions::default()
         .with_has_header(true)
         .into_reader_with_file_handle(data)
         .finish()
         .unwrap();
 
-    let mut df = df
-        .apply("Face1", |s| read_rectangle(s, "Face"))
-        .unwrap()
-        .clone();
-
-    let df = df
-        .apply("Segment1", |s| read_segment(s, "Segment"))
-        .unwrap()
-        .clone();
-
-    FlDataFrame::new(
+    FlDataFrame::try_with_special_columns(
         df,
         [
             ("Face1".to_string(), FlDataFrameSpecialColumn::Rectangle),
@@ -679,6 +931,7 @@ fn load_long_sample_data2() -> FlDataFrame {
         .into_iter()
         .collect(),
     )
+    .unwrap()
 }
 
 fn load_object_sample_data() -> FlObject {
@@ -689,88 +942,46 @@ fn load_object_sample_data() -> FlObject {
     FlObject::new(data)
 }
 
-fn read_rectangle(s: &Column, name: &str) -> Series {
-    let mut x1 = vec![];
-    let mut y1 = vec![];
-    let mut x2 = vec![];
-    let mut y2 = vec![];
-    for s in s.str().unwrap().into_iter() {
-        let s: Option<&str> = s;
-        if let Some(s) = s {
-            let t = serde_json::from_str::<FlDataFrameRectangle>(s).unwrap();
-            x1.push(Some(t.x1));
-            y1.push(Some(t.y1));
-            x2.push(Some(t.x2));
-            y2.push(Some(t.y2));
-        } else {
-            x1.push(None);
-            y1.push(None);
-            x2.push(None);
-            y2.push(None);
-        }
-    }
-    let x1 = Series::new("x1".into(), x1);
-    let y1 = Series::new("y1".into(), y1);
-    let x2 = Series::new("x2".into(), x2);
-    let y2 = Series::new("y2".into(), y2);
-
-    StructChunked::from_series(name.into(), x1.len(), [x1, y1, x2, y2].iter())
-        .unwrap()
-        .into_series()
-}
-
-fn read_segment(s: &Column, name: &str) -> Series {
-    let mut x1 = vec![];
-    let mut y1 = vec![];
-    let mut x2 = vec![];
-    let mut y2 = vec![];
-    for s in s.str().unwrap().into_iter() {
-        let s: Option<&str> = s;
-        if let Some(s) = s {
-            let t = serde_json::from_str::<FlDataFrameRectangle>(s).unwrap();
-            x1.push(Some(t.x1));
-            y1.push(Some(t.y1));
-            x2.push(Some(t.x2));
-            y2.push(Some(t.y2));
-        } else {
-            x1.push(None);
-            y1.push(None);
-            x2.push(None);
-            y2.push(None);
-        }
-    }
-    let x1 = Series::new("x1".into(), x1);
-    let y1 = Series::new("y1".into(), y1);
-    let x2 = Series::new("x2".into(), x2);
-    let y2 = Series::new("y2".into(), y2);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flexim_data_type::FlAffineTransform;
+
+    #[test]
+    fn annotation_edit_keeps_transform() {
+        let storage = Storage::default();
+        let bag_id = storage.create_bag("test".to_string());
+        let transform = FlAffineTransform {
+            offset_x: 10.0,
+            scale_x: 2.0,
+            ..Default::default()
+        };
+        storage
+            .insert_data_with_transform(
+                bag_id,
+                "faces".to_string(),
+                load_sample_data().into(),
+                Some(transform),
+            )
+            .unwrap();
 
-    StructChunked::from_series(name.into(), x1.len(), [x1, y1, x2, y2].iter())
-        .unwrap()
-        .into_series()
-}
+        let source = FlDataReference::new(
+            "faces".to_string(),
+            GenerationSelector::Latest,
+            FlDataType::DataFrame,
+        );
+        save_annotation_edit(&storage, bag_id, &source, load_sample_data()).unwrap();
 
-fn read_color(s: &Column, name: &str) -> Series {
-    let mut r = vec![];
-    let mut g = vec![];
-    let mut b = vec![];
-    for s in s.str().unwrap().into_iter() {
-        let s: Option<&str> = s;
-        if let Some(s) = s {
-            let t = serde_json::from_str::<FlDataFrameColor>(s).unwrap();
-            r.push(Some(t.r));
-            g.push(Some(t.g));
-            b.push(Some(t.b));
-        } else {
-            r.push(None);
-            g.push(None);
-            b.push(None);
-        }
+        let bag = storage.get_bag(bag_id).unwrap();
+        let bag = bag.read().unwrap();
+        let edited = FlDataReference::new(
+            "faces".to_string(),
+            GenerationSelector::Generation(1),
+            FlDataType::DataFrame,
+        );
+        assert_eq!(
+            bag.transform_by_reference(&edited).unwrap(),
+            Some(transform)
+        );
     }
-    let r = Series::new("r".into(), r);
-    let g = Series::new("g".into(), g);
-    let b = Series::new("b".into(), b);
-
-    StructChunked::from_series(name.into(), r.len(), [r, g, b].iter())
-        .unwrap()
-        .into_series()
 }